@@ -80,18 +80,11 @@ loginButton.addEventListener("click", (e) => {
                 }
             });
         } else if (response.status === 206) {
-            TwoFAForm.email.value = email;
-            response.json().then(data => {
-                TwoFAForm.login_attempt_id.value = data.loginAttemptId;
-            });
+            response.json().then(data => showTwoFA(email, data));
 
             loginForm.email.value = "";
             loginForm.password.value = "";
             resetLoginRecaptcha();
-
-            loginSection.style.display = "none";
-            twoFASection.style.display = "block";
-            signupSection.style.display = "none";
             loginErrAlter.style.display = "none";
         } else if (response.status === 200) {
             loginForm.email.value = "";
@@ -118,6 +111,58 @@ loginButton.addEventListener("click", (e) => {
     });
 });
 
+// Magic links log in without the password. The email is kept for the 2FA step, as the
+// link may be opened in a new tab.
+document.getElementById("magic-link-link").addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = loginForm.email.value;
+    const requestBody = { email };
+
+    const recaptchaContainer = document.getElementById('login-recaptcha-container');
+    if (recaptchaContainer.style.display !== 'none') {
+        requestBody.recaptchaToken = grecaptcha.getResponse(window.loginRecaptchaId);
+    }
+
+    fetch('/auth/magic-link/request', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify(requestBody),
+    }).then(response => response.json().then(data => {
+        if (response.status === 428) {
+            showLoginRecaptcha();
+            loginErrAlter.innerHTML = `<span><strong>Notice: </strong>Too many attempts. Please complete reCAPTCHA verification.</span>`;
+            loginErrAlter.style.display = "block";
+        } else if (response.ok) {
+            localStorage.setItem("magicLinkEmail", email);
+            resetLoginRecaptcha();
+            loginErrAlter.style.display = "none";
+            alert("If the account exists, a login link is on its way.");
+        } else if (data.error) {
+            loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+            loginErrAlter.style.display = "block";
+        }
+    }));
+});
+
+// Ask for the second factor of the login attempt in a 2FA response
+function showTwoFA(email, data) {
+    TwoFAForm.email.value = email;
+    TwoFAForm.login_attempt_id.value = data.loginAttemptId;
+    TwoFAForm.email_code.placeholder = data.method === "totp"
+        ? "Code from your authenticator app"
+        : "Code from your email";
+    if (data.method === "passkey") {
+        loginWithPasskey(data.passkeyOptions);
+    }
+
+    loginSection.style.display = "none";
+    twoFASection.style.display = "block";
+    signupSection.style.display = "none";
+}
+
 function showLoginRecaptcha() {
     const recaptchaContainer = document.getElementById('login-recaptcha-container');
     recaptchaContainer.style.display = 'flex';
@@ -193,7 +238,13 @@ signupButton.addEventListener("click", (e) => {
             signupForm.twoFA.checked = false;
             grecaptcha.reset(); // Reset reCAPTCHA
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
+            response.json().then(data => {
+                let message = "You have successfully created a user. Please check your email to verify your address.";
+                if (data.recoveryCodes) {
+                    message += "\n\nKeep these recovery codes somewhere safe. Each of them logs you in once if you lose access to your second factor, and they are not shown again:\n\n" + data.recoveryCodes.join("\n");
+                }
+                alert(message);
+            });
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -222,18 +273,20 @@ TwoFAButton.addEventListener("click", (e) => {
     const email = TwoFAForm.email.value;
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
+    const rememberDevice = TwoFAForm.remember_device.checked;
 
     fetch('/auth/verify-2fa', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
+        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode, rememberDevice }),
     }).then(response => {
         if (response.ok) {
             TwoFAForm.email.value = "";
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
+            TwoFAForm.remember_device.checked = false;
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in.");
             loginSection.style.display = "block";
@@ -251,4 +304,153 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+document.getElementById("2fa-resend-link").addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = TwoFAForm.email.value;
+    const loginAttemptId = TwoFAForm.login_attempt_id.value;
+
+    fetch('/auth/resend-2fa', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email, loginAttemptId }),
+    }).then(response => response.json().then(data => {
+        if (response.ok) {
+            TwoFAErrAlter.style.display = "none";
+            alert("A new code is on its way.");
+        } else if (data.error) {
+            TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+            TwoFAErrAlter.style.display = "block";
+        }
+    }));
+});
+
+// Answer a WebAuthn challenge with a passkey of the user, which logs them in
+function loginWithPasskey(options) {
+    navigator.credentials.get({ publicKey: PublicKeyCredential.parseRequestOptionsFromJSON(options) })
+        .then(credential => fetch('/auth/webauthn/authenticate/verify', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify(credential.toJSON()),
+        }))
+        .then(response => {
+            if (response.ok) {
+                TwoFAErrAlter.style.display = "none";
+                alert("You have successfully logged in.");
+                loginSection.style.display = "block";
+                twoFASection.style.display = "none";
+                signupSection.style.display = "none";
+            } else {
+                TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>Your passkey was not accepted.</span>`;
+                TwoFAErrAlter.style.display = "block";
+            }
+        })
+        .catch(() => {
+            TwoFAErrAlter.innerHTML = `<span><strong>Error: </strong>Passkey login was cancelled.</span>`;
+            TwoFAErrAlter.style.display = "block";
+        });
+}
+
+// Verification links from signup emails point here with the token in the query string
+const verifyEmailToken = new URLSearchParams(window.location.search).get("verify_email_token");
+if (verifyEmailToken) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    fetch('/auth/verify-email', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: verifyEmailToken }),
+    }).then(response => {
+        if (response.ok) {
+            alert("Your email address has been verified. You can now log in.");
+        } else {
+            alert("This verification link is invalid or has expired.");
+        }
+    });
+}
+
+// Password reset links from emails point here with the token in the query string
+const resetPasswordToken = new URLSearchParams(window.location.search).get("reset_password_token");
+if (resetPasswordToken) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    const newPassword = prompt("Enter your new password");
+    if (newPassword) {
+        fetch('/auth/password-reset/confirm', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ token: resetPasswordToken, newPassword }),
+        }).then(response => {
+            if (response.ok) {
+                alert("Your password has been reset. You can now log in.");
+            } else {
+                response.json().then(data => {
+                    alert(`Could not reset your password: ${data.error}`);
+                });
+            }
+        });
+    }
+}
+
+// Magic links from emails point here with the token in the query string
+const magicLinkToken = new URLSearchParams(window.location.search).get("magic_link_token");
+if (magicLinkToken) {
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    fetch('/auth/magic-link/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: magicLinkToken }),
+    }).then(response => {
+        if (response.status === 206) {
+            response.json().then(data => showTwoFA(localStorage.getItem("magicLinkEmail") || "", data));
+        } else if (response.ok) {
+            alert("You have successfully logged in.");
+        } else {
+            alert("This login link is invalid or has expired.");
+        }
+    });
+}
+
+// Email change links point here: the confirmation link is sent to the new address and the
+// cancellation link to the old one
+const emailChangeParams = new URLSearchParams(window.location.search);
+const emailChangeActions = [
+    ["confirm_email_change_token", "confirm", "Your email address has been changed. Please log in with your new address."],
+    ["cancel_email_change_token", "cancel", "The email change has been cancelled."],
+];
+for (const [param, action, successMessage] of emailChangeActions) {
+    const token = emailChangeParams.get(param);
+    if (!token) {
+        continue;
+    }
+    window.history.replaceState({}, document.title, window.location.pathname);
+
+    fetch(`/auth/change-email/${action}`, {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token }),
+    }).then(response => {
+        if (response.ok) {
+            alert(successMessage);
+        } else {
+            response.json().then(data => {
+                alert(`Could not ${action} the email change: ${data.error}`);
+            });
+        }
+    });
+}
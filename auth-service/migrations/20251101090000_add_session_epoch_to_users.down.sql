@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN IF EXISTS session_epoch;
+DROP SEQUENCE IF EXISTS session_epochs;
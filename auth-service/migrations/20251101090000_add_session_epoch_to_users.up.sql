@@ -0,0 +1,4 @@
+-- Per-user session epoch embedded in every JWT. Bumping it invalidates all of the user's tokens.
+-- Epochs come from a shared sequence so a deleted and re-created account never reuses an old epoch.
+CREATE SEQUENCE IF NOT EXISTS session_epochs;
+ALTER TABLE users ADD COLUMN session_epoch BIGINT NOT NULL DEFAULT nextval('session_epochs');
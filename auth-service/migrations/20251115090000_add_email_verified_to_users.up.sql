@@ -0,0 +1,4 @@
+-- Whether the user has followed the verification link sent to their email address.
+-- Accounts created before verification existed are considered verified.
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ALTER COLUMN email_verified SET DEFAULT FALSE;
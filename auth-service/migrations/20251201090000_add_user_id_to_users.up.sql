@@ -0,0 +1,6 @@
+-- Stable identifier of each user, carried as the JWT subject instead of the email address.
+-- Existing users are backfilled with random ids, and the email stays unique.
+ALTER TABLE users ADD COLUMN user_id UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD PRIMARY KEY (user_id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
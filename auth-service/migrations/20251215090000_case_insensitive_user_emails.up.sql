@@ -0,0 +1,25 @@
+-- Email addresses identify users whatever their case. Accounts whose addresses differ only
+-- in case have to be merged or renamed by hand first; scripts/email_collisions.sql lists them.
+DO $$
+DECLARE
+    collisions TEXT;
+BEGIN
+    SELECT string_agg(emails, '; ') INTO collisions
+    FROM (
+        SELECT string_agg(email, ', ' ORDER BY email) AS emails
+        FROM users
+        GROUP BY lower(email)
+        HAVING count(*) > 1
+    ) AS duplicates;
+
+    IF collisions IS NOT NULL THEN
+        RAISE EXCEPTION 'Email addresses differing only in case: %', collisions;
+    END IF;
+END $$;
+
+-- Bring stored addresses to the form the service now parses them into
+UPDATE users
+SET email = substring(btrim(email) FROM '^(.*)@') || '@' || lower(substring(btrim(email) FROM '@([^@]*)$'));
+
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_email_lower_key ON users (lower(email));
@@ -0,0 +1,9 @@
+-- TOTP authenticator secrets, encrypted with auth.totp_encryption_key.
+-- A secret is only used for 2FA once the user confirmed it with a first code.
+CREATE TABLE IF NOT EXISTS totp_secrets (
+    user_id UUID PRIMARY KEY REFERENCES users (user_id) ON DELETE CASCADE,
+    encrypted_secret BYTEA NOT NULL,
+    confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Time step of the last accepted code, so no code is accepted twice
+    last_used_step BIGINT
+);
@@ -0,0 +1,14 @@
+-- WebAuthn credentials (passkeys) of users. Credential ids are assigned by authenticators
+-- and unique across all users.
+CREATE TABLE IF NOT EXISTS passkey_credentials (
+    credential_id BYTEA PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    -- Uncompressed P-256 point of the ES256 credential key
+    public_key BYTEA NOT NULL,
+    -- Signature counter of the last accepted assertion, to detect cloned authenticators
+    sign_count BIGINT NOT NULL,
+    transports TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS passkey_credentials_user_id_idx ON passkey_credentials (user_id);
@@ -0,0 +1,7 @@
+-- One-time recovery codes standing in for the second factor, stored as SHA-256 hashes.
+-- Codes are deleted once used.
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
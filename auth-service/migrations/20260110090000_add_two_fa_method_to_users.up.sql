@@ -0,0 +1,4 @@
+-- 2FA method the user chose. NULL leaves the choice to the service, which uses the
+-- strongest method the user has set up.
+ALTER TABLE users ADD COLUMN two_fa_method TEXT
+    CHECK (two_fa_method IN ('email', 'totp', 'passkey'));
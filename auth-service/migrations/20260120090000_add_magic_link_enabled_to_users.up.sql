@@ -0,0 +1,3 @@
+-- Whether the user can log in with a link emailed to them instead of their password.
+-- Users opt out of it.
+ALTER TABLE users ADD COLUMN magic_link_enabled BOOLEAN NOT NULL DEFAULT TRUE;
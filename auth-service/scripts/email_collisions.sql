@@ -0,0 +1,15 @@
+-- Accounts whose email addresses differ only in case, which keep the
+-- case_insensitive_user_emails migration from applying. Each row lists the accounts
+-- sharing a canonical address, to be merged or renamed before migrating.
+--
+--   psql "$DATABASE_URL" -f scripts/email_collisions.sql
+SELECT
+    lower(email) AS canonical_email,
+    count(*) AS accounts,
+    array_agg(email ORDER BY email) AS emails,
+    array_agg(user_id ORDER BY email) AS user_ids,
+    array_agg(email_verified ORDER BY email) AS email_verified
+FROM users
+GROUP BY lower(email)
+HAVING count(*) > 1
+ORDER BY canonical_email;
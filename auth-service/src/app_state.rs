@@ -1,20 +1,34 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::services::{
-    postgres_user_store::PostgresUserStore, HashmapLoginAttemptStore, RecaptchaService,
-};
+use crate::services::{HashmapLoginAttemptStore, RecaptchaService};
 
 use crate::config::Settings;
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore};
+use crate::domain::{
+    BannedTokenStore, EmailChangeStore, EmailClient, EmailTokenStore, PasskeyStore,
+    RecoveryCodeStore, RefreshTokenStore, SessionStore, SigningKeyStore, TotpStore,
+    TrustedDeviceStore, TwoFACodeStore, UserStore, WebauthnChallengeStore,
+};
+use crate::utils::key_ring::KeyRing;
 
 // Using type aliases to improve readability!
-pub type UserStoreType = Arc<RwLock<PostgresUserStore>>;
+pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type LoginAttemptStoreType = Arc<RwLock<HashmapLoginAttemptStore>>;
 pub type RecaptchaServiceType = Arc<dyn RecaptchaService + Send + Sync>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
+pub type TrustedDeviceStoreType = Arc<RwLock<dyn TrustedDeviceStore + Send + Sync>>;
+pub type EmailTokenStoreType = Arc<RwLock<dyn EmailTokenStore + Send + Sync>>;
+pub type EmailChangeStoreType = Arc<RwLock<dyn EmailChangeStore + Send + Sync>>;
+pub type TotpStoreType = Arc<RwLock<dyn TotpStore + Send + Sync>>;
+pub type PasskeyStoreType = Arc<RwLock<dyn PasskeyStore + Send + Sync>>;
+pub type RecoveryCodeStoreType = Arc<RwLock<dyn RecoveryCodeStore + Send + Sync>>;
+pub type SigningKeyStoreType = Arc<RwLock<dyn SigningKeyStore + Send + Sync>>;
+pub type WebauthnChallengeStoreType = Arc<RwLock<dyn WebauthnChallengeStore + Send + Sync>>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type KeyRingType = Arc<RwLock<KeyRing>>;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -23,18 +37,39 @@ pub struct AppState {
     pub recaptcha_service: RecaptchaServiceType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub trusted_device_store: TrustedDeviceStoreType,
+    pub email_token_store: EmailTokenStoreType,
+    pub email_change_store: EmailChangeStoreType,
+    pub totp_store: TotpStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub webauthn_challenge_store: WebauthnChallengeStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
     pub email_client: EmailClientType,
+    pub key_ring: KeyRingType,
     pub settings: Settings,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         login_attempt_store: LoginAttemptStoreType,
         recaptcha_service: RecaptchaServiceType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        refresh_token_store: RefreshTokenStoreType,
+        session_store: SessionStoreType,
+        trusted_device_store: TrustedDeviceStoreType,
+        email_token_store: EmailTokenStoreType,
+        email_change_store: EmailChangeStoreType,
+        totp_store: TotpStoreType,
+        passkey_store: PasskeyStoreType,
+        webauthn_challenge_store: WebauthnChallengeStoreType,
+        recovery_code_store: RecoveryCodeStoreType,
         email_client: EmailClientType,
+        key_ring: KeyRingType,
         settings: Settings,
     ) -> Self {
         Self {
@@ -43,7 +78,17 @@ impl AppState {
             recaptcha_service,
             banned_token_store,
             two_fa_code_store,
+            refresh_token_store,
+            session_store,
+            trusted_device_store,
+            email_token_store,
+            email_change_store,
+            totp_store,
+            passkey_store,
+            webauthn_challenge_store,
+            recovery_code_store,
             email_client,
+            key_ring,
             settings,
         }
     }
@@ -1,4 +1,6 @@
 use config::{Config, ConfigError, Environment, File};
+use jsonwebtoken::Algorithm;
+use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use std::env;
 
@@ -9,6 +11,8 @@ pub struct Settings {
     pub database: DatabaseConfig,
     pub redis: RedisConfig,
     pub auth: AuthConfig,
+    pub admin: AdminConfig,
+    pub email: EmailConfig,
     pub cors: CorsConfig,
 }
 
@@ -33,10 +37,16 @@ pub struct DatabaseConfig {
 pub struct RedisConfig {
     pub hostname: String,
     pub password: String,
-    pub banned_token_ttl_seconds: u64,
     pub banned_token_key_prefix: String,
     pub two_fa_code_ttl_seconds: u64,
     pub two_fa_code_key_prefix: String,
+    pub refresh_token_key_prefix: String,
+    pub session_key_prefix: String,
+    pub email_token_key_prefix: String,
+    pub email_change_key_prefix: String,
+    pub webauthn_challenge_key_prefix: String,
+    pub trusted_device_key_prefix: String,
+    pub signing_key_key_prefix: String,
 }
 
 /// Authentication configuration
@@ -45,6 +55,134 @@ pub struct AuthConfig {
     pub jwt_secret: String,
     pub jwt_cookie_name: String,
     pub token_ttl_seconds: i64,
+    pub refresh_cookie_name: String,
+    pub refresh_token_ttl_seconds: u64,
+    /// Value of the `iss` claim of issued JWTs, required when validating them
+    pub issuer: String,
+    /// Value of the `aud` claim of issued JWTs, required when validating them
+    pub audience: String,
+    /// Clock skew tolerated when checking `exp` and `nbf`
+    pub leeway_seconds: u64,
+    /// Asymmetric keys used to sign and verify JWTs. When empty, tokens are
+    /// signed with HS256 using `jwt_secret`.
+    #[serde(default)]
+    pub signing_keys: Vec<SigningKeyConfig>,
+    /// Reject logins of users who have not verified their email address yet
+    pub require_email_verification: bool,
+    pub email_verification_ttl_seconds: u64,
+    pub password_reset_ttl_seconds: u64,
+    pub email_change_ttl_seconds: u64,
+    pub magic_link_ttl_seconds: u64,
+    /// Passphrase the encryption key of stored TOTP secrets is derived from
+    pub totp_encryption_key: Secret<String>,
+    /// Number of 30 second time steps a TOTP code may be ahead or behind the server clock
+    pub totp_drift_steps: u64,
+    /// Key of the keyed hashes 2FA codes are stored as
+    pub two_fa_code_key: Secret<String>,
+    /// Wrong 2FA codes accepted per login attempt, and per email until a login completes
+    pub max_2fa_attempts: u32,
+    /// Times the code of a login attempt may be sent again
+    pub max_2fa_resends: u32,
+    pub trusted_device_cookie_name: String,
+    /// Days a browser trusted on `verify_2fa` may skip 2FA on login
+    pub trusted_device_days: u64,
+    /// Key trusted device cookies are signed with
+    pub trusted_device_key: Secret<String>,
+    /// WebAuthn relying party id: the domain passkeys are registered for. Left empty, it
+    /// is the host of `webauthn_origin`.
+    pub webauthn_rp_id: String,
+    /// Origin the frontend is served from, which WebAuthn responses have to come from
+    pub webauthn_origin: String,
+    /// How long a WebAuthn ceremony may take, from issuing its challenge to the response
+    pub webauthn_challenge_ttl_seconds: u64,
+}
+
+/// JWT signing key configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningKeyConfig {
+    /// Key id published in the JWT `kid` header and in the JWKS
+    pub kid: String,
+    /// Either `RS256` or `EdDSA`
+    pub algorithm: Algorithm,
+    /// PEM encoded private key (PKCS#8)
+    pub private_key: String,
+    /// PEM encoded public key (SubjectPublicKeyInfo)
+    pub public_key: String,
+    /// Unix timestamp from which the key may sign tokens. Defaults to immediately.
+    pub activates_at: Option<i64>,
+    /// Unix timestamp after which tokens signed by the key are no longer accepted
+    pub retires_at: Option<i64>,
+}
+
+/// Admin API configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct AdminConfig {
+    /// Bearer token required by the admin routes. Admin routes are disabled when empty.
+    pub api_key: String,
+}
+
+/// Email delivery configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub backend: EmailBackend,
+    /// Mailbox emails are sent from, e.g. `Auth Service <no-reply@example.com>`. Left
+    /// empty, it is no-reply at the host of `link_base_url`.
+    pub sender: String,
+    /// Product name shown in emails
+    pub product_name: String,
+    /// Where users can get help, linked from every email
+    pub support_link: String,
+    /// Public URL of the auth service frontend, which links in emails point to
+    pub link_base_url: String,
+    pub smtp: SmtpConfig,
+    pub http: HttpEmailConfig,
+}
+
+/// How emails are delivered
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailBackend {
+    /// Only log emails, for development
+    Mock,
+    Smtp,
+    /// JSON API of an email provider, e.g. Postmark
+    Http,
+}
+
+/// SMTP server configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    /// Credentials are only sent when the username is set
+    pub username: String,
+    pub password: Secret<String>,
+    pub timeout_seconds: u64,
+}
+
+/// HTTP email provider configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpEmailConfig {
+    pub base_url: String,
+    pub api_token: Secret<String>,
+    pub timeout_seconds: u64,
+    /// Retries after a failed attempt on 5xx and 429 responses
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every following retry
+    pub retry_backoff_ms: u64,
+}
+
+/// Encryption of the connection to the SMTP server
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain text connection, only suitable for local relays and tests
+    None,
+    /// Upgrade a plain text connection with STARTTLS
+    Starttls,
+    /// Connect over TLS from the start
+    Tls,
 }
 
 /// CORS configuration
@@ -53,6 +191,35 @@ pub struct CorsConfig {
     pub allowed_origins: String,
 }
 
+/// Host of a URL such as "https://example.com/auth"
+fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+}
+
+/// Host of an origin such as "https://example.com", the narrowest relying party id
+/// passkeys of that origin can be registered for
+fn rp_id_from_origin(origin: &str) -> Result<String, ConfigError> {
+    url_host(origin)
+        .ok_or_else(|| ConfigError::Message(format!("invalid WebAuthn origin: {:?}", origin)))
+}
+
+/// No-reply mailbox at the host the links in emails point to
+fn sender_from_link_base_url(
+    product_name: &str,
+    link_base_url: &str,
+) -> Result<String, ConfigError> {
+    let host = url_host(link_base_url).ok_or_else(|| {
+        ConfigError::Message(format!("invalid email link base URL: {:?}", link_base_url))
+    })?;
+    Ok(format!("{} <no-reply@{}>", product_name, host))
+}
+
+fn is_local_host(host: &str) -> bool {
+    matches!(host, "localhost" | "127.0.0.1" | "[::1]")
+}
+
 impl DatabaseConfig {
     pub fn url(&self) -> String {
         format!(
@@ -96,7 +263,69 @@ impl Settings {
         }
 
         let config = builder.build()?;
-        config.try_deserialize()
+        let mut settings: Self = config.try_deserialize()?;
+
+        if settings.auth.webauthn_rp_id.is_empty() {
+            settings.auth.webauthn_rp_id = rp_id_from_origin(&settings.auth.webauthn_origin)?;
+        }
+        if settings.email.sender.is_empty() {
+            settings.email.sender = sender_from_link_base_url(
+                &settings.email.product_name,
+                &settings.email.link_base_url,
+            )?;
+        }
+
+        if run_mode == "production" {
+            settings.check_production_keys()?;
+        }
+        Ok(settings)
+    }
+
+    /// Refuse to run in production with the development keys of config/default.toml,
+    /// which are public, or with emails that link to and come from localhost
+    fn check_production_keys(&self) -> Result<(), ConfigError> {
+        let keys = [
+            (
+                "auth.totp_encryption_key",
+                self.auth.totp_encryption_key.expose_secret(),
+            ),
+            (
+                "auth.two_fa_code_key",
+                self.auth.two_fa_code_key.expose_secret(),
+            ),
+            (
+                "auth.trusted_device_key",
+                self.auth.trusted_device_key.expose_secret(),
+            ),
+        ];
+
+        for (name, key) in keys {
+            if key.is_empty() || key.starts_with("dev-") {
+                return Err(ConfigError::Message(format!(
+                    "{} must be set to a secret value in production",
+                    name
+                )));
+            }
+        }
+
+        if url_host(&self.email.link_base_url).is_none_or(|host| is_local_host(&host)) {
+            return Err(ConfigError::Message(
+                "email.link_base_url must be the public URL of the frontend in production"
+                    .to_owned(),
+            ));
+        }
+        let sender_host = self
+            .email
+            .sender
+            .trim_end_matches('>')
+            .rsplit_once('@')
+            .map(|(_, host)| host);
+        if sender_host.is_none_or(is_local_host) {
+            return Err(ConfigError::Message(
+                "email.sender must be a public address in production".to_owned(),
+            ));
+        }
+        Ok(())
     }
 
     /// Get the complete server address (host:port)
@@ -116,11 +345,49 @@ mod tests {
         assert_eq!(settings.server.port, 0);
         assert_eq!(settings.auth.jwt_cookie_name, "jwt");
         assert_eq!(settings.auth.token_ttl_seconds, 600);
+        assert_eq!(settings.auth.refresh_cookie_name, "refresh_token");
+        assert_eq!(settings.auth.refresh_token_ttl_seconds, 1209600);
+        assert_eq!(settings.auth.issuer, "auth-service");
+        assert_eq!(settings.auth.audience, "app-service");
+        assert_eq!(settings.auth.leeway_seconds, 60);
+        assert!(settings.auth.signing_keys.is_empty());
+        assert!(!settings.auth.require_email_verification);
+        assert_eq!(settings.auth.email_verification_ttl_seconds, 86400);
+        assert_eq!(settings.auth.password_reset_ttl_seconds, 3600);
+        assert_eq!(settings.auth.magic_link_ttl_seconds, 900);
+        assert_eq!(settings.auth.totp_drift_steps, 1);
+        assert!(!settings.auth.two_fa_code_key.expose_secret().is_empty());
+        assert_eq!(settings.auth.max_2fa_attempts, 5);
+        assert_eq!(settings.auth.max_2fa_resends, 3);
+        assert_eq!(settings.auth.trusted_device_cookie_name, "trusted_device");
+        assert_eq!(settings.auth.trusted_device_days, 30);
+        assert!(!settings.auth.trusted_device_key.expose_secret().is_empty());
+        assert_eq!(settings.auth.webauthn_rp_id, "localhost");
+        assert_eq!(settings.auth.webauthn_origin, "http://localhost");
+        assert!(settings.admin.api_key.is_empty());
+        assert_eq!(settings.email.backend, EmailBackend::Mock);
+        assert_eq!(settings.email.smtp.port, 587);
+        assert_eq!(settings.email.smtp.security, SmtpSecurity::Starttls);
+        assert!(settings.email.http.api_token.expose_secret().is_empty());
+        assert_eq!(settings.email.product_name, "Auth Service");
+        assert_eq!(settings.email.http.max_retries, 3);
         assert_eq!(settings.redis.hostname, "127.0.0.1");
-        assert_eq!(settings.redis.banned_token_ttl_seconds, 600);
         assert_eq!(settings.redis.banned_token_key_prefix, "banned_token:");
         assert_eq!(settings.redis.two_fa_code_ttl_seconds, 600);
         assert_eq!(settings.redis.two_fa_code_key_prefix, "two_fa_code:");
+        assert_eq!(
+            settings.redis.refresh_token_key_prefix,
+            "refresh_token_family:"
+        );
+        assert_eq!(settings.redis.session_key_prefix, "session:");
+        assert_eq!(settings.redis.email_token_key_prefix, "email_token:");
+        assert_eq!(settings.redis.email_change_key_prefix, "email_change:");
+        assert_eq!(
+            settings.redis.webauthn_challenge_key_prefix,
+            "webauthn_challenge:"
+        );
+        assert_eq!(settings.redis.trusted_device_key_prefix, "trusted_device:");
+        assert_eq!(settings.redis.signing_key_key_prefix, "signing_key:");
     }
 
     #[test]
@@ -128,4 +395,66 @@ mod tests {
         let settings = Settings::new().unwrap();
         assert_eq!(settings.server_address(), "127.0.0.1:0");
     }
+
+    #[test]
+    fn test_production_rejects_development_keys() {
+        let mut settings = Settings::new().unwrap();
+        settings.email.link_base_url = "https://example.com/auth".to_owned();
+        settings.email.sender = "Auth Service <no-reply@example.com>".to_owned();
+        assert!(settings.check_production_keys().is_err());
+
+        settings.auth.totp_encryption_key = Secret::new("e3b7c1a9f2d84c6b".to_owned());
+        assert!(settings.check_production_keys().is_err());
+
+        settings.auth.two_fa_code_key = Secret::new("9d41f0c27ab6e385".to_owned());
+        assert!(settings.check_production_keys().is_err());
+
+        settings.auth.trusted_device_key = Secret::new("5c8a2e7f13b94d60".to_owned());
+        assert!(settings.check_production_keys().is_ok());
+
+        settings.auth.totp_encryption_key = Secret::new(String::new());
+        assert!(settings.check_production_keys().is_err());
+    }
+
+    #[test]
+    fn test_production_rejects_localhost_emails() {
+        let mut settings = Settings::new().unwrap();
+        settings.auth.totp_encryption_key = Secret::new("e3b7c1a9f2d84c6b".to_owned());
+        settings.auth.two_fa_code_key = Secret::new("9d41f0c27ab6e385".to_owned());
+        settings.auth.trusted_device_key = Secret::new("5c8a2e7f13b94d60".to_owned());
+        assert_eq!(settings.email.sender, "Auth Service <no-reply@localhost>");
+        assert!(settings.check_production_keys().is_err());
+
+        settings.email.link_base_url = "https://example.com/auth".to_owned();
+        assert!(settings.check_production_keys().is_err());
+
+        settings.email.sender = "no-reply@example.com".to_owned();
+        assert!(settings.check_production_keys().is_ok());
+
+        settings.email.link_base_url = "http://127.0.0.1:3000/auth".to_owned();
+        assert!(settings.check_production_keys().is_err());
+    }
+
+    #[test]
+    fn test_sender_from_link_base_url() {
+        assert_eq!(
+            sender_from_link_base_url("Auth Service", "https://example.com/auth").unwrap(),
+            "Auth Service <no-reply@example.com>"
+        );
+        assert!(sender_from_link_base_url("Auth Service", "example.com/auth").is_err());
+    }
+
+    #[test]
+    fn test_rp_id_from_origin() {
+        assert_eq!(
+            rp_id_from_origin("https://auth.example.com").unwrap(),
+            "auth.example.com"
+        );
+        assert_eq!(
+            rp_id_from_origin("http://localhost:8000").unwrap(),
+            "localhost"
+        );
+        assert!(rp_id_from_origin("").is_err());
+        assert!(rp_id_from_origin("example.com").is_err());
+    }
 }
@@ -1,4 +1,7 @@
-use super::{Email, Password, User};
+use super::{
+    CredentialId, Email, KeyPromotion, PasskeyCredential, Password, RecoveryCode, Session,
+    TotpSecret, TrustedDevice, TwoFAMethod, User, UserId, WebauthnCeremony, WebauthnChallenge,
+};
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use rand::Rng;
 use thiserror::Error;
@@ -7,6 +10,7 @@ use thiserror::Error;
 pub trait UserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn get_user_by_id(&self, user_id: &UserId) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password)
         -> Result<(), UserStoreError>;
     async fn delete_user(
@@ -14,6 +18,37 @@ pub trait UserStore {
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError>;
+    // Every JWT embeds the session epoch of its user at issue time. Incrementing the
+    // epoch invalidates all tokens issued before, logging the user out everywhere.
+    async fn get_session_epoch(&self, user_id: &UserId) -> Result<i64, UserStoreError>;
+    async fn increment_session_epoch(&mut self, user_id: &UserId) -> Result<i64, UserStoreError>;
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError>;
+    // Move the user to a new email address, which is verified by the move
+    async fn update_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError>;
+    async fn set_requires_2fa(
+        &mut self,
+        user_id: &UserId,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError>;
+    async fn set_two_fa_method(
+        &mut self,
+        user_id: &UserId,
+        method: Option<TwoFAMethod>,
+    ) -> Result<(), UserStoreError>;
+    async fn set_magic_link_enabled(
+        &mut self,
+        user_id: &UserId,
+        enabled: bool,
+    ) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -40,10 +75,16 @@ impl PartialEq for UserStoreError {
     }
 }
 
+// Banned tokens are identified by their `jti` claim. A ban only needs to outlive the
+// token itself, so it is dropped once the token's expiry (a Unix timestamp) has passed.
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn store_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
-    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    async fn store_token(
+        &mut self,
+        token_id: String,
+        expires_at: u64,
+    ) -> Result<(), BannedTokenStoreError>;
+    async fn contains_token(&self, token_id: &str) -> Result<bool, BannedTokenStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -52,20 +93,480 @@ pub enum BannedTokenStoreError {
     UnexpectedError(#[source] Report),
 }
 
+// This trait represents the interface all concrete refresh token stores should implement.
+// Refresh tokens are grouped into families: every rotation replaces the current token of
+// a family, and presenting a token that has already been rotated is treated as reuse.
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    async fn create_family(
+        &mut self,
+        family: RefreshTokenFamily,
+    ) -> Result<RefreshToken, RefreshTokenStoreError>;
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(RefreshTokenFamily, RefreshToken), RefreshTokenStoreError>;
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError>;
+}
+
+// The user a refresh token family belongs to, and the session epoch it was started in
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefreshTokenFamily {
+    pub user_id: UserId,
+    pub session_epoch: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum RefreshTokenStoreError {
+    #[error("Refresh token family not found")]
+    FamilyNotFound,
+    // Carries the user of the family, unless it was created before user ids were introduced
+    #[error("Refresh token reused")]
+    TokenReused(Option<UserId>),
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RefreshTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::FamilyNotFound, Self::FamilyNotFound)
+                | (Self::TokenReused(_), Self::TokenReused(_))
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// The inventory of logged in sessions of each user. A session is dropped once it
+// has not been seen for as long as its refresh token family lives.
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(
+        &mut self,
+        user_id: &UserId,
+        session: Session,
+    ) -> Result<(), SessionStoreError>;
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError>;
+    async fn touch_session(
+        &mut self,
+        user_id: &UserId,
+        session_id: &str,
+        last_seen: i64,
+    ) -> Result<(), SessionStoreError>;
+    async fn remove_session(
+        &mut self,
+        user_id: &UserId,
+        session_id: &str,
+    ) -> Result<(), SessionStoreError>;
+    async fn remove_all_sessions(&mut self, user_id: &UserId) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum SessionStoreError {
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for SessionStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SessionNotFound, Self::SessionNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Browsers each user trusts to skip 2FA on login. A device is dropped once its trust
+// has expired.
+#[async_trait::async_trait]
+pub trait TrustedDeviceStore {
+    async fn add_device(
+        &mut self,
+        user_id: &UserId,
+        device: TrustedDevice,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    async fn get_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError>;
+    async fn is_trusted(
+        &self,
+        user_id: &UserId,
+        device_id: &str,
+    ) -> Result<bool, TrustedDeviceStoreError>;
+    async fn remove_device(
+        &mut self,
+        user_id: &UserId,
+        device_id: &str,
+    ) -> Result<(), TrustedDeviceStoreError>;
+    async fn remove_all_devices(&mut self, user_id: &UserId)
+        -> Result<(), TrustedDeviceStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum TrustedDeviceStoreError {
+    #[error("Trusted device not found")]
+    DeviceNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for TrustedDeviceStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::DeviceNotFound, Self::DeviceNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Single-use tokens sent to users in email links. A token only serves the purpose it
+// was issued for and is removed when consumed or once its TTL has passed.
+#[async_trait::async_trait]
+pub trait EmailTokenStore {
+    async fn add_token(
+        &mut self,
+        token: EmailToken,
+        purpose: EmailTokenPurpose,
+        email: Email,
+        ttl_seconds: u64,
+    ) -> Result<(), EmailTokenStoreError>;
+    async fn consume_token(
+        &mut self,
+        token: &EmailToken,
+        purpose: EmailTokenPurpose,
+    ) -> Result<Email, EmailTokenStoreError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+    MagicLink,
+}
+
+impl AsRef<str> for EmailTokenPurpose {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::VerifyEmail => "verify_email",
+            Self::ResetPassword => "reset_password",
+            Self::MagicLink => "magic_link",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EmailTokenStoreError {
+    #[error("Email token not found")]
+    TokenNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailTokenStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::TokenNotFound, Self::TokenNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Pending changes of the email address of users. A change is confirmed with the token
+// sent to the new address or cancelled with the token sent to the old one. A user has at
+// most one pending change: requesting another one invalidates the tokens of the previous.
+#[async_trait::async_trait]
+pub trait EmailChangeStore {
+    async fn add_change(
+        &mut self,
+        change: EmailChange,
+        ttl_seconds: u64,
+    ) -> Result<EmailChangeTokens, EmailChangeStoreError>;
+    async fn confirm_change(
+        &mut self,
+        confirm_token: &EmailToken,
+    ) -> Result<EmailChange, EmailChangeStoreError>;
+    async fn cancel_change(
+        &mut self,
+        cancel_token: &EmailToken,
+    ) -> Result<EmailChange, EmailChangeStoreError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailChange {
+    pub old_email: Email,
+    pub new_email: Email,
+}
+
+// The tokens of a pending email change, sent to the new and the old address respectively
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailChangeTokens {
+    pub confirm_token: EmailToken,
+    pub cancel_token: EmailToken,
+}
+
+#[derive(Debug, Error)]
+pub enum EmailChangeStoreError {
+    #[error("Email change not found")]
+    ChangeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for EmailChangeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ChangeNotFound, Self::ChangeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// TOTP authenticator secrets of users. A new secret stays pending until the user proves
+// their authenticator produces its codes, and only a confirmed secret is used for 2FA.
+#[async_trait::async_trait]
+pub trait TotpStore {
+    // Replace the pending secret of the user. Fails once a secret is confirmed.
+    async fn set_pending_secret(
+        &mut self,
+        user_id: &UserId,
+        secret: TotpSecret,
+    ) -> Result<(), TotpStoreError>;
+    async fn get_secret(&self, user_id: &UserId) -> Result<TotpEnrollment, TotpStoreError>;
+    async fn confirm_secret(&mut self, user_id: &UserId) -> Result<(), TotpStoreError>;
+    // Record the time step of an accepted code. Steps up to the last recorded one are
+    // rejected, so every code is accepted at most once.
+    async fn use_time_step(&mut self, user_id: &UserId, step: u64) -> Result<(), TotpStoreError>;
+}
+
+#[derive(Debug)]
+pub struct TotpEnrollment {
+    pub secret: TotpSecret,
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum TotpStoreError {
+    #[error("TOTP secret not found")]
+    SecretNotFound,
+    #[error("TOTP secret already confirmed")]
+    AlreadyConfirmed,
+    #[error("TOTP code already used")]
+    CodeReused,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for TotpStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SecretNotFound, Self::SecretNotFound)
+                | (Self::AlreadyConfirmed, Self::AlreadyConfirmed)
+                | (Self::CodeReused, Self::CodeReused)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// WebAuthn credentials (passkeys) of users. Credential ids are unique across all users,
+// so a credential alone identifies the user it belongs to.
+#[async_trait::async_trait]
+pub trait PasskeyStore {
+    async fn add_credential(
+        &mut self,
+        user_id: &UserId,
+        credential: PasskeyCredential,
+    ) -> Result<(), PasskeyStoreError>;
+    async fn get_credentials(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PasskeyCredential>, PasskeyStoreError>;
+    async fn get_credential(
+        &self,
+        credential_id: &CredentialId,
+    ) -> Result<(UserId, PasskeyCredential), PasskeyStoreError>;
+    // Record the signature counter of an accepted assertion. A counter that does not
+    // increase hints at a cloned authenticator and is rejected, unless the authenticator
+    // does not implement counters and always reports zero.
+    async fn update_sign_count(
+        &mut self,
+        credential_id: &CredentialId,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum PasskeyStoreError {
+    #[error("Passkey credential already exists")]
+    CredentialAlreadyExists,
+    #[error("Passkey credential not found")]
+    CredentialNotFound,
+    #[error("Passkey signature counter did not increase")]
+    SignCountRegressed,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for PasskeyStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CredentialAlreadyExists, Self::CredentialAlreadyExists)
+                | (Self::CredentialNotFound, Self::CredentialNotFound)
+                | (Self::SignCountRegressed, Self::SignCountRegressed)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Recovery codes of users, stored hashed. Every code is accepted at most once.
+#[async_trait::async_trait]
+pub trait RecoveryCodeStore {
+    // Replace all codes of the user, so codes of an earlier set stop working
+    async fn replace_codes(
+        &mut self,
+        user_id: &UserId,
+        codes: &[RecoveryCode],
+    ) -> Result<(), RecoveryCodeStoreError>;
+    async fn use_code(
+        &mut self,
+        user_id: &UserId,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    async fn count_codes(&self, user_id: &UserId) -> Result<usize, RecoveryCodeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RecoveryCodeStoreError {
+    #[error("Recovery code not found")]
+    CodeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for RecoveryCodeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::CodeNotFound, Self::CodeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Challenges of pending WebAuthn ceremonies. A challenge is consumed by the first
+// response to it, so every signed response is accepted at most once.
+#[async_trait::async_trait]
+pub trait WebauthnChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        challenge: WebauthnChallenge,
+        ceremony: WebauthnCeremony,
+        ttl_seconds: u64,
+    ) -> Result<(), WebauthnChallengeStoreError>;
+    async fn take_challenge(
+        &mut self,
+        challenge: &WebauthnChallenge,
+    ) -> Result<WebauthnCeremony, WebauthnChallengeStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum WebauthnChallengeStoreError {
+    #[error("WebAuthn challenge not found")]
+    ChallengeNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for WebauthnChallengeStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::ChallengeNotFound, Self::ChallengeNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+// Signing key promotions, in the order they were made. Every instance replays them over
+// the key ring it loaded from its configuration, so all instances sign with the same key
+// and a promotion survives restarts.
+#[async_trait::async_trait]
+pub trait SigningKeyStore {
+    async fn add_promotion(&mut self, promotion: KeyPromotion) -> Result<(), SigningKeyStoreError>;
+    // Promotions after the first `skip`, which the caller has already applied
+    async fn get_promotions(&self, skip: usize) -> Result<Vec<KeyPromotion>, SigningKeyStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum SigningKeyStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
 // This trait represents the interface all concrete 2FA code stores should implement
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
+    // Codes are only ever stored hashed, see `utils::two_fa_code`
     async fn add_code(
         &mut self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code_hash: TwoFACodeHash,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Give a pending login attempt a new code, keeping its expiry and failed attempts
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code_hash: TwoFACodeHash,
+    ) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
-    async fn get_code(
+    // Remove the codes of every pending login attempt of the email
+    async fn remove_codes(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn get_code_hash(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFACodeHash, TwoFACodeStoreError>;
+    // Count a wrong guess against the code of a login attempt, returning the number of
+    // failed attempts of the login attempt so far
+    async fn record_failed_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<u32, TwoFACodeStoreError>;
+    // Count a wrong guess against the email, across all of its login attempts, returning
+    // the number of wrong guesses since the last completed 2FA login of the email. The
+    // count expires like a code would.
+    async fn record_failed_email_attempt(
+        &mut self,
+        email: &Email,
+    ) -> Result<u32, TwoFACodeStoreError>;
+    // Forget the wrong guesses of the email once it completes a 2FA login
+    async fn reset_failed_email_attempts(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Count a resend of the code of a login attempt, returning the number of resends of
+    // the login attempt so far
+    async fn record_resend(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<u32, TwoFACodeStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -109,14 +610,35 @@ impl AsRef<str> for LoginAttemptId {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailToken(String);
+
+impl EmailToken {
+    pub fn parse(token: String) -> Result<Self> {
+        let parsed_token = uuid::Uuid::parse_str(&token).wrap_err("Invalid email token")?;
+        Ok(Self(parsed_token.to_string()))
+    }
+}
+
+impl Default for EmailToken {
+    fn default() -> Self {
+        EmailToken(uuid::Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for EmailToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct TwoFACode(String);
 
 impl TwoFACode {
+    // Emailed codes never start with a zero, but authenticator app codes may
     pub fn parse(code: String) -> Result<Self> {
-        let code_as_u32 = code.parse::<u32>().wrap_err("Invalid 2FA code")?;
-
-        match (100_000..=999_999).contains(&code_as_u32) {
+        match code.len() == 6 && code.bytes().all(|byte| byte.is_ascii_digit()) {
             true => Ok(Self(code)),
             false => Err(eyre!("Invalid 2FA code")),
         }
@@ -138,3 +660,78 @@ impl AsRef<str> for TwoFACode {
         &self.0
     }
 }
+
+// Hex encoded keyed hash of a 2FA code, which is all the 2FA code store keeps of it
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoFACodeHash(String);
+
+impl TwoFACodeHash {
+    pub fn parse(hash: String) -> Result<Self> {
+        match hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            true => Ok(Self(hash.to_ascii_lowercase())),
+            false => Err(eyre!("Invalid 2FA code hash")),
+        }
+    }
+}
+
+impl AsRef<str> for TwoFACodeHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// A refresh token is made of the id of the family it belongs to and a random
+// per-rotation token id, serialized as `<family_id>.<token_id>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefreshToken {
+    family_id: String,
+    token_id: String,
+}
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self> {
+        let (family_id, token_id) = token
+            .split_once('.')
+            .ok_or_else(|| eyre!("Invalid refresh token"))?;
+
+        let family_id = uuid::Uuid::parse_str(family_id).wrap_err("Invalid refresh token")?;
+        let token_id = uuid::Uuid::parse_str(token_id).wrap_err("Invalid refresh token")?;
+
+        Ok(Self {
+            family_id: family_id.to_string(),
+            token_id: token_id.to_string(),
+        })
+    }
+
+    // Create the next token of the same family
+    pub fn rotate(&self) -> Self {
+        Self {
+            family_id: self.family_id.clone(),
+            token_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+
+    pub fn family_id(&self) -> &str {
+        &self.family_id
+    }
+
+    pub fn token_id(&self) -> &str {
+        &self.token_id
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        // A default refresh token starts a brand new family
+        Self {
+            family_id: uuid::Uuid::new_v4().to_string(),
+            token_id: uuid::Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for RefreshToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.family_id, self.token_id)
+    }
+}
@@ -4,18 +4,20 @@ use color_eyre::eyre::{eyre, Result};
 use secrecy::{ExposeSecret, Secret};
 use validator::validate_email;
 
+// Addresses are kept trimmed and with their domain lowercased, but compared by their
+// canonical form, so an account is found whatever the case the address is typed in
 #[derive(Debug, Clone)]
 pub struct Email(Secret<String>);
 
 impl PartialEq for Email {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        self.canonical() == other.canonical()
     }
 }
 
 impl Hash for Email {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.0.expose_secret().hash(state);
+        self.canonical().hash(state);
     }
 }
 
@@ -23,14 +25,24 @@ impl Eq for Email {}
 
 impl Email {
     pub fn parse(s: Secret<String>) -> Result<Self> {
-        match validate_email(s.expose_secret()) {
-            true => Ok(Self(s)),
-            false => Err(eyre!(format!(
+        let address = s.expose_secret().trim();
+        match address.rsplit_once('@') {
+            // Domains are case-insensitive, unlike the local part, which is delivered as given
+            Some((local_part, domain)) if validate_email(address) => Ok(Self(Secret::new(
+                format!("{}@{}", local_part, domain.to_lowercase()),
+            ))),
+            _ => Err(eyre!(format!(
                 "{} is not a valid email.",
                 s.expose_secret()
             ))),
         }
     }
+
+    /// The address with its local part lowercased too, which identifies the account.
+    /// Addresses differing only in case belong to the same account.
+    pub fn canonical(&self) -> String {
+        self.0.expose_secret().to_lowercase()
+    }
 }
 
 impl AsRef<Secret<String>> for Email {
@@ -152,6 +164,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_email_is_trimmed_and_domain_lowercased() {
+        let email = Email::parse(Secret::new("  Alice.Smith@Example.COM \n".to_string())).unwrap();
+        assert_eq!(email.as_ref().expose_secret(), "Alice.Smith@example.com");
+        assert_eq!(email.canonical(), "alice.smith@example.com");
+    }
+
+    #[test]
+    fn test_emails_differing_in_case_are_equal() {
+        let first = Email::parse(Secret::new("Alice@Example.com".to_string())).unwrap();
+        let second = Email::parse(Secret::new("alice@example.com".to_string())).unwrap();
+        assert_eq!(first, second);
+
+        let mut emails = std::collections::HashSet::new();
+        emails.insert(first);
+        assert!(emails.contains(&second));
+
+        let other = Email::parse(Secret::new("bob@example.com".to_string())).unwrap();
+        assert_ne!(second, other);
+    }
+
     #[test]
     fn test_as_ref_trait() {
         let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
@@ -178,7 +211,7 @@ mod tests {
     fn prop_valid_email_roundtrip(email: ValidEmail) -> bool {
         let email_str = email.0;
         match Email::parse(Secret::new(email_str.clone())) {
-            Ok(parsed) => parsed.as_ref().expose_secret().to_string() == email_str,
+            Ok(parsed) => *parsed.as_ref().expose_secret() == email_str,
             Err(_) => false,
         }
     }
@@ -1,8 +1,16 @@
 use super::Email;
 use color_eyre::eyre::Result;
 
+// An email with alternative HTML and plain-text bodies
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
 // This trait represents the interface all concrete email clients should implement
 #[async_trait::async_trait]
 pub trait EmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()>;
+    async fn send_multipart_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()>;
 }
@@ -15,6 +15,24 @@ pub enum AuthAPIError {
     InvalidToken,
     #[error("Missing token")]
     MissingToken,
+    #[error("Session not found")]
+    SessionNotFound,
+    #[error("Trusted device not found")]
+    TrustedDeviceNotFound,
+    #[error("Email not verified")]
+    EmailNotVerified,
+    #[error("TOTP already enabled")]
+    TotpAlreadyEnabled,
+    #[error("Passkey already registered")]
+    PasskeyAlreadyRegistered,
+    #[error("2FA already enabled")]
+    TwoFAAlreadyEnabled,
+    #[error("2FA not enabled")]
+    TwoFANotEnabled,
+    #[error("2FA method not set up")]
+    TwoFAMethodNotSetUp,
+    #[error("Too many requests")]
+    TooManyRequests,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+// A configured signing key made the active one through the admin API. The key that was
+// signing before retires `overlap_seconds` after `promoted_at`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyPromotion {
+    pub kid: String,
+    pub promoted_at: i64,
+    pub overlap_seconds: i64,
+}
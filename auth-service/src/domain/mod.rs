@@ -2,16 +2,28 @@ pub mod data_stores;
 pub mod email;
 pub mod email_client;
 pub mod error;
+pub mod key_promotion;
 pub mod login_attempts;
 pub mod password;
 pub mod recaptcha;
+pub mod recovery_code;
+pub mod session;
+pub mod totp;
+pub mod trusted_device;
 pub mod user;
+pub mod webauthn;
 
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
 pub use error::*;
+pub use key_promotion::*;
 pub use login_attempts::*;
 pub use password::*;
 pub use recaptcha::*;
+pub use recovery_code::*;
+pub use session::*;
+pub use totp::*;
+pub use trusted_device::*;
 pub use user::*;
+pub use webauthn::*;
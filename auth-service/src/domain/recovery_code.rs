@@ -0,0 +1,98 @@
+use color_eyre::eyre::{eyre, Result};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+
+// Characters of recovery codes, leaving out 0, 1, I, L and O, which are easily confused
+// when copied from paper
+const ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+// Two groups of five characters, close to 50 bits of randomness
+const GROUP_LENGTH: usize = 5;
+
+// One-time code that stands in for the second factor of a user who lost access to it.
+// Codes are written as two groups separated by a hyphen, e.g. `ABCDE-23456`.
+#[derive(Debug, Clone)]
+pub struct RecoveryCode(Secret<String>);
+
+impl RecoveryCode {
+    // Codes are accepted in any case and with or without the hyphen, as users type them
+    // in from wherever they kept them
+    pub fn parse(code: Secret<String>) -> Result<Self> {
+        let characters: Vec<u8> = code
+            .expose_secret()
+            .trim()
+            .bytes()
+            .filter(|byte| *byte != b'-')
+            .map(|byte| byte.to_ascii_uppercase())
+            .collect();
+
+        match characters.len() == 2 * GROUP_LENGTH
+            && characters.iter().all(|byte| ALPHABET.contains(byte))
+        {
+            true => Ok(Self::from_characters(&characters)),
+            false => Err(eyre!("Invalid recovery code")),
+        }
+    }
+
+    fn from_characters(characters: &[u8]) -> Self {
+        let (first, second) = characters.split_at(GROUP_LENGTH);
+        Self(Secret::new(format!(
+            "{}-{}",
+            String::from_utf8_lossy(first),
+            String::from_utf8_lossy(second)
+        )))
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        let characters: Vec<u8> = (0..2 * GROUP_LENGTH)
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())])
+            .collect();
+        Self::from_characters(&characters)
+    }
+}
+
+impl AsRef<Secret<String>> for RecoveryCode {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(code: &str) -> Result<RecoveryCode> {
+        RecoveryCode::parse(Secret::new(code.to_owned()))
+    }
+
+    #[test]
+    fn test_parse_normalizes_codes() {
+        for code in ["ABCDE-23456", "abcde-23456", "ABCDE23456", " abCDe-23456 "] {
+            assert_eq!(parse(code).unwrap().as_ref().expose_secret(), "ABCDE-23456");
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_codes() {
+        for code in ["", "123456", "ABCDE-2345", "ABCDE-234567", "ABCDE-2345O"] {
+            assert!(parse(code).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_default_codes_are_random_and_valid() {
+        let code = RecoveryCode::default();
+        let parsed = parse(code.as_ref().expose_secret()).unwrap();
+        assert_eq!(
+            parsed.as_ref().expose_secret(),
+            code.as_ref().expose_secret()
+        );
+        assert_ne!(
+            RecoveryCode::default().as_ref().expose_secret(),
+            code.as_ref().expose_secret()
+        );
+    }
+}
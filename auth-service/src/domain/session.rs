@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+// A logged in session of a user. Its id is the id of the refresh token family started
+// at login, and every JWT issued for the session carries it in the `sid` claim.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: String,
+    pub created_at: i64,
+    pub last_seen: i64,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub two_fa_used: bool,
+}
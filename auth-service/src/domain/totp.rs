@@ -0,0 +1,85 @@
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+
+// 160 bits, the key length RFC 4226 recommends for HMAC-SHA1
+const SECRET_LENGTH: usize = 20;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+// Shared secret between the service and the authenticator app of a user
+pub struct TotpSecret(Secret<Vec<u8>>);
+
+impl std::fmt::Debug for TotpSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TotpSecret([REDACTED])")
+    }
+}
+
+impl TotpSecret {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self(Secret::new(bytes))
+    }
+
+    /// Unpadded base32 encoding, which is how authenticator apps expect the secret to be
+    /// typed in or embedded in an `otpauth://` URI
+    pub fn to_base32(&self) -> Secret<String> {
+        let mut encoded = String::new();
+        for chunk in self.0.expose_secret().chunks(5) {
+            let mut buffer = [0u8; 5];
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            let bits = buffer
+                .iter()
+                .fold(0u64, |bits, byte| bits << 8 | *byte as u64);
+            let chars = (chunk.len() * 8).div_ceil(5);
+            for i in 0..chars {
+                let index = (bits >> (35 - i * 5)) & 0x1f;
+                encoded.push(BASE32_ALPHABET[index as usize] as char);
+            }
+        }
+        Secret::new(encoded)
+    }
+}
+
+impl Default for TotpSecret {
+    fn default() -> Self {
+        let mut bytes = vec![0u8; SECRET_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self::from_bytes(bytes)
+    }
+}
+
+impl AsRef<[u8]> for TotpSecret {
+    fn as_ref(&self) -> &[u8] {
+        self.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_base32() {
+        // Test vectors from RFC 4648
+        for (bytes, encoded) in [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ] {
+            let secret = TotpSecret::from_bytes(bytes.as_bytes().to_vec());
+            assert_eq!(secret.to_base32().expose_secret(), encoded);
+        }
+    }
+
+    #[test]
+    fn test_default_secrets_are_random() {
+        let first = TotpSecret::default();
+        let second = TotpSecret::default();
+        assert_eq!(first.as_ref().len(), SECRET_LENGTH);
+        assert_ne!(first.as_ref(), second.as_ref());
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+// A browser the user chose to trust after proving the second factor on it. Logins from
+// it skip 2FA until `expires_at`, unless the user revokes it or changes their password.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDevice {
+    pub id: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
@@ -1,18 +1,129 @@
+use std::fmt;
+
+use color_eyre::eyre::{eyre, Context, Result};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
 use super::{Email, Password};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    // Method the user chose for 2FA. Without a choice, or when the chosen method is no
+    // longer set up, the strongest method the user has set up is used.
+    pub two_fa_method: Option<TwoFAMethod>,
+    pub email_verified: bool,
+    // Whether the user can log in with a link emailed to them instead of their password
+    pub magic_link_enabled: bool,
 }
 
 impl User {
+    // New users have not verified their email address yet, and can log in with a link
+    // until they opt out
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
         Self {
+            id: UserId::default(),
             email,
             password,
             requires_2fa,
+            two_fa_method: None,
+            email_verified: false,
+            magic_link_enabled: true,
+        }
+    }
+}
+
+// How a user proves the second factor when logging in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAMethod {
+    Email,
+    Totp,
+    Passkey,
+}
+
+impl TwoFAMethod {
+    pub fn parse(method: &str) -> Result<Self> {
+        match method {
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            "passkey" => Ok(Self::Passkey),
+            _ => Err(eyre!("Invalid 2FA method")),
         }
     }
 }
+
+impl AsRef<str> for TwoFAMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Email => "email",
+            Self::Totp => "totp",
+            Self::Passkey => "passkey",
+        }
+    }
+}
+
+// Stable identifier of a user. Unlike the email address it never changes and is not
+// personal data, so tokens and stores refer to users by it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    pub fn parse(id: &str) -> Result<Self> {
+        let parsed_id = Uuid::parse_str(id).wrap_err("Invalid user id")?;
+        Ok(Self(parsed_id))
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        Self(id)
+    }
+}
+
+impl AsRef<Uuid> for UserId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_id_roundtrip() {
+        let id = UserId::default();
+        assert_eq!(UserId::parse(&id.to_string()).unwrap(), id);
+
+        assert!(UserId::parse("user@example.com").is_err());
+    }
+
+    #[test]
+    fn test_two_fa_method_roundtrip() {
+        for method in [TwoFAMethod::Email, TwoFAMethod::Totp, TwoFAMethod::Passkey] {
+            assert_eq!(TwoFAMethod::parse(method.as_ref()).unwrap(), method);
+            assert_eq!(
+                serde_json::to_string(&method).unwrap(),
+                format!("\"{}\"", method.as_ref())
+            );
+        }
+
+        assert!(TwoFAMethod::parse("sms").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use rand::RngCore;
+
+use super::{LoginAttemptId, UserId};
+
+// WebAuthn asks for challenges of at least 16 random bytes
+const CHALLENGE_LENGTH: usize = 32;
+
+// Upper bound WebAuthn sets on the length of credential ids
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+
+// Random challenge an authenticator signs to prove it holds a credential. It travels
+// base64url encoded, which is also how the client echoes it back in its response.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebauthnChallenge(String);
+
+impl WebauthnChallenge {
+    pub fn parse(challenge: &str) -> Result<Self> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(challenge)
+            .wrap_err("Invalid WebAuthn challenge")?;
+        match bytes.len() == CHALLENGE_LENGTH {
+            true => Ok(Self(challenge.to_owned())),
+            false => Err(eyre!("Invalid WebAuthn challenge")),
+        }
+    }
+}
+
+impl Default for WebauthnChallenge {
+    fn default() -> Self {
+        let mut bytes = [0u8; CHALLENGE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for WebauthnChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// What a challenge was issued for, which decides what a response to it may do
+#[derive(Clone, Debug, PartialEq)]
+pub enum WebauthnCeremony {
+    // Adding a passkey to the account of a logged in user
+    Registration {
+        user_id: UserId,
+    },
+    // Proving the second factor of a login that passed the password check
+    SecondFactor {
+        user_id: UserId,
+        login_attempt_id: LoginAttemptId,
+    },
+    // Logging in with a passkey alone, by whichever user it belongs to
+    Passwordless,
+}
+
+// Id the authenticator assigned to a credential, unique across all users
+#[derive(Clone, Debug, PartialEq)]
+pub struct CredentialId(Vec<u8>);
+
+impl CredentialId {
+    pub fn parse(bytes: Vec<u8>) -> Result<Self> {
+        match (1..=MAX_CREDENTIAL_ID_LENGTH).contains(&bytes.len()) {
+            true => Ok(Self(bytes)),
+            false => Err(eyre!("Invalid credential id")),
+        }
+    }
+
+    pub fn to_base64(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.0)
+    }
+}
+
+impl AsRef<[u8]> for CredentialId {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// A WebAuthn public key credential of a user. Only ES256 credentials are accepted, so
+// the public key is an uncompressed P-256 point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasskeyCredential {
+    pub id: CredentialId,
+    pub public_key: Vec<u8>,
+    // Signature counter of the last accepted assertion
+    pub sign_count: u32,
+    // How the client can reach the authenticator, e.g. `usb` or `internal`
+    pub transports: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_roundtrip() {
+        let challenge = WebauthnChallenge::default();
+        assert_eq!(
+            WebauthnChallenge::parse(challenge.as_ref()).unwrap(),
+            challenge
+        );
+        assert_ne!(WebauthnChallenge::default(), challenge);
+
+        assert!(WebauthnChallenge::parse("not base64url!").is_err());
+        assert!(WebauthnChallenge::parse(&URL_SAFE_NO_PAD.encode([0u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_credential_id_length() {
+        assert!(CredentialId::parse(vec![]).is_err());
+        assert!(CredentialId::parse(vec![0; MAX_CREDENTIAL_ID_LENGTH + 1]).is_err());
+
+        let id = CredentialId::parse(vec![0xfb, 0xff]).unwrap();
+        assert_eq!(id.to_base64(), "-_8");
+    }
+}
@@ -3,12 +3,22 @@ use redis::RedisResult;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::error::Error;
+use std::net::SocketAddr;
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 
 pub use crate::app_state::AppState;
 pub use crate::config::Settings;
 use crate::domain::AuthAPIError;
-use crate::routes::{delete_account, login, logout, signup, verify_2fa, verify_token};
+use crate::routes::{
+    cancel_email_change, change_password, confirm_email_change, confirm_magic_link,
+    confirm_password_reset, confirm_totp, delete_account, disable_2fa, disable_magic_link,
+    enable_2fa, enable_magic_link, enroll_totp, jwks, list_sessions, list_trusted_devices, login,
+    logout, logout_all, promote_signing_key, refresh_token, regenerate_recovery_codes,
+    request_email_change, request_magic_link, request_password_reset, resend_2fa,
+    resend_verification_email, revoke_session, revoke_trusted_device, revoke_trusted_devices,
+    set_2fa_method, signup, verify_2fa, verify_email, verify_token, webauthn_authenticate_options,
+    webauthn_authenticate_verify, webauthn_register_options, webauthn_register_verify,
+};
 use utils::tracing::{make_span_with_request_id, on_request, on_response};
 
 pub mod app_state;
@@ -19,9 +29,11 @@ pub mod services;
 pub mod utils;
 
 use axum::{
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
     http::StatusCode,
+    middleware::AddExtension,
     response::{IntoResponse, Response},
-    routing::{delete, post},
+    routing::{delete, get, post},
     serve::Serve,
     Json, Router,
 };
@@ -29,7 +41,10 @@ use serde::{Deserialize, Serialize};
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
@@ -62,9 +77,55 @@ impl Application {
             .route("/signup", post(signup))
             .route("/login", post(login))
             .route("/verify-2fa", post(verify_2fa))
+            .route("/resend-2fa", post(resend_2fa))
+            .route("/magic-link/request", post(request_magic_link))
+            .route("/magic-link/confirm", post(confirm_magic_link))
+            .route("/magic-link/enable", post(enable_magic_link))
+            .route("/magic-link/disable", post(disable_magic_link))
+            .route("/2fa/enable", post(enable_2fa))
+            .route("/2fa/disable", post(disable_2fa))
+            .route("/2fa/method", post(set_2fa_method))
+            .route("/totp/enroll", post(enroll_totp))
+            .route("/totp/confirm", post(confirm_totp))
+            .route(
+                "/webauthn/register/options",
+                post(webauthn_register_options),
+            )
+            .route("/webauthn/register/verify", post(webauthn_register_verify))
+            .route(
+                "/webauthn/authenticate/options",
+                post(webauthn_authenticate_options),
+            )
+            .route(
+                "/webauthn/authenticate/verify",
+                post(webauthn_authenticate_verify),
+            )
+            .route(
+                "/recovery-codes/regenerate",
+                post(regenerate_recovery_codes),
+            )
+            .route("/verify-email", post(verify_email))
+            .route("/verify-email/resend", post(resend_verification_email))
             .route("/logout", post(logout))
+            .route("/logout-all", post(logout_all))
+            .route("/change-password", post(change_password))
+            .route("/change-email/request", post(request_email_change))
+            .route("/change-email/confirm", post(confirm_email_change))
+            .route("/change-email/cancel", post(cancel_email_change))
+            .route("/password-reset/request", post(request_password_reset))
+            .route("/password-reset/confirm", post(confirm_password_reset))
+            .route("/refresh", post(refresh_token))
+            .route("/sessions", get(list_sessions))
+            .route("/sessions/:id", delete(revoke_session))
+            .route(
+                "/trusted-devices",
+                get(list_trusted_devices).delete(revoke_trusted_devices),
+            )
+            .route("/trusted-devices/:id", delete(revoke_trusted_device))
             .route("/verify-token", post(verify_token))
             .route("/delete-account", delete(delete_account))
+            .route("/.well-known/jwks.json", get(jwks))
+            .route("/admin/signing-keys/promote", post(promote_signing_key))
             .layer(cors)
             .layer(
                 TraceLayer::new_for_http()
@@ -76,7 +137,11 @@ impl Application {
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Peer addresses are recorded with the sessions clients start
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         Ok(Self { server, address })
     }
@@ -105,6 +170,19 @@ impl IntoResponse for AuthAPIError {
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing token"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::TrustedDeviceNotFound => {
+                (StatusCode::NOT_FOUND, "Trusted device not found")
+            }
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
+            AuthAPIError::TotpAlreadyEnabled => (StatusCode::CONFLICT, "TOTP already enabled"),
+            AuthAPIError::PasskeyAlreadyRegistered => {
+                (StatusCode::CONFLICT, "Passkey already registered")
+            }
+            AuthAPIError::TwoFAAlreadyEnabled => (StatusCode::CONFLICT, "2FA already enabled"),
+            AuthAPIError::TwoFANotEnabled => (StatusCode::CONFLICT, "2FA not enabled"),
+            AuthAPIError::TwoFAMethodNotSetUp => (StatusCode::BAD_REQUEST, "2FA method not set up"),
+            AuthAPIError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, "Too many requests"),
             AuthAPIError::UnexpectedError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
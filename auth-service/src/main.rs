@@ -1,12 +1,24 @@
 use std::sync::Arc;
 
 use auth_service::services::{
-    postgres_user_store::PostgresUserStore, HashmapLoginAttemptStore, MockEmailClient,
-    MockRecaptchaService, RedisBannedTokenStore, RedisTwoFACodeStore,
+    postgres_user_store::PostgresUserStore, HashmapLoginAttemptStore, HttpEmailClient,
+    MockEmailClient, MockRecaptchaService, PostgresPasskeyStore, PostgresRecoveryCodeStore,
+    PostgresTotpStore, RedisBannedTokenStore, RedisEmailChangeStore, RedisEmailTokenStore,
+    RedisRefreshTokenStore, RedisSessionStore, RedisSigningKeyStore, RedisTrustedDeviceStore,
+    RedisTwoFACodeStore, RedisWebauthnChallengeStore, SmtpEmailClient,
+};
+use auth_service::utils::{
+    key_ring::{sync_key_ring, KeyRing},
+    secret_cipher::SecretCipher,
+    tracing::init_tracing,
+};
+use auth_service::{
+    app_state::{AppState, EmailClientType},
+    config::{EmailBackend, Settings},
+    Application,
 };
-use auth_service::utils::tracing::init_tracing;
-use auth_service::{app_state::AppState, config::Settings, Application};
 use auth_service::{get_postgres_pool, get_redis_connection};
+use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use tokio::sync::RwLock;
 
@@ -20,11 +32,16 @@ async fn main() {
     let pg_pool = configure_postgresql(&settings.database.url()).await;
     let redis_conn = configure_redis(&settings.redis.hostname, &settings.redis.password).await;
 
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+    let totp_store = Arc::new(RwLock::new(PostgresTotpStore::new(
+        pg_pool.clone(),
+        SecretCipher::new(settings.auth.totp_encryption_key.expose_secret()),
+    )));
+    let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+    let recovery_code_store = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool)));
     let login_attempt_store = Arc::new(RwLock::new(HashmapLoginAttemptStore::new()));
     let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new_with_config(
         Arc::new(RwLock::new(redis_conn)),
-        settings.redis.banned_token_ttl_seconds,
         settings.redis.banned_token_key_prefix.clone(),
     )));
     let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new_with_config(
@@ -34,7 +51,72 @@ async fn main() {
         settings.redis.two_fa_code_ttl_seconds,
         settings.redis.two_fa_code_key_prefix.clone(),
     )));
-    let email_client = Arc::new(MockEmailClient);
+    let refresh_token_store = Arc::new(RwLock::new(RedisRefreshTokenStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.auth.refresh_token_ttl_seconds,
+        settings.redis.refresh_token_key_prefix.clone(),
+    )));
+    let session_store = Arc::new(RwLock::new(RedisSessionStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.auth.refresh_token_ttl_seconds,
+        settings.redis.session_key_prefix.clone(),
+    )));
+    let trusted_device_store = Arc::new(RwLock::new(RedisTrustedDeviceStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.auth.trusted_device_days * 86400,
+        settings.redis.trusted_device_key_prefix.clone(),
+    )));
+    let email_token_store = Arc::new(RwLock::new(RedisEmailTokenStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.redis.email_token_key_prefix.clone(),
+    )));
+    let email_change_store = Arc::new(RwLock::new(RedisEmailChangeStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.redis.email_change_key_prefix.clone(),
+    )));
+    let webauthn_challenge_store =
+        Arc::new(RwLock::new(RedisWebauthnChallengeStore::new_with_config(
+            Arc::new(RwLock::new(
+                configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+            )),
+            settings.redis.webauthn_challenge_key_prefix.clone(),
+        )));
+    let email_client: EmailClientType = match settings.email.backend {
+        EmailBackend::Mock => Arc::new(MockEmailClient),
+        EmailBackend::Smtp => Arc::new(
+            SmtpEmailClient::new(&settings.email.smtp, &settings.email.sender)
+                .expect("Failed to configure SMTP email client"),
+        ),
+        EmailBackend::Http => Arc::new(
+            HttpEmailClient::new(&settings.email.http, &settings.email.sender)
+                .expect("Failed to configure HTTP email client"),
+        ),
+    };
+    let signing_key_store = Arc::new(RwLock::new(RedisSigningKeyStore::new_with_config(
+        Arc::new(RwLock::new(
+            configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+        )),
+        settings.redis.signing_key_key_prefix.clone(),
+    )));
+    let key_ring = Arc::new(RwLock::new(
+        KeyRing::from_config(&settings.auth)
+            .expect("Failed to load signing keys")
+            .with_promotion_store(signing_key_store),
+    ));
+    // Start out signing with the key promoted last, even if that was before a restart
+    sync_key_ring(&key_ring)
+        .await
+        .expect("Failed to load signing key promotions");
 
     // For development, use a mock reCAPTCHA service that always succeeds
     // In production, use GoogleRecaptchaService with real secret key
@@ -46,7 +128,17 @@ async fn main() {
         recaptcha_service,
         banned_token_store,
         two_fa_code_store,
+        refresh_token_store,
+        session_store,
+        trusted_device_store,
+        email_token_store,
+        email_change_store,
+        totp_store,
+        passkey_store,
+        webauthn_challenge_store,
+        recovery_code_store,
         email_client,
+        key_ring,
         settings.clone(),
     );
 
@@ -0,0 +1,237 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{authenticate, check_recaptcha, LoginResponse};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailChange, EmailChangeStoreError, EmailToken, LoginAttempt,
+        LoginAttemptStore, Password, UserStoreError,
+    },
+    utils::email_templates::{email_link, EmailTemplate},
+};
+
+// Start moving the account of the caller to a new email address. Nothing changes until
+// the link sent to the new address is followed.
+#[tracing::instrument(name = "Request Email Change", skip_all)]
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<EmailChangeRequest>,
+) -> Result<Response, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+
+    let new_email = Email::parse(request.new_email).map_err(|_| AuthAPIError::InvalidInput)?;
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let email = state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?
+        .email;
+    if new_email == email {
+        return Err(AuthAPIError::InvalidInput);
+    }
+
+    // Guessing the password with a stolen session is gated like guessing it on login,
+    // and the failures count towards the same limit
+    if !check_recaptcha(&state, &email, request.recaptcha_token).await? {
+        return Ok((
+            StatusCode::PRECONDITION_REQUIRED,
+            Json(LoginResponse::RecaptchaRequired),
+        )
+            .into_response());
+    }
+
+    let valid = match state
+        .user_store
+        .read()
+        .await
+        .validate_user(&email, &password)
+        .await
+    {
+        Ok(()) => true,
+        Err(UserStoreError::InvalidCredentials) => false,
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(LoginAttempt::new(email.clone(), valid))
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if !valid {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    match state.user_store.read().await.get_user(&new_email).await {
+        Ok(_) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    send_email_change_emails(
+        &state,
+        EmailChange {
+            old_email: email,
+            new_email,
+        },
+    )
+    .await
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(EmailChangeResponse {
+        message: "A confirmation link has been sent to the new email address".to_string(),
+    });
+
+    Ok((StatusCode::OK, response).into_response())
+}
+
+#[tracing::instrument(name = "Confirm Email Change", skip_all)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(request): Json<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailToken::parse(request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let change = state
+        .email_change_store
+        .write()
+        .await
+        .confirm_change(&token)
+        .await
+        .map_err(|e| match e {
+            EmailChangeStoreError::ChangeNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // The new address may have been taken, or the account deleted, since the link was sent
+    {
+        let mut user_store = state.user_store.write().await;
+        let user = user_store
+            .get_user(&change.old_email)
+            .await
+            .map_err(|e| match e {
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })?;
+        user_store
+            .update_email(&user.id, &change.new_email)
+            .await
+            .map_err(|e| match e {
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })?;
+    }
+
+    // Sessions are tied to the user id and carry over, but pending 2FA codes were issued
+    // for the old address
+    state
+        .two_fa_code_store
+        .write()
+        .await
+        .remove_codes(&change.old_email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(EmailChangeResponse {
+        message: "Email changed successfully!".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Cancel Email Change", skip_all)]
+pub async fn cancel_email_change(
+    State(state): State<AppState>,
+    Json(request): Json<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailToken::parse(request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    state
+        .email_change_store
+        .write()
+        .await
+        .cancel_change(&token)
+        .await
+        .map_err(|e| match e {
+            EmailChangeStoreError::ChangeNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let response = Json(EmailChangeResponse {
+        message: "Email change cancelled".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Record the pending change, then email the confirmation link to the new address and
+// a notice with the cancellation link to the old one
+#[tracing::instrument(name = "Send Email Change Emails", skip_all)]
+async fn send_email_change_emails(state: &AppState, change: EmailChange) -> Result<()> {
+    let tokens = state
+        .email_change_store
+        .write()
+        .await
+        .add_change(change.clone(), state.settings.auth.email_change_ttl_seconds)
+        .await?;
+
+    let config = &state.settings.email;
+    let confirm_link = email_link(config, "confirm_email_change_token", &tokens.confirm_token);
+    let cancel_link = email_link(config, "cancel_email_change_token", &tokens.cancel_token);
+
+    let confirmation = EmailTemplate::ConfirmEmailChange {
+        link: &confirm_link,
+    }
+    .render(config)?;
+    state
+        .email_client
+        .send_multipart_email(&change.new_email, &confirmation)
+        .await?;
+
+    let notice = EmailTemplate::EmailChangeRequested {
+        link: &cancel_link,
+        new_email: change.new_email.as_ref().expose_secret(),
+    }
+    .render(config)?;
+    state
+        .email_client
+        .send_multipart_email(&change.old_email, &notice)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: Secret<String>,
+    pub password: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeTokenRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EmailChangeResponse {
+    pub message: String,
+}
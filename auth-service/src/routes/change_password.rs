@@ -0,0 +1,181 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use super::{authenticate, check_recaptcha, end_all_sessions, start_session, LoginResponse};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, LoginAttempt, LoginAttemptStore, Password, UserStoreError},
+    utils::{client_info::ClientInfo, email_templates::EmailTemplate},
+};
+
+#[tracing::instrument(name = "Change Password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<Response, AuthAPIError>) {
+    let (claims, user_id) = match authenticate(&jar, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (current_password, new_password) = match (
+        Password::parse(request.current_password),
+        Password::parse(request.new_password),
+    ) {
+        (Ok(current), Ok(new)) => (current, new),
+        _ => return (jar, Err(AuthAPIError::InvalidInput)),
+    };
+
+    let email = match state.user_store.read().await.get_user_by_id(&user_id).await {
+        Ok(user) => user.email,
+        Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // Guessing the current password with a stolen session is gated like guessing it on
+    // login, and the failures count towards the same limit
+    match check_recaptcha(&state, &email, request.recaptcha_token).await {
+        Ok(true) => (),
+        Ok(false) => {
+            let response = (
+                StatusCode::PRECONDITION_REQUIRED,
+                Json(LoginResponse::RecaptchaRequired),
+            );
+            return (jar, Ok(response.into_response()));
+        }
+        Err(e) => return (jar, Err(e)),
+    }
+
+    let valid = match state
+        .user_store
+        .read()
+        .await
+        .validate_user(&email, &current_password)
+        .await
+    {
+        Ok(()) => true,
+        Err(UserStoreError::InvalidCredentials) => false,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let attempt = LoginAttempt::new(email.clone(), valid);
+    if let Err(e) = state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(attempt)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    if !valid {
+        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    }
+
+    if let Err(e) = state
+        .user_store
+        .write()
+        .await
+        .update_password(&user_id, new_password)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    // The new session of the caller keeps whether it was started with 2FA
+    let two_fa_used = match state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&user_id)
+        .await
+    {
+        Ok(sessions) => sessions
+            .into_iter()
+            .any(|session| Some(&session.id) == claims.sid.as_ref() && session.two_fa_used),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // Every session is ended, including the caller's, which is then replaced by a new one
+    // so the caller stays logged in
+    if let Err(e) = end_all_sessions(&state, &user_id).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+    // Browsers trusted to skip 2FA may have been trusted by whoever knew the old password
+    if let Err(e) = state
+        .trusted_device_store
+        .write()
+        .await
+        .remove_all_devices(&user_id)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    if let Some(session_id) = &claims.sid {
+        if let Err(e) = state
+            .refresh_token_store
+            .write()
+            .await
+            .revoke_family(session_id)
+            .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
+    let (auth_cookie, refresh_cookie) =
+        match start_session(&state, &user_id, two_fa_used, client_info).await {
+            Ok(cookies) => cookies,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        };
+    let jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    // The password has changed by now, so a failure to notify the user is only logged
+    let notification = EmailTemplate::PasswordChanged.render(&state.settings.email);
+    let sent = match notification {
+        Ok(message) => {
+            state
+                .email_client
+                .send_multipart_email(&email, &message)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        tracing::error!(
+            "Failed to send password change notification to user {}: {:?}",
+            user_id,
+            e
+        );
+    }
+
+    let response = Json(ChangePasswordResponse {
+        message: "Password changed successfully!".to_string(),
+    });
+
+    (jar, Ok((StatusCode::OK, response).into_response()))
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
@@ -3,7 +3,7 @@ use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    domain::{AuthAPIError, Email, Password, UserStore},
+    domain::{AuthAPIError, Email, Password},
     AppState,
 };
 
@@ -18,17 +18,37 @@ pub async fn delete_account(
     let password =
         Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let mut user_store = state.user_store.write().await;
-
     // Delete the user (this validates credentials internally)
-    user_store
-        .delete_user(&email, &password)
-        .await
-        .map_err(|e| match e {
+    let user_id = {
+        let mut user_store = state.user_store.write().await;
+        let map_error = |e| match e {
             crate::domain::UserStoreError::UserNotFound => AuthAPIError::InvalidCredentials,
             crate::domain::UserStoreError::InvalidCredentials => AuthAPIError::InvalidCredentials,
             _ => AuthAPIError::UnexpectedError(e.into()),
-        })?;
+        };
+        let user = user_store.get_user(&email).await.map_err(map_error)?;
+        user_store
+            .delete_user(&email, &password)
+            .await
+            .map_err(map_error)?;
+        user.id
+    };
+
+    // Tokens of the account are rejected from now on, so its sessions are gone
+    state
+        .session_store
+        .write()
+        .await
+        .remove_all_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    state
+        .trusted_device_store
+        .write()
+        .await
+        .remove_all_devices(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
 
     let response = Json(DeleteAccountResponse {
         message: "Account deleted successfully!".to_string(),
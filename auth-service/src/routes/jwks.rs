@@ -0,0 +1,15 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+
+use crate::{app_state::AppState, domain::AuthAPIError, utils::key_ring::sync_key_ring};
+
+// Publish the public keys used to sign JWTs so other services can verify tokens locally
+#[tracing::instrument(name = "JWKS", skip_all)]
+pub async fn jwks(State(state): State<AppState>) -> Result<impl IntoResponse, AuthAPIError> {
+    sync_key_ring(&state.key_ring)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let keys = state.key_ring.read().await.jwks(Utc::now().timestamp());
+    Ok(Json(JwkSet { keys }))
+}
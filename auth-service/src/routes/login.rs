@@ -1,20 +1,27 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use color_eyre::eyre::Result;
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     app_state::AppState,
     domain::{
-        AuthAPIError, Email, LoginAttempt, LoginAttemptStore, Password, RecaptchaToken, UserStore,
+        AuthAPIError, Email, LoginAttempt, LoginAttemptId, LoginAttemptStore, Password,
+        RecaptchaToken, TwoFACode, TwoFAMethod, User, UserId, WebauthnCeremony,
     },
-    utils::auth::generate_auth_cookie,
+    utils::{client_info::ClientInfo, email_templates::EmailTemplate, two_fa_code::hash_code},
+};
+
+use super::{
+    is_trusted_device, passkey_credentials, passkey_request_options, start_session, two_fa_method,
+    PasskeyRequestOptions,
 };
 
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
+    client_info: ClientInfo,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
@@ -29,38 +36,19 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::InvalidInput)),
     };
 
-    // Check if reCAPTCHA is required for this email
-    let requires_recaptcha = {
-        let store = state.login_attempt_store.read().await;
-        match store.get_attempt_summary(&email).await {
-            Ok(summary) => summary.requires_recaptcha,
-            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
-        }
-    };
-
     // Handle reCAPTCHA verification if required
-    if requires_recaptcha {
-        match request.recaptcha_token {
-            Some(token_str) => {
-                let token = match RecaptchaToken::new(token_str) {
-                    Ok(t) => t,
-                    Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
-                };
-
-                if let Err(_) = state.recaptcha_service.verify_token(&token, None).await {
-                    return (jar, Err(AuthAPIError::InvalidCredentials));
-                }
-            }
-            None => {
-                return (
-                    jar,
-                    Ok((
-                        StatusCode::PRECONDITION_REQUIRED,
-                        Json(LoginResponse::RecaptchaRequired),
-                    )),
-                );
-            }
+    match check_recaptcha(&state, &email, request.recaptcha_token).await {
+        Ok(true) => (),
+        Ok(false) => {
+            return (
+                jar,
+                Ok((
+                    StatusCode::PRECONDITION_REQUIRED,
+                    Json(LoginResponse::RecaptchaRequired),
+                )),
+            );
         }
+        Err(e) => return (jar, Err(e)),
     }
 
     // Get user and validate credentials
@@ -72,8 +60,9 @@ pub async fn login(
         }
     };
 
-    // Record the login attempt
-    {
+    // Record the login attempt. The password is only half the login of users with 2FA, so
+    // their failed attempts are kept until the second factor is verified too.
+    if !user.as_ref().is_some_and(|user| user.requires_2fa) {
         let mut store = state.login_attempt_store.write().await;
         let attempt = LoginAttempt::new(email.clone(), user.is_some());
         if let Err(e) = store.record_attempt(attempt).await {
@@ -87,53 +76,148 @@ pub async fn login(
         None => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
+    // Only checked once the password is known to be correct, so the response does not
+    // reveal whether an account is verified
+    if state.settings.auth.require_email_verification && !user.email_verified {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    complete_login(&user, client_info, jar, state).await
+}
+
+// Whether the caller may go on logging in as the email. Once the email has seen too many
+// failed attempts a valid reCAPTCHA token is required, and without one the caller is
+// asked for it by returning false.
+#[tracing::instrument(name = "Check reCAPTCHA", skip_all)]
+pub(crate) async fn check_recaptcha(
+    state: &AppState,
+    email: &Email,
+    recaptcha_token: Option<String>,
+) -> Result<bool, AuthAPIError> {
+    let requires_recaptcha = state
+        .login_attempt_store
+        .read()
+        .await
+        .get_attempt_summary(email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .requires_recaptcha;
+    if !requires_recaptcha {
+        return Ok(true);
+    }
+
+    let token = match recaptcha_token {
+        Some(token) => RecaptchaToken::new(token).map_err(|_| AuthAPIError::InvalidCredentials)?,
+        None => return Ok(false),
+    };
+    state
+        .recaptcha_service
+        .verify_token(&token, None)
+        .await
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    Ok(true)
+}
+
+// Finish the login of a user who proved the first factor, starting their session or,
+// if they have 2FA, asking for the second factor
+#[tracing::instrument(name = "Complete Login", skip_all)]
+pub(crate) async fn complete_login(
+    user: &User,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    state: AppState,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    // Browsers the user trusts skip the second factor, which was proven on them before
+    let trusted_device = match user.requires_2fa {
+        true => match is_trusted_device(&jar, &state, &user.id).await {
+            Ok(trusted) => trusted,
+            Err(e) => return (jar, Err(e)),
+        },
+        false => false,
+    };
+    if trusted_device {
+        // The login is complete, which clears the failed attempts of the email
+        let attempt = LoginAttempt::new(user.email.clone(), true);
+        if let Err(e) = state
+            .login_attempt_store
+            .write()
+            .await
+            .record_attempt(attempt)
+            .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
     // Handle request based on user's 2FA configuration
-    match user.requires_2fa {
-        true => handle_2fa(&user.email, jar, state).await,
-        false => handle_no_2fa(&user.email, jar, &state.settings.auth).await,
+    match user.requires_2fa && !trusted_device {
+        true => handle_2fa(user, jar, state).await,
+        false => handle_no_2fa(&user.id, client_info, jar, &state).await,
     }
 }
 
 #[tracing::instrument(name = "Handle 2FA", skip_all)]
 async fn handle_2fa(
-    email: &Email,
+    user: &User,
     jar: CookieJar,
     state: AppState,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    use crate::domain::data_stores::{LoginAttemptId, TwoFACode};
-
     // Generate a new login attempt ID and 2FA code
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
-    // Store the 2FA code in the store
+    let method = match two_fa_method(&state, user).await {
+        Ok(method) => method,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Store the hash of the 2FA code in the store. Users proving the second factor with a
+    // passkey or an authenticator app are never sent the code, which then only tracks the
+    // login attempt.
+    let code_hash = hash_code(
+        state.settings.auth.two_fa_code_key.expose_secret(),
+        &login_attempt_id,
+        &two_fa_code,
+    );
     if let Err(e) = state
         .two_fa_code_store
         .write()
         .await
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(user.email.clone(), login_attempt_id.clone(), code_hash)
         .await
     {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
+    let passkey_options = match method {
+        TwoFAMethod::Passkey => {
+            let credentials = match passkey_credentials(&state, &user.id).await {
+                Ok(credentials) => credentials,
+                Err(e) => return (jar, Err(e)),
+            };
+            let ceremony = WebauthnCeremony::SecondFactor {
+                user_id: user.id,
+                login_attempt_id: login_attempt_id.clone(),
+            };
+            match passkey_request_options(&state, ceremony, &credentials).await {
+                Ok(options) => Some(options),
+                Err(e) => return (jar, Err(e)),
+            }
+        }
+        _ => None,
+    };
+
     // Send 2FA code via email
-    if let Err(e) = state
-        .email_client
-        .send_email(
-            email,
-            "Your 2FA Code",
-            &format!(
-                "Your two-factor authentication code is: {}",
-                two_fa_code.as_ref()
-            ),
-        )
-        .await
-    {
-        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    if method == TwoFAMethod::Email {
+        if let Err(e) = send_two_fa_code(&state, &user.email, &two_fa_code).await {
+            return (jar, Err(AuthAPIError::UnexpectedError(e)));
+        }
     }
 
     (
@@ -143,29 +227,50 @@ async fn handle_2fa(
             Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
                 message: "2FA required".to_string(),
                 login_attempt_id: login_attempt_id.as_ref().to_string(),
+                method,
+                passkey_options,
             })),
         )),
     )
 }
 
+// Email a 2FA code to the user
+#[tracing::instrument(name = "Send 2FA Code", skip_all)]
+pub(crate) async fn send_two_fa_code(
+    state: &AppState,
+    email: &Email,
+    code: &TwoFACode,
+) -> Result<()> {
+    let message = EmailTemplate::TwoFACode {
+        code: code.as_ref(),
+    }
+    .render(&state.settings.email)?;
+    state
+        .email_client
+        .send_multipart_email(email, &message)
+        .await
+}
+
 #[tracing::instrument(name = "Handle No 2FA", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    user_id: &UserId,
+    client_info: ClientInfo,
     jar: CookieJar,
-    auth_config: &crate::config::AuthConfig,
+    state: &AppState,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    // Generate auth cookie for successful login
-    let auth_cookie = match generate_auth_cookie(email, auth_config) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
-    };
+    // Start a new session with its auth and refresh cookies
+    let (auth_cookie, refresh_cookie) =
+        match start_session(state, user_id, false, client_info).await {
+            Ok(cookies) => cookies,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        };
 
     // Return success with updated cookie jar
     (
-        jar.add(auth_cookie),
+        jar.add(auth_cookie).add(refresh_cookie),
         Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))),
     )
 }
@@ -176,6 +281,15 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    // Where the user finds the code to send to `verify_2fa`, unless it is a passkey
+    pub method: TwoFAMethod,
+    // Challenge for the passkeys of the user, answered at `/webauthn/authenticate/verify`
+    #[serde(
+        rename = "passkeyOptions",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub passkey_options: Option<PasskeyRequestOptions>,
 }
 
 #[derive(Deserialize)]
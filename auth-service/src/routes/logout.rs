@@ -1,7 +1,12 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use axum_extra::extract::{cookie::Cookie, CookieJar};
 
-use crate::{domain::AuthAPIError, utils::auth::validate_token, AppState};
+use super::forget_session;
+use crate::{
+    domain::{AuthAPIError, RefreshToken},
+    utils::auth::{ban_session, ban_token, validate_token},
+    AppState,
+};
 
 #[tracing::instrument(name = "Logout", skip_all)]
 pub async fn logout(
@@ -17,34 +22,78 @@ pub async fn logout(
 
     let token = cookie.value();
 
-    match validate_token(
+    let claims = match validate_token(
         token,
         &app_state.banned_token_store,
+        &app_state.user_store,
+        &app_state.key_ring,
         &app_state.settings.auth,
     )
     .await
     {
-        Ok(_) => (),
+        Ok(claims) => claims,
         Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
-    }
+    };
 
     // Add the token to the banned token store
-    if let Err(e) = app_state
-        .banned_token_store
-        .write()
-        .await
-        .store_token(token.to_string())
+    if let Err(e) = ban_token(
+        token,
+        &claims,
+        &app_state.banned_token_store,
+        &app_state.settings.auth,
+    )
+    .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    // End the session the token was issued for, so its other tokens are rejected too
+    if let Some(session_id) = &claims.sid {
+        if let Err(e) = ban_session(
+            session_id,
+            &app_state.banned_token_store,
+            &app_state.settings.auth,
+        )
         .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e)));
+        }
+
+        let user_id = match claims.user_id() {
+            Ok(user_id) => user_id,
+            Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+        };
+        if let Err(e) = forget_session(&app_state, &user_id, session_id).await {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
+    // Revoke the refresh token family of this session, if any
+    if let Some(refresh_token) = jar
+        .get(&app_state.settings.auth.refresh_cookie_name)
+        .and_then(|cookie| RefreshToken::parse(cookie.value().to_owned()).ok())
     {
-        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        if let Err(e) = app_state
+            .refresh_token_store
+            .write()
+            .await
+            .revoke_family(refresh_token.family_id())
+            .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
     }
 
-    // Remove the JWT cookie by creating a removal cookie
+    // Remove the JWT and refresh cookies by creating removal cookies
     let removal_cookie = Cookie::build((app_state.settings.auth.jwt_cookie_name.clone(), ""))
         .path("/")
         .build();
+    let refresh_removal_cookie =
+        Cookie::build((app_state.settings.auth.refresh_cookie_name.clone(), ""))
+            .path("/")
+            .build();
 
-    let jar = jar.remove(removal_cookie);
+    let jar = jar.remove(removal_cookie).remove(refresh_removal_cookie);
 
     (jar, Ok(StatusCode::OK))
 }
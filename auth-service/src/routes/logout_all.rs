@@ -0,0 +1,84 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+use color_eyre::eyre::Result;
+
+use super::remove_session_cookies;
+use crate::{
+    domain::{AuthAPIError, RefreshToken, UserId},
+    utils::auth::validate_token,
+    AppState,
+};
+
+// Log the user out of every session. Bumping the session epoch invalidates every
+// access token and refresh token family issued so far, not only the current ones.
+#[tracing::instrument(name = "Logout All", skip_all)]
+pub async fn logout_all(
+    State(app_state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let cookie = match jar.get(&app_state.settings.auth.jwt_cookie_name) {
+        Some(cookie) => cookie,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let claims = match validate_token(
+        cookie.value(),
+        &app_state.banned_token_store,
+        &app_state.user_store,
+        &app_state.key_ring,
+        &app_state.settings.auth,
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let user_id = match claims.user_id() {
+        Ok(user_id) => user_id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    if let Err(e) = end_all_sessions(&app_state, &user_id).await {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    // Other families are rejected on their next rotation, but the current one
+    // can be dropped from the store right away
+    if let Some(refresh_token) = jar
+        .get(&app_state.settings.auth.refresh_cookie_name)
+        .and_then(|cookie| RefreshToken::parse(cookie.value().to_owned()).ok())
+    {
+        if let Err(e) = app_state
+            .refresh_token_store
+            .write()
+            .await
+            .revoke_family(refresh_token.family_id())
+            .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
+    (remove_session_cookies(jar, &app_state), Ok(StatusCode::OK))
+}
+
+// Invalidate every token issued to the user so far and empty their session inventory
+#[tracing::instrument(name = "End All Sessions", skip_all)]
+pub(crate) async fn end_all_sessions(state: &AppState, user_id: &UserId) -> Result<()> {
+    state
+        .user_store
+        .write()
+        .await
+        .increment_session_epoch(user_id)
+        .await?;
+
+    state
+        .session_store
+        .write()
+        .await
+        .remove_all_sessions(user_id)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use super::{authenticate, check_recaptcha, complete_login, LoginResponse};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailToken, EmailTokenPurpose, EmailTokenStoreError, LoginAttempt,
+        LoginAttemptStore, UserStoreError,
+    },
+    utils::{
+        client_info::ClientInfo,
+        email_templates::{email_link, EmailTemplate},
+    },
+};
+
+// Email the user a link that logs them in without their password
+#[tracing::instrument(name = "Request Magic Link", skip_all)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    Json(request): Json<MagicLinkRequest>,
+) -> Result<Response, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    // Asking for links is gated like password logins, with the same response
+    if !check_recaptcha(&state, &email, request.recaptcha_token).await? {
+        return Ok((
+            StatusCode::PRECONDITION_REQUIRED,
+            Json(LoginResponse::RecaptchaRequired),
+        )
+            .into_response());
+    }
+
+    // Every link counts as a failed attempt until it is used, so asking for links over
+    // and over calls for reCAPTCHA just like guessing passwords does
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(LoginAttempt::new(email.clone(), false))
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let user = state.user_store.read().await.get_user(&email).await;
+
+    // The response is the same whether or not a link was sent, so it does not reveal
+    // which addresses have an account or opted out. The email is sent in the background,
+    // so the response does not take longer when a link is sent either.
+    match user {
+        Ok(user) if user.magic_link_enabled => {
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = send_magic_link_email(&state, &user.email).await {
+                    tracing::error!("Failed to send magic link email: {:?}", e);
+                }
+            });
+        }
+        Ok(_) | Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(MagicLinkResponse {
+        message: "If the account exists, a login link has been sent".to_string(),
+    });
+
+    Ok((StatusCode::OK, response).into_response())
+}
+
+// Log in with the token of a magic link. The link stands in for the password, so users
+// with 2FA are still asked for the second factor.
+#[tracing::instrument(name = "Confirm Magic Link", skip_all)]
+pub async fn confirm_magic_link(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<MagicLinkConfirmRequest>,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let token = match EmailToken::parse(request.token) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let email = match state
+        .email_token_store
+        .write()
+        .await
+        .consume_token(&token, EmailTokenPurpose::MagicLink)
+        .await
+    {
+        Ok(email) => email,
+        Err(EmailTokenStoreError::TokenNotFound) => return (jar, Err(AuthAPIError::InvalidToken)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // The account may have been deleted, or opted out, since the link was sent
+    let user = {
+        let mut user_store = state.user_store.write().await;
+        let user = match user_store.get_user(&email).await {
+            Ok(user) if user.magic_link_enabled => user,
+            Ok(_) | Err(UserStoreError::UserNotFound) => {
+                return (jar, Err(AuthAPIError::InvalidToken))
+            }
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+        };
+
+        // Following the link proves the user owns the address
+        if let Err(e) = user_store.mark_email_verified(&email).await {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+        user
+    };
+
+    // Without 2FA the login is complete, which clears the failed attempts of the email
+    if !user.requires_2fa {
+        let attempt = LoginAttempt::new(email, true);
+        if let Err(e) = state
+            .login_attempt_store
+            .write()
+            .await
+            .record_attempt(attempt)
+            .await
+        {
+            return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+        }
+    }
+
+    complete_login(&user, client_info, jar, state).await
+}
+
+// Let the user log in with magic links again
+#[tracing::instrument(name = "Enable Magic Link", skip_all)]
+pub async fn enable_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    set_magic_link_enabled(&state, &jar, true).await?;
+
+    let response = Json(MagicLinkResponse {
+        message: "Magic link login enabled".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Opt the user out of magic links. Links already sent stop working too.
+#[tracing::instrument(name = "Disable Magic Link", skip_all)]
+pub async fn disable_magic_link(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    set_magic_link_enabled(&state, &jar, false).await?;
+
+    let response = Json(MagicLinkResponse {
+        message: "Magic link login disabled".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+async fn set_magic_link_enabled(
+    state: &AppState,
+    jar: &CookieJar,
+    enabled: bool,
+) -> Result<(), AuthAPIError> {
+    let (_, user_id) = authenticate(jar, state).await?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_magic_link_enabled(&user_id, enabled)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })
+}
+
+// Issue a magic link token for the user and email them the link consuming it
+#[tracing::instrument(name = "Send Magic Link Email", skip_all)]
+async fn send_magic_link_email(state: &AppState, email: &Email) -> Result<()> {
+    let token = EmailToken::default();
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(
+            token.clone(),
+            EmailTokenPurpose::MagicLink,
+            email.clone(),
+            state.settings.auth.magic_link_ttl_seconds,
+        )
+        .await?;
+
+    let link = email_link(&state.settings.email, "magic_link_token", &token);
+    let message = EmailTemplate::MagicLink { link: &link }.render(&state.settings.email)?;
+
+    state
+        .email_client
+        .send_multipart_email(email, &message)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkRequest {
+    pub email: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MagicLinkConfirmRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct MagicLinkResponse {
+    pub message: String,
+}
@@ -1,13 +1,45 @@
+mod change_email;
+mod change_password;
 mod delete_account;
+mod jwks;
 mod login;
 mod logout;
+mod logout_all;
+mod magic_link;
+mod password_reset;
+mod promote_signing_key;
+mod recovery_codes;
+mod refresh_token;
+mod resend_2fa;
+mod sessions;
 mod signup;
+mod totp;
+mod trusted_devices;
+mod two_fa;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
 
+pub use change_email::*;
+pub use change_password::*;
 pub use delete_account::*;
+pub use jwks::*;
 pub use login::*;
 pub use logout::*;
+pub use logout_all::*;
+pub use magic_link::*;
+pub use password_reset::*;
+pub use promote_signing_key::*;
+pub use recovery_codes::*;
+pub use refresh_token::*;
+pub use resend_2fa::*;
+pub use sessions::*;
 pub use signup::*;
+pub use totp::*;
+pub use trusted_devices::*;
+pub use two_fa::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
+pub use webauthn::*;
@@ -0,0 +1,168 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use super::end_all_sessions;
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailToken, EmailTokenPurpose, EmailTokenStoreError,
+        LoginAttemptStore, Password, RecaptchaToken, UserStoreError,
+    },
+    utils::email_templates::{email_link, EmailTemplate},
+};
+
+#[tracing::instrument(name = "Request Password Reset", skip_all)]
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let recaptcha_token = RecaptchaToken::new(request.recaptcha_token)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    state
+        .recaptcha_service
+        .verify_token(&recaptcha_token, None)
+        .await
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let user = state.user_store.read().await.get_user(&email).await;
+
+    // The response is the same whether or not the account exists. The email is sent in
+    // the background, so the response does not take longer for existing accounts either.
+    match user {
+        Ok(_) => {
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = send_password_reset_email(&state, &email).await {
+                    tracing::error!("Failed to send password reset email: {:?}", e);
+                }
+            });
+        }
+        Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(PasswordResetResponse {
+        message: "If the account exists, a password reset email has been sent".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Confirm Password Reset", skip_all)]
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailToken::parse(request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // The new password is checked before consuming the token, so a rejected password
+    // does not use up the link
+    let password = Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let email = state
+        .email_token_store
+        .write()
+        .await
+        .consume_token(&token, EmailTokenPurpose::ResetPassword)
+        .await
+        .map_err(|e| match e {
+            EmailTokenStoreError::TokenNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let user_id = {
+        let mut user_store = state.user_store.write().await;
+        let map_error = |e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        };
+        let user = user_store.get_user(&email).await.map_err(map_error)?;
+        user_store
+            .update_password(&user.id, password)
+            .await
+            .map_err(map_error)?;
+
+        // Following the link proves the user owns the address
+        user_store
+            .mark_email_verified(&email)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        user.id
+    };
+
+    // Whoever knew the old password is logged out everywhere
+    end_all_sessions(&state, &user_id)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    // And no browser skips 2FA anymore
+    state
+        .trusted_device_store
+        .write()
+        .await
+        .remove_all_devices(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .reset_attempts(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(PasswordResetResponse {
+        message: "Password reset successfully!".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Issue a password reset token for the user and email them the link consuming it
+#[tracing::instrument(name = "Send Password Reset Email", skip_all)]
+async fn send_password_reset_email(state: &AppState, email: &Email) -> Result<()> {
+    let token = EmailToken::default();
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(
+            token.clone(),
+            EmailTokenPurpose::ResetPassword,
+            email.clone(),
+            state.settings.auth.password_reset_ttl_seconds,
+        )
+        .await?;
+
+    let link = email_link(&state.settings.email, "reset_password_token", &token);
+    let message = EmailTemplate::ResetPassword { link: &link }.render(&state.settings.email)?;
+
+    state
+        .email_client
+        .send_multipart_email(email, &message)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
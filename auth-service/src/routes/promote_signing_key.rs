@@ -0,0 +1,48 @@
+use axum::{
+    extract::{Json, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{
+    domain::AuthAPIError,
+    utils::{auth::authorize_admin, key_ring::KeyRingError},
+    AppState,
+};
+
+#[derive(Deserialize)]
+pub struct PromoteSigningKeyRequest {
+    pub kid: String,
+}
+
+// Make a configured key the active JWT signing key on every instance. The previous key
+// keeps verifying tokens for one token lifetime plus the leeway, so live sessions
+// survive the rotation.
+#[tracing::instrument(name = "Promote Signing Key", skip_all)]
+pub async fn promote_signing_key(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<PromoteSigningKeyRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authorize_admin(&headers, &state.settings.admin)?;
+
+    let auth_config = &state.settings.auth;
+    let overlap_seconds = auth_config
+        .token_ttl_seconds
+        .saturating_add_unsigned(auth_config.leeway_seconds);
+
+    state
+        .key_ring
+        .write()
+        .await
+        .promote(&request.kid, Utc::now().timestamp(), overlap_seconds)
+        .await
+        .map_err(|e| match e {
+            KeyRingError::KeyNotFound | KeyRingError::KeyRetired => AuthAPIError::InvalidInput,
+            KeyRingError::UnexpectedError(e) => AuthAPIError::UnexpectedError(e),
+        })?;
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,170 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{authenticate, check_recaptcha, LoginResponse};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, LoginAttempt, LoginAttemptStore, Password, RecoveryCode,
+        RecoveryCodeStoreError, UserId, UserStoreError,
+    },
+    utils::email_templates::EmailTemplate,
+};
+
+// Number of codes in a set, each usable once
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// Replace the recovery codes of the user with a new set. The codes are only ever shown
+// in this response, so the current password is asked for before handing them out.
+#[tracing::instrument(name = "Regenerate Recovery Codes", skip_all)]
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<RegenerateRecoveryCodesRequest>,
+) -> Result<Response, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let email = state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?
+        .email;
+
+    // Guessing the password with a stolen session is gated like guessing it on login,
+    // and the failures count towards the same limit
+    if !check_recaptcha(&state, &email, request.recaptcha_token).await? {
+        return Ok((
+            StatusCode::PRECONDITION_REQUIRED,
+            Json(LoginResponse::RecaptchaRequired),
+        )
+            .into_response());
+    }
+
+    let valid = match state
+        .user_store
+        .read()
+        .await
+        .validate_user(&email, &password)
+        .await
+    {
+        Ok(()) => true,
+        Err(UserStoreError::InvalidCredentials) => false,
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(LoginAttempt::new(email, valid))
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    if !valid {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    let recovery_codes = issue_recovery_codes(&state, &user_id)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RecoveryCodesResponse { recovery_codes }),
+    )
+        .into_response())
+}
+
+// Generate a new set of recovery codes for the user, replacing any earlier set, and
+// return them for showing to the user once
+#[tracing::instrument(name = "Issue Recovery Codes", skip_all)]
+pub(crate) async fn issue_recovery_codes(
+    state: &AppState,
+    user_id: &UserId,
+) -> Result<Vec<String>> {
+    let codes: Vec<RecoveryCode> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| RecoveryCode::default())
+        .collect();
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .replace_codes(user_id, &codes)
+        .await?;
+
+    Ok(codes
+        .iter()
+        .map(|code| code.as_ref().expose_secret().to_owned())
+        .collect())
+}
+
+// Accept a recovery code of the user in place of their second factor, at most once, and
+// let them know by email that one was used
+#[tracing::instrument(name = "Use Recovery Code", skip_all)]
+pub(crate) async fn use_recovery_code(
+    state: &AppState,
+    user_id: &UserId,
+    email: &Email,
+    code: &RecoveryCode,
+) -> Result<(), AuthAPIError> {
+    let mut recovery_code_store = state.recovery_code_store.write().await;
+    match recovery_code_store.use_code(user_id, code).await {
+        Ok(()) => {}
+        Err(RecoveryCodeStoreError::CodeNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+    let remaining = recovery_code_store.count_codes(user_id).await;
+    drop(recovery_code_store);
+
+    // The code is used up by now, so a failure to notify the user is only logged
+    let notification = remaining.map_err(Into::into).and_then(|remaining| {
+        EmailTemplate::RecoveryCodeUsed { remaining }.render(&state.settings.email)
+    });
+    let sent = match notification {
+        Ok(message) => {
+            state
+                .email_client
+                .send_multipart_email(email, &message)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = sent {
+        tracing::error!(
+            "Failed to send recovery code notification to user {}: {:?}",
+            user_id,
+            e
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateRecoveryCodesRequest {
+    pub password: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
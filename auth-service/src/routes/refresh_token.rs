@@ -0,0 +1,151 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, RefreshToken, RefreshTokenStoreError, SessionStoreError, UserId,
+        UserStoreError,
+    },
+    utils::auth::{ban_session, create_refresh_cookie, generate_auth_cookie},
+};
+
+#[tracing::instrument(name = "Refresh Token", skip_all)]
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let auth_config = &state.settings.auth;
+
+    let cookie = match jar.get(&auth_config.refresh_cookie_name) {
+        Some(cookie) => cookie,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let token = match RefreshToken::parse(cookie.value().to_owned()) {
+        Ok(token) => token,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let mut refresh_token_store = state.refresh_token_store.write().await;
+
+    let (family, new_token) = match refresh_token_store.rotate_token(&token).await {
+        Ok(rotated) => rotated,
+        Err(RefreshTokenStoreError::TokenReused(user_id)) => {
+            // A token that was already rotated has been presented again, so it may have
+            // been stolen. Revoke the whole family to log out every holder of it.
+            tracing::warn!("refresh token reuse detected, revoking token family");
+            if let Err(e) = refresh_token_store.revoke_family(token.family_id()).await {
+                return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+            }
+            // Access tokens already issued to the session are rejected as well
+            if let Err(e) =
+                ban_session(token.family_id(), &state.banned_token_store, auth_config).await
+            {
+                return (jar, Err(AuthAPIError::UnexpectedError(e)));
+            }
+            if let Some(user_id) = user_id {
+                if let Err(e) = forget_session(&state, &user_id, token.family_id()).await {
+                    return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+                }
+            }
+            return (
+                remove_session_cookies(jar, &state),
+                Err(AuthAPIError::InvalidToken),
+            );
+        }
+        Err(RefreshTokenStoreError::FamilyNotFound) => {
+            return (jar, Err(AuthAPIError::InvalidToken))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    // The family was started before the user logged out everywhere, or the user is gone
+    let session_epoch = state
+        .user_store
+        .read()
+        .await
+        .get_session_epoch(&family.user_id)
+        .await;
+    match session_epoch {
+        Ok(session_epoch) if session_epoch == family.session_epoch => (),
+        Ok(_) | Err(UserStoreError::UserNotFound) => {
+            if let Err(e) = refresh_token_store.revoke_family(token.family_id()).await {
+                return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+            }
+            if let Err(e) = forget_session(&state, &family.user_id, token.family_id()).await {
+                return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+            }
+            return (
+                remove_session_cookies(jar, &state),
+                Err(AuthAPIError::InvalidToken),
+            );
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // Sessions started before the session inventory existed are not listed
+    match state
+        .session_store
+        .write()
+        .await
+        .touch_session(&family.user_id, token.family_id(), Utc::now().timestamp())
+        .await
+    {
+        Ok(()) | Err(SessionStoreError::SessionNotFound) => (),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    let auth_cookie = match generate_auth_cookie(
+        &family.user_id,
+        family.session_epoch,
+        token.family_id(),
+        &state.key_ring,
+        auth_config,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+
+    let refresh_cookie = match create_refresh_cookie(&new_token, auth_config) {
+        Ok(cookie) => cookie,
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+// Drop a session from the inventory of its user, if it is still listed
+pub(crate) async fn forget_session(
+    state: &AppState,
+    user_id: &UserId,
+    session_id: &str,
+) -> Result<(), SessionStoreError> {
+    match state
+        .session_store
+        .write()
+        .await
+        .remove_session(user_id, session_id)
+        .await
+    {
+        Ok(()) | Err(SessionStoreError::SessionNotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn remove_session_cookies(jar: CookieJar, state: &AppState) -> CookieJar {
+    let auth_config = &state.settings.auth;
+    jar.remove(
+        Cookie::build((auth_config.jwt_cookie_name.clone(), ""))
+            .path("/")
+            .build(),
+    )
+    .remove(
+        Cookie::build((auth_config.refresh_cookie_name.clone(), ""))
+            .path("/")
+            .build(),
+    )
+}
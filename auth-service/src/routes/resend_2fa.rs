@@ -0,0 +1,91 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use super::{send_two_fa_code, two_fa_method};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, LoginAttemptId, TwoFACode, TwoFACodeStoreError, TwoFAMethod,
+        UserStoreError,
+    },
+    utils::two_fa_code::hash_code,
+};
+
+// Email a new code for a pending login attempt, in case the first one never arrived. The
+// new code replaces the old one, but the login attempt keeps its expiry and its failed
+// attempts, and it can only be resent a few times.
+#[tracing::instrument(name = "Resend 2FA", skip_all)]
+pub async fn resend_2fa(
+    State(state): State<AppState>,
+    Json(request): Json<Resend2FARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(Secret::new(request.email)).map_err(|_| AuthAPIError::InvalidInput)?;
+    let login_attempt_id =
+        LoginAttemptId::parse(request.login_attempt_id).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // Only emailed codes can be sent again
+    if two_fa_method(&state, &user).await? != TwoFAMethod::Email {
+        return Err(AuthAPIError::InvalidInput);
+    }
+
+    let code = TwoFACode::default();
+    let code_hash = hash_code(
+        state.settings.auth.two_fa_code_key.expose_secret(),
+        &login_attempt_id,
+        &code,
+    );
+    {
+        let mut two_fa_code_store = state.two_fa_code_store.write().await;
+        let resends = two_fa_code_store
+            .record_resend(&email, &login_attempt_id)
+            .await
+            .map_err(|e| match e {
+                TwoFACodeStoreError::LoginAttemptIdNotFound => AuthAPIError::IncorrectCredentials,
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })?;
+        if resends > state.settings.auth.max_2fa_resends {
+            return Err(AuthAPIError::TooManyRequests);
+        }
+        two_fa_code_store
+            .replace_code(&email, &login_attempt_id, code_hash)
+            .await
+            .map_err(|e| match e {
+                TwoFACodeStoreError::LoginAttemptIdNotFound => AuthAPIError::IncorrectCredentials,
+                e => AuthAPIError::UnexpectedError(e.into()),
+            })?;
+    }
+
+    send_two_fa_code(&state, &user.email, &code)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(Resend2FAResponse {
+        message: "2FA code sent".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Resend2FARequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Resend2FAResponse {
+    pub message: String,
+}
@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::remove_session_cookies;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RefreshTokenFamily, Session, SessionStoreError, UserId},
+    utils::{
+        auth::{ban_session, create_refresh_cookie, generate_auth_cookie, validate_token, Claims},
+        client_info::ClientInfo,
+    },
+};
+
+#[tracing::instrument(name = "List Sessions", skip_all)]
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (claims, user_id) = authenticate(&jar, &state).await?;
+
+    let sessions = state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: claims.sid.as_deref() == Some(session.id.as_str()),
+            session,
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(SessionsResponse { sessions })))
+}
+
+#[tracing::instrument(name = "Revoke Session", skip_all)]
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(session_id): Path<String>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let (claims, user_id) = match authenticate(&jar, &state).await {
+        Ok(authenticated) => authenticated,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Sessions are looked up among the caller's own, so other users' sessions are never found
+    match state
+        .session_store
+        .write()
+        .await
+        .remove_session(&user_id, &session_id)
+        .await
+    {
+        Ok(()) => (),
+        Err(SessionStoreError::SessionNotFound) => {
+            return (jar, Err(AuthAPIError::SessionNotFound))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    if let Err(e) = state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_family(&session_id)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    if let Err(e) = ban_session(&session_id, &state.banned_token_store, &state.settings.auth).await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e)));
+    }
+
+    // Revoking the current session logs the caller out
+    let jar = if claims.sid.as_deref() == Some(session_id.as_str()) {
+        remove_session_cookies(jar, &state)
+    } else {
+        jar
+    };
+
+    (jar, Ok(StatusCode::OK))
+}
+
+// Start a new session for the user: a refresh token family, its entry in the session
+// inventory and a JWT bound to it. Returns the auth and refresh cookies to set.
+#[tracing::instrument(name = "Start Session", skip_all)]
+pub(crate) async fn start_session(
+    state: &AppState,
+    user_id: &UserId,
+    two_fa_used: bool,
+    client_info: ClientInfo,
+) -> Result<(Cookie<'static>, Cookie<'static>)> {
+    let auth_config = &state.settings.auth;
+
+    let session_epoch = state
+        .user_store
+        .read()
+        .await
+        .get_session_epoch(user_id)
+        .await?;
+
+    let refresh_token = state
+        .refresh_token_store
+        .write()
+        .await
+        .create_family(RefreshTokenFamily {
+            user_id: *user_id,
+            session_epoch,
+        })
+        .await?;
+
+    let now = Utc::now().timestamp();
+    let session = Session {
+        id: refresh_token.family_id().to_owned(),
+        created_at: now,
+        last_seen: now,
+        user_agent: client_info.user_agent,
+        ip_address: client_info.ip_address,
+        two_fa_used,
+    };
+    state
+        .session_store
+        .write()
+        .await
+        .add_session(user_id, session)
+        .await?;
+
+    let auth_cookie = generate_auth_cookie(
+        user_id,
+        session_epoch,
+        refresh_token.family_id(),
+        &state.key_ring,
+        auth_config,
+    )
+    .await?;
+    let refresh_cookie = create_refresh_cookie(&refresh_token, auth_config)?;
+
+    Ok((auth_cookie, refresh_cookie))
+}
+
+// Validate the JWT cookie of the caller and return its claims along with the id of its user
+pub(crate) async fn authenticate(
+    jar: &CookieJar,
+    state: &AppState,
+) -> Result<(Claims, UserId), AuthAPIError> {
+    let cookie = jar
+        .get(&state.settings.auth.jwt_cookie_name)
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let claims = validate_token(
+        cookie.value(),
+        &state.banned_token_store,
+        &state.user_store,
+        &state.key_ring,
+        &state.settings.auth,
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let user_id = claims.user_id().map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Ok((claims, user_id))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    pub session: Session,
+    // Whether this is the session of the request listing it
+    pub current: bool,
+}
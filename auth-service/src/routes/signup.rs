@@ -2,8 +2,9 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 
+use super::{issue_recovery_codes, send_verification_email};
 use crate::{
-    domain::{AuthAPIError, Email, Password, RecaptchaToken, User, UserStore},
+    domain::{AuthAPIError, Email, Password, RecaptchaToken, User},
     AppState,
 };
 
@@ -29,6 +30,7 @@ pub async fn signup(
         Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
     let user = User::new(email.clone(), password, request.requires_2fa);
+    let user_id = user.id;
 
     let mut user_store = state.user_store.write().await;
 
@@ -40,9 +42,36 @@ pub async fn signup(
         .add_user(user)
         .await
         .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    drop(user_store);
+
+    // The account exists from here on, so failing the request would only make retries
+    // fail as well. The user can ask for a new link through /verify-email/resend instead.
+    let mut message = "User created successfully!".to_string();
+    if let Err(e) = send_verification_email(&state, &email).await {
+        tracing::error!("Failed to send verification email on signup: {:?}", e);
+        message.push_str(" The verification email could not be sent, please request a new one");
+    }
+
+    // Users signing up with 2FA get recovery codes right away, for when they lose access
+    // to their second factor. Failing to issue them is not fatal either, as they can be
+    // regenerated once logged in.
+    let recovery_codes = match request.requires_2fa {
+        true => match issue_recovery_codes(&state, &user_id).await {
+            Ok(recovery_codes) => Some(recovery_codes),
+            Err(e) => {
+                tracing::error!("Failed to issue recovery codes on signup: {:?}", e);
+                message.push_str(
+                    " The recovery codes could not be issued, please regenerate them after logging in",
+                );
+                None
+            }
+        },
+        false => None,
+    };
 
     let response = Json(SignupResponse {
-        message: "User created successfully!".to_string(),
+        message,
+        recovery_codes,
     });
 
     Ok((StatusCode::CREATED, response))
@@ -61,4 +90,10 @@ pub struct SignupRequest {
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
 }
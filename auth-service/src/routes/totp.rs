@@ -0,0 +1,153 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::authenticate;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, TotpSecret, TotpStoreError, TwoFACode, UserId, UserStoreError},
+    utils::totp,
+};
+
+// Start enrolling an authenticator app. The returned secret only becomes usable for 2FA
+// once confirmed with a code from the app, and enrolling again replaces it until then.
+#[tracing::instrument(name = "Enroll TOTP", skip_all)]
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let secret = TotpSecret::default();
+    let response = Json(TotpEnrollmentResponse {
+        secret: secret.to_base32().expose_secret().to_owned(),
+        otpauth_uri: totp::provisioning_uri(
+            &state.settings.email.product_name,
+            user.email.as_ref().expose_secret(),
+            &secret,
+        ),
+    });
+
+    state
+        .totp_store
+        .write()
+        .await
+        .set_pending_secret(&user_id, secret)
+        .await
+        .map_err(|e| match e {
+            TotpStoreError::AlreadyConfirmed => AuthAPIError::TotpAlreadyEnabled,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Confirm TOTP", skip_all)]
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<TotpConfirmRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let enrollment = state
+        .totp_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+        .map_err(|e| match e {
+            // There is nothing to confirm before enrolling
+            TotpStoreError::SecretNotFound => AuthAPIError::InvalidInput,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+    if enrollment.confirmed {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    verify_totp_code(&state, &user_id, &enrollment.secret, &code).await?;
+
+    state
+        .totp_store
+        .write()
+        .await
+        .confirm_secret(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(TotpConfirmResponse {
+        message: "TOTP enabled".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Users with a confirmed authenticator app prove the second factor with it, everyone
+// else with a code sent by email
+#[tracing::instrument(name = "Get Confirmed TOTP Secret", skip_all)]
+pub(crate) async fn confirmed_totp_secret(
+    state: &AppState,
+    user_id: &UserId,
+) -> Result<Option<TotpSecret>, AuthAPIError> {
+    match state.totp_store.read().await.get_secret(user_id).await {
+        Ok(enrollment) if enrollment.confirmed => Ok(Some(enrollment.secret)),
+        Ok(_) | Err(TotpStoreError::SecretNotFound) => Ok(None),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+// Accept a code of the authenticator app of the user, at most once
+#[tracing::instrument(name = "Verify TOTP Code", skip_all)]
+pub(crate) async fn verify_totp_code(
+    state: &AppState,
+    user_id: &UserId,
+    secret: &TotpSecret,
+    code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    let now = u64::try_from(Utc::now().timestamp())
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    let step = totp::verify_code(secret, code, now, state.settings.auth.totp_drift_steps)
+        .ok_or(AuthAPIError::IncorrectCredentials)?;
+
+    state
+        .totp_store
+        .write()
+        .await
+        .use_time_step(user_id, step)
+        .await
+        .map_err(|e| match e {
+            TotpStoreError::CodeReused => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })
+}
+
+#[derive(Deserialize)]
+pub struct TotpConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TotpConfirmResponse {
+    pub message: String,
+}
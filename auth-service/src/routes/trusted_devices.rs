@@ -0,0 +1,195 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::authenticate;
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, TrustedDevice, TrustedDeviceStoreError, UserId},
+    utils::{
+        auth::create_trusted_device_cookie,
+        client_info::ClientInfo,
+        trusted_device::{sign_device, verify_device},
+    },
+};
+
+#[tracing::instrument(name = "List Trusted Devices", skip_all)]
+pub async fn list_trusted_devices(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+
+    let devices = state
+        .trusted_device_store
+        .read()
+        .await
+        .get_devices(&user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let current_device_id = cookie_device_id(&jar, &state, &user_id);
+    let devices = devices
+        .into_iter()
+        .map(|device| TrustedDeviceResponse {
+            current: current_device_id.as_deref() == Some(device.id.as_str()),
+            device,
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(TrustedDevicesResponse { devices })))
+}
+
+#[tracing::instrument(name = "Revoke Trusted Device", skip_all)]
+pub async fn revoke_trusted_device(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user_id = match authenticate(&jar, &state).await {
+        Ok((_, user_id)) => user_id,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Devices are looked up among the caller's own, so other users' devices are never found
+    match state
+        .trusted_device_store
+        .write()
+        .await
+        .remove_device(&user_id, &device_id)
+        .await
+    {
+        Ok(()) => (),
+        Err(TrustedDeviceStoreError::DeviceNotFound) => {
+            return (jar, Err(AuthAPIError::TrustedDeviceNotFound))
+        }
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    }
+
+    // The cookie of a revoked device is of no use anymore
+    let jar = match cookie_device_id(&jar, &state, &user_id) == Some(device_id) {
+        true => remove_trusted_device_cookie(jar, &state),
+        false => jar,
+    };
+
+    (jar, Ok(StatusCode::OK))
+}
+
+#[tracing::instrument(name = "Revoke All Trusted Devices", skip_all)]
+pub async fn revoke_trusted_devices(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user_id = match authenticate(&jar, &state).await {
+        Ok((_, user_id)) => user_id,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    if let Err(e) = state
+        .trusted_device_store
+        .write()
+        .await
+        .remove_all_devices(&user_id)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    (
+        remove_trusted_device_cookie(jar, &state),
+        Ok(StatusCode::OK),
+    )
+}
+
+// Trust the browser of the client to skip 2FA on login for the configured number of days.
+// Returns the cookie that remembers it.
+#[tracing::instrument(name = "Trust Device", skip_all)]
+pub(crate) async fn trust_device(
+    state: &AppState,
+    user_id: &UserId,
+    client_info: ClientInfo,
+) -> Result<Cookie<'static>> {
+    let auth_config = &state.settings.auth;
+
+    let now = Utc::now().timestamp();
+    let device = TrustedDevice {
+        id: uuid::Uuid::new_v4().to_string(),
+        created_at: now,
+        expires_at: now + i64::try_from(auth_config.trusted_device_days * 86400)?,
+        user_agent: client_info.user_agent,
+        ip_address: client_info.ip_address,
+    };
+    let value = sign_device(
+        auth_config.trusted_device_key.expose_secret(),
+        user_id,
+        &device.id,
+    );
+    state
+        .trusted_device_store
+        .write()
+        .await
+        .add_device(user_id, device)
+        .await?;
+
+    create_trusted_device_cookie(value, auth_config)
+}
+
+// Whether the caller logs in from a browser the user trusts
+#[tracing::instrument(name = "Check Trusted Device", skip_all)]
+pub(crate) async fn is_trusted_device(
+    jar: &CookieJar,
+    state: &AppState,
+    user_id: &UserId,
+) -> Result<bool, AuthAPIError> {
+    let device_id = match cookie_device_id(jar, state, user_id) {
+        Some(device_id) => device_id,
+        None => return Ok(false),
+    };
+
+    state
+        .trusted_device_store
+        .read()
+        .await
+        .is_trusted(user_id, &device_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+// Id of the device in the trusted device cookie of the caller, if it was signed for the user
+fn cookie_device_id(jar: &CookieJar, state: &AppState, user_id: &UserId) -> Option<String> {
+    let cookie = jar.get(&state.settings.auth.trusted_device_cookie_name)?;
+    verify_device(
+        state.settings.auth.trusted_device_key.expose_secret(),
+        user_id,
+        cookie.value(),
+    )
+}
+
+fn remove_trusted_device_cookie(jar: CookieJar, state: &AppState) -> CookieJar {
+    jar.remove(
+        Cookie::build((state.settings.auth.trusted_device_cookie_name.clone(), ""))
+            .path("/")
+            .build(),
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustedDevicesResponse {
+    pub devices: Vec<TrustedDeviceResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustedDeviceResponse {
+    #[serde(flatten)]
+    pub device: TrustedDevice,
+    // Whether this is the device of the request listing it
+    pub current: bool,
+}
@@ -0,0 +1,306 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    authenticate, check_recaptcha, confirmed_totp_secret, issue_recovery_codes,
+    passkey_credentials, verify_totp_code, LoginResponse,
+};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, LoginAttempt, LoginAttemptStore, Password, TwoFACode, TwoFAMethod, User,
+        UserId, UserStoreError,
+    },
+};
+
+// Turn on 2FA for the user, with the method they choose or, without a choice, the
+// strongest one they have set up. Enabling hands out a new set of recovery codes.
+#[tracing::instrument(name = "Enable 2FA", skip_all)]
+pub async fn enable_2fa(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<EnableTwoFARequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+    let user = get_user(&state, &user_id).await?;
+    if user.requires_2fa {
+        return Err(AuthAPIError::TwoFAAlreadyEnabled);
+    }
+    if let Some(method) = request.method {
+        ensure_set_up(&state, &user_id, method).await?;
+    }
+
+    {
+        let mut user_store = state.user_store.write().await;
+        user_store
+            .set_two_fa_method(&user_id, request.method)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        user_store
+            .set_requires_2fa(&user_id, true)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    let recovery_codes = issue_recovery_codes(&state, &user_id)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(EnableTwoFAResponse {
+        message: "2FA enabled".to_string(),
+        recovery_codes,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Turn off 2FA for the user. A stolen session alone must not be enough, so the user
+// proves who they are again with their password or a code of their authenticator app.
+#[tracing::instrument(name = "Disable 2FA", skip_all)]
+pub async fn disable_2fa(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<DisableTwoFARequest>,
+) -> Result<Response, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+    let user = get_user(&state, &user_id).await?;
+    if !user.requires_2fa {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    if !reauthenticate(&state, &user, request.proof).await? {
+        return Ok(recaptcha_required());
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_requires_2fa(&user_id, false)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // Recovery codes are only good for 2FA, and enabling it again issues new ones
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .replace_codes(&user_id, &[])
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(TwoFAResponse {
+        message: "2FA disabled".to_string(),
+    });
+
+    Ok((StatusCode::OK, response).into_response())
+}
+
+// Choose the method the user proves the second factor with. Without a method the
+// strongest one they have set up is used. Switching to a weaker method takes the same
+// proof as turning 2FA off.
+#[tracing::instrument(name = "Set 2FA Method", skip_all)]
+pub async fn set_2fa_method(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<SetTwoFAMethodRequest>,
+) -> Result<Response, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+    let user = get_user(&state, &user_id).await?;
+    if !reauthenticate(&state, &user, request.proof).await? {
+        return Ok(recaptcha_required());
+    }
+    if let Some(method) = request.method {
+        ensure_set_up(&state, &user_id, method).await?;
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_two_fa_method(&user_id, request.method)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let response = Json(TwoFAResponse {
+        message: "2FA method updated".to_string(),
+    });
+
+    Ok((StatusCode::OK, response).into_response())
+}
+
+// Method the user proves the second factor with when logging in
+#[tracing::instrument(name = "Get 2FA Method", skip_all)]
+pub(crate) async fn two_fa_method(
+    state: &AppState,
+    user: &User,
+) -> Result<TwoFAMethod, AuthAPIError> {
+    // The chosen method, unless what it needs was removed since
+    if let Some(method) = user.two_fa_method {
+        if is_set_up(state, &user.id, method).await? {
+            return Ok(method);
+        }
+    }
+
+    // Passkeys are preferred over an authenticator app, which is preferred over email
+    for method in [TwoFAMethod::Passkey, TwoFAMethod::Totp] {
+        if is_set_up(state, &user.id, method).await? {
+            return Ok(method);
+        }
+    }
+    Ok(TwoFAMethod::Email)
+}
+
+// Make the user prove who they are again with their password or a code of their
+// authenticator app. Users whose only second factor is a passkey use their password.
+// Guessing with a stolen session is gated like guessing the password on login, and the
+// failures count towards the same limit. As with `check_recaptcha`, false asks the caller
+// for a reCAPTCHA token.
+async fn reauthenticate(
+    state: &AppState,
+    user: &User,
+    proof: Reauthentication,
+) -> Result<bool, AuthAPIError> {
+    if !check_recaptcha(state, &user.email, proof.recaptcha_token).await? {
+        return Ok(false);
+    }
+
+    let result = match (proof.password, proof.code) {
+        (Some(password), _) => {
+            let password = Password::parse(password).map_err(|_| AuthAPIError::InvalidInput)?;
+            state
+                .user_store
+                .read()
+                .await
+                .validate_user(&user.email, &password)
+                .await
+                .map_err(|e| match e {
+                    UserStoreError::InvalidCredentials => AuthAPIError::IncorrectCredentials,
+                    e => AuthAPIError::UnexpectedError(e.into()),
+                })
+        }
+        (None, Some(code)) => {
+            let code = TwoFACode::parse(code).map_err(|_| AuthAPIError::InvalidInput)?;
+            match confirmed_totp_secret(state, &user.id).await? {
+                Some(secret) => verify_totp_code(state, &user.id, &secret, &code).await,
+                None => Err(AuthAPIError::IncorrectCredentials),
+            }
+        }
+        (None, None) => return Err(AuthAPIError::InvalidInput),
+    };
+    let valid = match result {
+        Ok(()) => true,
+        Err(AuthAPIError::IncorrectCredentials) => false,
+        Err(e) => return Err(e),
+    };
+
+    let attempt = LoginAttempt::new(user.email.clone(), valid);
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(attempt)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    match valid {
+        true => Ok(true),
+        false => Err(AuthAPIError::IncorrectCredentials),
+    }
+}
+
+fn recaptcha_required() -> Response {
+    (
+        StatusCode::PRECONDITION_REQUIRED,
+        Json(LoginResponse::RecaptchaRequired),
+    )
+        .into_response()
+}
+
+async fn is_set_up(
+    state: &AppState,
+    user_id: &UserId,
+    method: TwoFAMethod,
+) -> Result<bool, AuthAPIError> {
+    match method {
+        TwoFAMethod::Email => Ok(true),
+        TwoFAMethod::Totp => Ok(confirmed_totp_secret(state, user_id).await?.is_some()),
+        TwoFAMethod::Passkey => Ok(!passkey_credentials(state, user_id).await?.is_empty()),
+    }
+}
+
+async fn ensure_set_up(
+    state: &AppState,
+    user_id: &UserId,
+    method: TwoFAMethod,
+) -> Result<(), AuthAPIError> {
+    match is_set_up(state, user_id, method).await? {
+        true => Ok(()),
+        false => Err(AuthAPIError::TwoFAMethodNotSetUp),
+    }
+}
+
+async fn get_user(state: &AppState, user_id: &UserId) -> Result<User, AuthAPIError> {
+    state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })
+}
+
+#[derive(Deserialize)]
+pub struct EnableTwoFARequest {
+    #[serde(default)]
+    pub method: Option<TwoFAMethod>,
+}
+
+// How the user proves who they are again before weakening their 2FA
+#[derive(Deserialize)]
+pub struct Reauthentication {
+    #[serde(default)]
+    pub password: Option<Secret<String>>,
+    // Code of the authenticator app of the user
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(rename = "recaptchaToken", default)]
+    pub recaptcha_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTwoFARequest {
+    #[serde(flatten)]
+    pub proof: Reauthentication,
+}
+
+#[derive(Deserialize)]
+pub struct SetTwoFAMethodRequest {
+    pub method: Option<TwoFAMethod>,
+    #[serde(flatten)]
+    pub proof: Reauthentication,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EnableTwoFAResponse {
+    pub message: String,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TwoFAResponse {
+    pub message: String,
+}
@@ -1,25 +1,44 @@
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        AuthAPIError, Email, LoginAttempt, LoginAttemptId, LoginAttemptStore, RecoveryCode,
+        TwoFACode, TwoFACodeHash, TwoFACodeStore, TwoFAMethod, User, UserStoreError,
+    },
+    utils::{client_info::ClientInfo, two_fa_code::verify_code},
 };
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
-use secrecy::Secret;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 
+use super::{
+    confirmed_totp_secret, start_session, trust_device, two_fa_method, use_recovery_code,
+    verify_totp_code,
+};
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Verify2FARequest {
     pub email: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    // Either a 2FA code or one of the recovery codes of the user
     #[serde(rename = "2FACode")]
     pub two_fa_code: String,
+    // Trust this browser, so logins from it skip 2FA for a while
+    #[serde(rename = "rememberDevice", default)]
+    pub remember_device: bool,
+}
+
+// What the user proves the second factor with
+enum SecondFactor {
+    Code(TwoFACode),
+    RecoveryCode(RecoveryCode),
 }
 
 #[tracing::instrument(name = "Verify 2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
+    client_info: ClientInfo,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
@@ -33,37 +52,156 @@ pub async fn verify_2fa(
         Err(_) => return (jar, Err(AuthAPIError::InvalidInput)),
     };
 
-    let two_fa_code = match TwoFACode::parse(request.two_fa_code) {
-        Ok(code) => code,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidInput)),
+    // The two kinds of codes have different lengths, so a code is never both
+    let second_factor = match TwoFACode::parse(request.two_fa_code.clone()) {
+        Ok(code) => SecondFactor::Code(code),
+        Err(_) => match RecoveryCode::parse(Secret::new(request.two_fa_code)) {
+            Ok(code) => SecondFactor::RecoveryCode(code),
+            Err(_) => return (jar, Err(AuthAPIError::InvalidInput)),
+        },
     };
 
     let mut two_fa_code_store = state.two_fa_code_store.write().await;
 
-    // Call `two_fa_code_store.get_code`. If the call fails
-    // return a `AuthAPIError::IncorrectCredentials`.
-    let code_tuple = match two_fa_code_store.get_code(&email).await {
-        Ok(tuple) => tuple,
+    // Codes are kept per login attempt, so an unknown or finished attempt has none
+    let sent_code_hash = match two_fa_code_store
+        .get_code_hash(&email, &login_attempt_id)
+        .await
+    {
+        Ok(code_hash) => code_hash,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    // Validate that the `login_attempt_id` and `two_fa_code`
-    // in the request body matches values in the `code_tuple`.
-    // If not, return a `AuthAPIError::IncorrectCredentials`
-    if code_tuple.0 != login_attempt_id || code_tuple.1 != two_fa_code {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    };
+
+    let verified = match &second_factor {
+        // Recovery codes stand in for whichever second factor the user has
+        SecondFactor::RecoveryCode(code) => use_recovery_code(&state, &user.id, &email, code).await,
+        SecondFactor::Code(code) => {
+            verify_two_fa_code(&state, &user, &login_attempt_id, &sent_code_hash, code).await
+        }
+    };
+    if let Err(e) = verified {
+        if let AuthAPIError::IncorrectCredentials = e {
+            if let Err(e) =
+                record_failed_attempt(&state, &mut *two_fa_code_store, &email, &login_attempt_id)
+                    .await
+            {
+                return (jar, Err(e));
+            }
+        }
+        return (jar, Err(e));
     }
 
-    // Remove the used code from the store
-    if let Err(e) = two_fa_code_store.remove_code(&email).await {
+    // Remove the used code from the store, and the wrong codes of the email with it
+    if let Err(e) = two_fa_code_store
+        .remove_code(&email, &login_attempt_id)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+    if let Err(e) = two_fa_code_store.reset_failed_email_attempts(&email).await {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    // Generate auth cookie for successful 2FA verification
-    let auth_cookie = match generate_auth_cookie(&email, &state.settings.auth) {
-        Ok(cookie) => cookie,
-        Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e.into()))),
+    // The login is complete, which clears the failed attempts of the email
+    let attempt = LoginAttempt::new(email, true);
+    if let Err(e) = state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(attempt)
+        .await
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
+    }
+
+    // Start a new session with its auth and refresh cookies
+    let (auth_cookie, refresh_cookie) =
+        match start_session(&state, &user.id, true, client_info.clone()).await {
+            Ok(cookies) => cookies,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        };
+    let jar = jar.add(auth_cookie).add(refresh_cookie);
+
+    let jar = match request.remember_device {
+        true => match trust_device(&state, &user.id, client_info).await {
+            Ok(trusted_device_cookie) => jar.add(trusted_device_cookie),
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        },
+        false => jar,
     };
 
-    (jar.add(auth_cookie), Ok(StatusCode::OK.into_response()))
+    (jar, Ok(StatusCode::OK.into_response()))
+}
+
+// Count a wrong code against its login attempt and against the wrong codes of the email.
+// Once either has seen too many the code is removed, so guessing cannot go on until it
+// expires and the user logs in again. Only wrong codes count here, so failed password
+// logins cannot cancel the login of the owner. The wrong code also counts as a failed
+// login, towards requiring reCAPTCHA.
+async fn record_failed_attempt(
+    state: &AppState,
+    two_fa_code_store: &mut (dyn TwoFACodeStore + Send + Sync),
+    email: &Email,
+    login_attempt_id: &LoginAttemptId,
+) -> Result<(), AuthAPIError> {
+    let login_attempt_failures = two_fa_code_store
+        .record_failed_attempt(email, login_attempt_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let email_failures = two_fa_code_store
+        .record_failed_email_attempt(email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state
+        .login_attempt_store
+        .write()
+        .await
+        .record_attempt(LoginAttempt::new(email.clone(), false))
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let max_attempts = state.settings.auth.max_2fa_attempts;
+    if login_attempt_failures >= max_attempts || email_failures >= max_attempts {
+        tracing::warn!("too many failed 2FA attempts, cancelling login attempt");
+        two_fa_code_store
+            .remove_code(email, login_attempt_id)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
+
+    Ok(())
+}
+
+// Check a 2FA code against the second factor of the user
+async fn verify_two_fa_code(
+    state: &AppState,
+    user: &User,
+    login_attempt_id: &LoginAttemptId,
+    sent_code_hash: &TwoFACodeHash,
+    code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    match two_fa_method(state, user).await? {
+        // Users with a passkey prove the second factor with it, so no code is accepted.
+        // The code stored by `login` was never sent and must not be guessable instead.
+        TwoFAMethod::Passkey => Err(AuthAPIError::IncorrectCredentials),
+        TwoFAMethod::Totp => match confirmed_totp_secret(state, &user.id).await? {
+            Some(secret) => verify_totp_code(state, &user.id, &secret, code).await,
+            None => Err(AuthAPIError::IncorrectCredentials),
+        },
+        TwoFAMethod::Email => {
+            let key = state.settings.auth.two_fa_code_key.expose_secret();
+            match verify_code(key, sent_code_hash, login_attempt_id, code) {
+                true => Ok(()),
+                false => Err(AuthAPIError::IncorrectCredentials),
+            }
+        }
+    }
 }
@@ -0,0 +1,129 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use color_eyre::eyre::Result;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, EmailToken, EmailTokenPurpose, EmailTokenStoreError, RecaptchaToken,
+        UserStoreError,
+    },
+    utils::email_templates::{email_link, EmailTemplate},
+};
+
+#[tracing::instrument(name = "Verify Email", skip_all)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = EmailToken::parse(request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let email = state
+        .email_token_store
+        .write()
+        .await
+        .consume_token(&token, EmailTokenPurpose::VerifyEmail)
+        .await
+        .map_err(|e| match e {
+            EmailTokenStoreError::TokenNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // The account may have been deleted since the link was sent
+    state
+        .user_store
+        .write()
+        .await
+        .mark_email_verified(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let response = Json(VerifyEmailResponse {
+        message: "Email verified successfully!".to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Resend Verification Email", skip_all)]
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let recaptcha_token = RecaptchaToken::new(request.recaptcha_token)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    state
+        .recaptcha_service
+        .verify_token(&recaptcha_token, None)
+        .await
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let user = state.user_store.read().await.get_user(&email).await;
+
+    // The response is the same whether or not an email was sent, so it does not reveal
+    // which addresses have an account
+    match user {
+        Ok(user) if !user.email_verified => send_verification_email(&state, &email)
+            .await
+            .map_err(AuthAPIError::UnexpectedError)?,
+        Ok(_) | Err(UserStoreError::UserNotFound) => (),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let response = Json(VerifyEmailResponse {
+        message:
+            "If the account exists and is not verified yet, a verification email has been sent"
+                .to_string(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+// Issue a new verification token for the user and email them the link consuming it
+#[tracing::instrument(name = "Send Verification Email", skip_all)]
+pub(crate) async fn send_verification_email(state: &AppState, email: &Email) -> Result<()> {
+    let token = EmailToken::default();
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(
+            token.clone(),
+            EmailTokenPurpose::VerifyEmail,
+            email.clone(),
+            state.settings.auth.email_verification_ttl_seconds,
+        )
+        .await?;
+
+    let link = email_link(&state.settings.email, "verify_email_token", &token);
+    let message = EmailTemplate::VerifyEmail { link: &link }.render(&state.settings.email)?;
+
+    state
+        .email_client
+        .send_multipart_email(email, &message)
+        .await
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationEmailRequest {
+    pub email: Secret<String>,
+    #[serde(rename = "recaptchaToken")]
+    pub recaptcha_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
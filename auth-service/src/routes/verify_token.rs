@@ -20,6 +20,8 @@ pub async fn verify_token(
     match validate_token(
         &request.token,
         &app_state.banned_token_store,
+        &app_state.user_store,
+        &app_state.key_ring,
         &app_state.settings.auth,
     )
     .await
@@ -0,0 +1,492 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::{authenticate, start_session};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, CredentialId, LoginAttemptStore, PasskeyCredential, PasskeyStoreError,
+        UserId, UserStoreError, WebauthnCeremony, WebauthnChallenge, WebauthnChallengeStoreError,
+    },
+    utils::{
+        client_info::ClientInfo,
+        webauthn::{self, CeremonyType, RelyingParty, ES256},
+    },
+};
+
+// Transports defined by WebAuthn, which clients use to find the authenticator
+const KNOWN_TRANSPORTS: [&str; 6] = ["ble", "hybrid", "internal", "nfc", "smart-card", "usb"];
+
+// Start registering a passkey for the logged in user
+#[tracing::instrument(name = "WebAuthn Registration Options", skip_all)]
+pub async fn webauthn_register_options(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+    let credentials = passkey_credentials(&state, &user_id).await?;
+
+    let challenge = WebauthnChallenge::default();
+    add_challenge(
+        &state,
+        challenge.clone(),
+        WebauthnCeremony::Registration { user_id },
+    )
+    .await?;
+
+    let auth_config = &state.settings.auth;
+    let email = user.email.as_ref().expose_secret();
+    let response = Json(PasskeyCreationOptions {
+        challenge: challenge.as_ref().to_owned(),
+        rp: RelyingPartyEntity {
+            id: auth_config.webauthn_rp_id.clone(),
+            name: state.settings.email.product_name.clone(),
+        },
+        // The user handle is stored on the authenticator, so it must not be personal data
+        user: UserEntity {
+            id: URL_SAFE_NO_PAD.encode(user_id.as_ref().as_bytes()),
+            name: email.to_owned(),
+            display_name: email.to_owned(),
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            credential_type: "public-key".to_owned(),
+            alg: ES256,
+        }],
+        timeout: auth_config.webauthn_challenge_ttl_seconds * 1000,
+        // Registering an authenticator twice would not add a factor
+        exclude_credentials: credentials.iter().map(CredentialDescriptor::from).collect(),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred".to_owned(),
+            user_verification: "preferred".to_owned(),
+        },
+        attestation: "none".to_owned(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "WebAuthn Registration", skip_all)]
+pub async fn webauthn_register_verify(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(request): Json<RegistrationCredential>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (_, user_id) = authenticate(&jar, &state).await?;
+
+    let (credential_id, client_data_json, attestation_object) = match (
+        decode(&request.id),
+        decode(&request.response.client_data_json),
+        decode(&request.response.attestation_object),
+    ) {
+        (Ok(id), Ok(client_data_json), Ok(attestation_object)) => {
+            (id, client_data_json, attestation_object)
+        }
+        _ => return Err(AuthAPIError::InvalidInput),
+    };
+
+    let rp = relying_party(&state);
+    let challenge = webauthn::client_data_challenge(&rp, &client_data_json, CeremonyType::Create)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    match take_challenge(&state, &challenge).await? {
+        WebauthnCeremony::Registration { user_id: expected } if expected == user_id => {}
+        _ => return Err(AuthAPIError::IncorrectCredentials),
+    }
+
+    let mut credential = webauthn::verify_registration(&rp, &attestation_object, false)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    if credential.id.as_ref() != credential_id {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+    credential.transports = request
+        .response
+        .transports
+        .into_iter()
+        .filter(|transport| KNOWN_TRANSPORTS.contains(&transport.as_str()))
+        .collect();
+
+    state
+        .passkey_store
+        .write()
+        .await
+        .add_credential(&user_id, credential)
+        .await
+        .map_err(|e| match e {
+            PasskeyStoreError::CredentialAlreadyExists => AuthAPIError::PasskeyAlreadyRegistered,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let response = Json(PasskeyRegistrationResponse {
+        message: "Passkey registered".to_string(),
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+// Start a passwordless login. Passkeys are discoverable, so the authenticator offers the
+// user their credentials and nothing reveals which accounts exist.
+#[tracing::instrument(name = "WebAuthn Authentication Options", skip_all)]
+pub async fn webauthn_authenticate_options(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let options = passkey_request_options(&state, WebauthnCeremony::Passwordless, &[]).await?;
+    Ok((StatusCode::OK, Json(options)))
+}
+
+// Log in with a passkey, either passwordless or as the second factor of a login
+#[tracing::instrument(name = "WebAuthn Authentication", skip_all)]
+pub async fn webauthn_authenticate_verify(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<AuthenticationCredential>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user_id = match verify_assertion(&state, request).await {
+        Ok(user_id) => user_id,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    // Passkeys are a second factor of their own, as passwordless logins require the
+    // authenticator to verify the user
+    let (auth_cookie, refresh_cookie) =
+        match start_session(&state, &user_id, true, client_info).await {
+            Ok(cookies) => cookies,
+            Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))),
+        };
+
+    (
+        jar.add(auth_cookie).add(refresh_cookie),
+        Ok(StatusCode::OK.into_response()),
+    )
+}
+
+// Check an assertion answers a pending challenge and return the user it logs in
+async fn verify_assertion(
+    state: &AppState,
+    request: AuthenticationCredential,
+) -> Result<UserId, AuthAPIError> {
+    let response = &request.response;
+    let (credential_id, client_data_json, authenticator_data, signature) = match (
+        decode(&request.id),
+        decode(&response.client_data_json),
+        decode(&response.authenticator_data),
+        decode(&response.signature),
+    ) {
+        (Ok(id), Ok(client_data_json), Ok(authenticator_data), Ok(signature)) => {
+            (id, client_data_json, authenticator_data, signature)
+        }
+        _ => return Err(AuthAPIError::InvalidInput),
+    };
+    let credential_id =
+        CredentialId::parse(credential_id).map_err(|_| AuthAPIError::InvalidInput)?;
+
+    let rp = relying_party(state);
+    let challenge = webauthn::client_data_challenge(&rp, &client_data_json, CeremonyType::Get)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    let ceremony = take_challenge(state, &challenge).await?;
+
+    let (user_id, credential) = state
+        .passkey_store
+        .read()
+        .await
+        .get_credential(&credential_id)
+        .await
+        .map_err(|e| match e {
+            PasskeyStoreError::CredentialNotFound => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    // A second factor has to come from a passkey of the user who entered the password
+    let require_user_verification = match &ceremony {
+        WebauthnCeremony::SecondFactor {
+            user_id: expected, ..
+        } if *expected == user_id => false,
+        WebauthnCeremony::Passwordless => true,
+        _ => return Err(AuthAPIError::IncorrectCredentials),
+    };
+    if let Some(user_handle) = &response.user_handle {
+        if decode(user_handle).ok().as_deref() != Some(user_id.as_ref().as_bytes()) {
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+    }
+
+    let sign_count = webauthn::verify_assertion(
+        &rp,
+        &credential,
+        &authenticator_data,
+        &client_data_json,
+        &signature,
+        require_user_verification,
+    )
+    .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    state
+        .passkey_store
+        .write()
+        .await
+        .update_sign_count(&credential_id, sign_count)
+        .await
+        .map_err(|e| match e {
+            PasskeyStoreError::SignCountRegressed => {
+                tracing::warn!("Rejected passkey assertion of user {}: signature counter did not increase, the authenticator may be cloned", user_id);
+                AuthAPIError::IncorrectCredentials
+            }
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user_by_id(&user_id)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    match ceremony {
+        // The login attempt ends with its second factor, like in `verify_2fa`
+        WebauthnCeremony::SecondFactor {
+            login_attempt_id, ..
+        } => {
+            let mut two_fa_code_store = state.two_fa_code_store.write().await;
+            if two_fa_code_store
+                .get_code_hash(&user.email, &login_attempt_id)
+                .await
+                .is_err()
+            {
+                return Err(AuthAPIError::IncorrectCredentials);
+            }
+            two_fa_code_store
+                .remove_code(&user.email, &login_attempt_id)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+            state
+                .login_attempt_store
+                .write()
+                .await
+                .reset_attempts(&user.email)
+                .await
+                .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        }
+        // Passwordless logins skip `login`, which checks this otherwise
+        _ => {
+            if state.settings.auth.require_email_verification && !user.email_verified {
+                return Err(AuthAPIError::EmailNotVerified);
+            }
+        }
+    }
+
+    Ok(user_id)
+}
+
+// Passkeys of the user, with which they prove the second factor if they have any
+#[tracing::instrument(name = "Get Passkey Credentials", skip_all)]
+pub(crate) async fn passkey_credentials(
+    state: &AppState,
+    user_id: &UserId,
+) -> Result<Vec<PasskeyCredential>, AuthAPIError> {
+    state
+        .passkey_store
+        .read()
+        .await
+        .get_credentials(user_id)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+// Issue the challenge of an authentication ceremony, limited to the given credentials
+// unless there are none
+#[tracing::instrument(name = "Passkey Request Options", skip_all)]
+pub(crate) async fn passkey_request_options(
+    state: &AppState,
+    ceremony: WebauthnCeremony,
+    credentials: &[PasskeyCredential],
+) -> Result<PasskeyRequestOptions, AuthAPIError> {
+    let user_verification = match ceremony {
+        WebauthnCeremony::Passwordless => "required",
+        _ => "preferred",
+    };
+
+    let challenge = WebauthnChallenge::default();
+    add_challenge(state, challenge.clone(), ceremony).await?;
+
+    let auth_config = &state.settings.auth;
+    Ok(PasskeyRequestOptions {
+        challenge: challenge.as_ref().to_owned(),
+        timeout: auth_config.webauthn_challenge_ttl_seconds * 1000,
+        rp_id: auth_config.webauthn_rp_id.clone(),
+        allow_credentials: credentials.iter().map(CredentialDescriptor::from).collect(),
+        user_verification: user_verification.to_owned(),
+    })
+}
+
+async fn add_challenge(
+    state: &AppState,
+    challenge: WebauthnChallenge,
+    ceremony: WebauthnCeremony,
+) -> Result<(), AuthAPIError> {
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(
+            challenge,
+            ceremony,
+            state.settings.auth.webauthn_challenge_ttl_seconds,
+        )
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+async fn take_challenge(
+    state: &AppState,
+    challenge: &WebauthnChallenge,
+) -> Result<WebauthnCeremony, AuthAPIError> {
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .take_challenge(challenge)
+        .await
+        .map_err(|e| match e {
+            WebauthnChallengeStoreError::ChallengeNotFound => AuthAPIError::IncorrectCredentials,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })
+}
+
+fn relying_party(state: &AppState) -> RelyingParty<'_> {
+    RelyingParty {
+        id: &state.settings.auth.webauthn_rp_id,
+        origin: &state.settings.auth.webauthn_origin,
+    }
+}
+
+// Binary fields of WebAuthn JSON are base64url encoded
+fn decode(value: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    URL_SAFE_NO_PAD.decode(value)
+}
+
+// Options for `navigator.credentials.create`, in the JSON form of WebAuthn Level 3
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: String,
+}
+
+// Options for `navigator.credentials.get`, in the JSON form of WebAuthn Level 3
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    pub challenge: String,
+    pub timeout: u64,
+    pub rp_id: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub id: String,
+    pub transports: Vec<String>,
+}
+
+impl From<&PasskeyCredential> for CredentialDescriptor {
+    fn from(credential: &PasskeyCredential) -> Self {
+        Self {
+            credential_type: "public-key".to_owned(),
+            id: credential.id.to_base64(),
+            transports: credential.transports.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub user_verification: String,
+}
+
+// Result of `navigator.credentials.create`, as serialized by `PublicKeyCredential.toJSON`
+#[derive(Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+// Result of `navigator.credentials.get`, as serialized by `PublicKeyCredential.toJSON`
+#[derive(Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PasskeyRegistrationResponse {
+    pub message: String,
+}
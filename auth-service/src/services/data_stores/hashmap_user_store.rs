@@ -1,10 +1,22 @@
 use std::collections::HashMap;
 
-use crate::domain::{user::User, Email, Password, UserStore, UserStoreError};
+use crate::domain::{user::User, Email, Password, TwoFAMethod, UserId, UserStore, UserStoreError};
 
 #[derive(Default)]
 pub struct HashmapUserStore {
     users: HashMap<Email, User>,
+    session_epochs: HashMap<UserId, i64>,
+    // Mirrors the Postgres sequence, so a re-created user never reuses an epoch
+    last_session_epoch: i64,
+}
+
+impl HashmapUserStore {
+    fn find_user_mut(&mut self, user_id: &UserId) -> Result<&mut User, UserStoreError> {
+        self.users
+            .values_mut()
+            .find(|user| user.id == *user_id)
+            .ok_or(UserStoreError::UserNotFound)
+    }
 }
 
 #[async_trait::async_trait]
@@ -14,6 +26,8 @@ impl UserStore for HashmapUserStore {
             return Err(UserStoreError::UserAlreadyExists);
         }
 
+        self.last_session_epoch += 1;
+        self.session_epochs.insert(user.id, self.last_session_epoch);
         self.users.insert(user.email.clone(), user);
         Ok(())
     }
@@ -25,6 +39,14 @@ impl UserStore for HashmapUserStore {
             .ok_or(UserStoreError::UserNotFound)
     }
 
+    async fn get_user_by_id(&self, user_id: &UserId) -> Result<User, UserStoreError> {
+        self.users
+            .values()
+            .find(|user| user.id == *user_id)
+            .cloned()
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
     async fn validate_user(
         &self,
         email: &Email,
@@ -46,12 +68,96 @@ impl UserStore for HashmapUserStore {
         self.validate_user(email, password).await?;
 
         // Remove the user from storage
-        self.users
+        let user = self
+            .users
             .remove(email)
             .ok_or(UserStoreError::UserNotFound)?;
+        self.session_epochs.remove(&user.id);
 
         Ok(())
     }
+
+    async fn get_session_epoch(&self, user_id: &UserId) -> Result<i64, UserStoreError> {
+        self.session_epochs
+            .get(user_id)
+            .copied()
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn increment_session_epoch(&mut self, user_id: &UserId) -> Result<i64, UserStoreError> {
+        let session_epoch = self
+            .session_epochs
+            .get_mut(user_id)
+            .ok_or(UserStoreError::UserNotFound)?;
+        self.last_session_epoch += 1;
+        *session_epoch = self.last_session_epoch;
+        Ok(*session_epoch)
+    }
+
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let user = self
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.email_verified = true;
+        Ok(())
+    }
+
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        self.find_user_mut(user_id)?.password = password;
+        Ok(())
+    }
+
+    async fn update_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError> {
+        if self.users.contains_key(new_email) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+
+        let email = self.find_user_mut(user_id)?.email.clone();
+        let mut user = self
+            .users
+            .remove(&email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.email = new_email.clone();
+        user.email_verified = true;
+        self.users.insert(new_email.clone(), user);
+        Ok(())
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        user_id: &UserId,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        self.find_user_mut(user_id)?.requires_2fa = requires_2fa;
+        Ok(())
+    }
+
+    async fn set_two_fa_method(
+        &mut self,
+        user_id: &UserId,
+        method: Option<TwoFAMethod>,
+    ) -> Result<(), UserStoreError> {
+        self.find_user_mut(user_id)?.two_fa_method = method;
+        Ok(())
+    }
+
+    async fn set_magic_link_enabled(
+        &mut self,
+        user_id: &UserId,
+        enabled: bool,
+    ) -> Result<(), UserStoreError> {
+        self.find_user_mut(user_id)?.magic_link_enabled = enabled;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -225,4 +331,233 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), UserStoreError::UserNotFound);
     }
+
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("by_id@example.com", "Password123!").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        assert_eq!(user_store.get_user_by_id(&user.id).await.unwrap(), user);
+        assert_eq!(
+            user_store
+                .get_user_by_id(&UserId::default())
+                .await
+                .unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_increment_session_epoch() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("test@example.com", "Password123!").await;
+        let user_id = user.id;
+        user_store.add_user(user).await.unwrap();
+
+        let initial_epoch = user_store.get_session_epoch(&user_id).await.unwrap();
+        let new_epoch = user_store.increment_session_epoch(&user_id).await.unwrap();
+        assert!(new_epoch > initial_epoch);
+        assert_eq!(
+            user_store.get_session_epoch(&user_id).await.unwrap(),
+            new_epoch
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recreated_user_gets_new_session_epoch() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("test@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        let password = user.password.clone();
+
+        user_store.add_user(user.clone()).await.unwrap();
+        let initial_epoch = user_store.get_session_epoch(&user_id).await.unwrap();
+
+        user_store.delete_user(&email, &password).await.unwrap();
+        assert_eq!(
+            user_store.get_session_epoch(&user_id).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+
+        user_store.add_user(user).await.unwrap();
+        assert_ne!(
+            user_store.get_session_epoch(&user_id).await.unwrap(),
+            initial_epoch
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("verify@example.com", "Password123!").await;
+        let email = user.email.clone();
+        user_store.add_user(user).await.unwrap();
+        assert!(!user_store.get_user(&email).await.unwrap().email_verified);
+
+        user_store.mark_email_verified(&email).await.unwrap();
+        assert!(user_store.get_user(&email).await.unwrap().email_verified);
+
+        let unknown = Email::parse(Secret::new("unknown@example.com".to_string())).unwrap();
+        assert_eq!(
+            user_store.mark_email_verified(&unknown).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("update@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        let old_password = user.password.clone();
+        user_store.add_user(user).await.unwrap();
+
+        let new_password = Password::parse(Secret::new("NewPassword456!".to_string())).unwrap();
+        user_store
+            .update_password(&user_id, new_password.clone())
+            .await
+            .unwrap();
+
+        assert!(user_store
+            .validate_user(&email, &new_password)
+            .await
+            .is_ok());
+        assert_eq!(
+            user_store
+                .validate_user(&email, &old_password)
+                .await
+                .unwrap_err(),
+            UserStoreError::InvalidCredentials
+        );
+
+        assert_eq!(
+            user_store
+                .update_password(&UserId::default(), new_password)
+                .await
+                .unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("old@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        let password = user.password.clone();
+        user_store.add_user(user).await.unwrap();
+        let session_epoch = user_store.get_session_epoch(&user_id).await.unwrap();
+
+        let new_email = Email::parse(Secret::new("new@example.com".to_string())).unwrap();
+        user_store.update_email(&user_id, &new_email).await.unwrap();
+
+        let user = user_store.get_user(&new_email).await.unwrap();
+        assert_eq!(user.id, user_id);
+        assert_eq!(user.email, new_email);
+        assert!(user.email_verified);
+        assert!(user_store
+            .validate_user(&new_email, &password)
+            .await
+            .is_ok());
+        assert_eq!(
+            user_store.get_session_epoch(&user_id).await.unwrap(),
+            session_epoch
+        );
+        assert_eq!(
+            user_store.get_user(&email).await.unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_email_to_taken_address_fails() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("first@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        user_store.add_user(user).await.unwrap();
+        let other = create_user("second@example.com", "Password123!").await;
+        let other_email = other.email.clone();
+        user_store.add_user(other).await.unwrap();
+
+        assert_eq!(
+            user_store
+                .update_email(&user_id, &other_email)
+                .await
+                .unwrap_err(),
+            UserStoreError::UserAlreadyExists
+        );
+        assert!(user_store.get_user(&email).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_two_fa_settings() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("two-fa@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        user_store.add_user(user).await.unwrap();
+
+        user_store.set_requires_2fa(&user_id, true).await.unwrap();
+        user_store
+            .set_two_fa_method(&user_id, Some(TwoFAMethod::Totp))
+            .await
+            .unwrap();
+        let user = user_store.get_user(&email).await.unwrap();
+        assert!(user.requires_2fa);
+        assert_eq!(user.two_fa_method, Some(TwoFAMethod::Totp));
+
+        user_store.set_requires_2fa(&user_id, false).await.unwrap();
+        user_store.set_two_fa_method(&user_id, None).await.unwrap();
+        let user = user_store.get_user(&email).await.unwrap();
+        assert!(!user.requires_2fa);
+        assert_eq!(user.two_fa_method, None);
+
+        assert_eq!(
+            user_store
+                .set_requires_2fa(&UserId::default(), true)
+                .await
+                .unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
+    #[tokio::test]
+    async fn test_set_magic_link_enabled() {
+        let mut user_store = HashmapUserStore::default();
+        let user = create_user("magic-link@example.com", "Password123!").await;
+        let email = user.email.clone();
+        let user_id = user.id;
+        user_store.add_user(user).await.unwrap();
+        assert!(
+            user_store
+                .get_user(&email)
+                .await
+                .unwrap()
+                .magic_link_enabled
+        );
+
+        user_store
+            .set_magic_link_enabled(&user_id, false)
+            .await
+            .unwrap();
+        assert!(
+            !user_store
+                .get_user(&email)
+                .await
+                .unwrap()
+                .magic_link_enabled
+        );
+
+        assert_eq!(
+            user_store
+                .set_magic_link_enabled(&UserId::default(), false)
+                .await
+                .unwrap_err(),
+            UserStoreError::UserNotFound
+        );
+    }
 }
@@ -1,11 +1,31 @@
 pub mod hashmap_login_attempt_store;
 pub mod hashmap_user_store;
+pub mod postgres_passkey_store;
+pub mod postgres_recovery_code_store;
+pub mod postgres_totp_store;
 pub mod postgres_user_store;
 pub mod redis_banned_token_store;
+pub mod redis_email_change_store;
+pub mod redis_email_token_store;
+pub mod redis_refresh_token_store;
+pub mod redis_session_store;
+pub mod redis_signing_key_store;
+pub mod redis_trusted_device_store;
 pub mod redis_two_fa_code_store;
+pub mod redis_webauthn_challenge_store;
 
 pub use hashmap_login_attempt_store::*;
 pub use hashmap_user_store::*;
+pub use postgres_passkey_store::*;
+pub use postgres_recovery_code_store::*;
+pub use postgres_totp_store::*;
 pub use postgres_user_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_email_change_store::*;
+pub use redis_email_token_store::*;
+pub use redis_refresh_token_store::*;
+pub use redis_session_store::*;
+pub use redis_signing_key_store::*;
+pub use redis_trusted_device_store::*;
 pub use redis_two_fa_code_store::*;
+pub use redis_webauthn_challenge_store::*;
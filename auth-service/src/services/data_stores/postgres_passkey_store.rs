@@ -0,0 +1,144 @@
+use sqlx::PgPool;
+
+use crate::domain::{
+    data_stores::{PasskeyStore, PasskeyStoreError},
+    CredentialId, PasskeyCredential, UserId,
+};
+
+pub struct PostgresPasskeyStore {
+    pool: PgPool,
+}
+
+impl PostgresPasskeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for PostgresPasskeyStore {
+    #[tracing::instrument(name = "Adding passkey credential to PostgreSQL", skip_all)]
+    async fn add_credential(
+        &mut self,
+        user_id: &UserId,
+        credential: PasskeyCredential,
+    ) -> Result<(), PasskeyStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO passkey_credentials (credential_id, user_id, public_key, sign_count, transports)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            credential.id.as_ref(),
+            user_id.as_ref(),
+            credential.public_key,
+            i64::from(credential.sign_count),
+            &credential.transports
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                PasskeyStoreError::CredentialAlreadyExists
+            }
+            e => PasskeyStoreError::UnexpectedError(e.into()),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(
+        name = "Retrieving passkey credentials of user from PostgreSQL",
+        skip_all
+    )]
+    async fn get_credentials(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<PasskeyCredential>, PasskeyStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT credential_id, public_key, sign_count, transports FROM passkey_credentials
+            WHERE user_id = $1 ORDER BY created_at
+            "#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(|row| {
+            to_credential(
+                row.credential_id,
+                row.public_key,
+                row.sign_count,
+                row.transports,
+            )
+        })
+        .collect()
+    }
+
+    #[tracing::instrument(name = "Retrieving passkey credential from PostgreSQL", skip_all)]
+    async fn get_credential(
+        &self,
+        credential_id: &CredentialId,
+    ) -> Result<(UserId, PasskeyCredential), PasskeyStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT credential_id, user_id, public_key, sign_count, transports
+            FROM passkey_credentials WHERE credential_id = $1
+            "#,
+            credential_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?
+        .ok_or(PasskeyStoreError::CredentialNotFound)?;
+
+        let credential = to_credential(
+            row.credential_id,
+            row.public_key,
+            row.sign_count,
+            row.transports,
+        )?;
+        Ok((UserId::from(row.user_id), credential))
+    }
+
+    #[tracing::instrument(name = "Updating passkey signature counter in PostgreSQL", skip_all)]
+    async fn update_sign_count(
+        &mut self,
+        credential_id: &CredentialId,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        // A single conditional update, so concurrent assertions cannot reuse a counter
+        let result = sqlx::query!(
+            r#"
+            UPDATE passkey_credentials SET sign_count = $2
+            WHERE credential_id = $1 AND (sign_count < $2 OR (sign_count = 0 AND $2 = 0))
+            "#,
+            credential_id.as_ref(),
+            i64::from(sign_count)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(PasskeyStoreError::SignCountRegressed),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn to_credential(
+    credential_id: Vec<u8>,
+    public_key: Vec<u8>,
+    sign_count: i64,
+    transports: Vec<String>,
+) -> Result<PasskeyCredential, PasskeyStoreError> {
+    Ok(PasskeyCredential {
+        id: CredentialId::parse(credential_id).map_err(PasskeyStoreError::UnexpectedError)?,
+        public_key,
+        sign_count: u32::try_from(sign_count)
+            .map_err(|e| PasskeyStoreError::UnexpectedError(e.into()))?,
+        transports,
+    })
+}
@@ -0,0 +1,108 @@
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::domain::{
+    data_stores::{RecoveryCodeStore, RecoveryCodeStoreError},
+    RecoveryCode, UserId,
+};
+
+pub struct PostgresRecoveryCodeStore {
+    pool: PgPool,
+}
+
+impl PostgresRecoveryCodeStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for PostgresRecoveryCodeStore {
+    #[tracing::instrument(name = "Replacing recovery codes in PostgreSQL", skip_all)]
+    async fn replace_codes(
+        &mut self,
+        user_id: &UserId,
+        codes: &[RecoveryCode],
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let code_hashes: Vec<String> = codes.iter().map(hash_code).collect();
+
+        // The old set is removed in the same transaction, so it never lives on beside the
+        // new one
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            "DELETE FROM recovery_codes WHERE user_id = $1",
+            user_id.as_ref()
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (user_id, code_hash)
+            SELECT $1, code_hash FROM UNNEST($2::TEXT[]) AS code_hash
+            ON CONFLICT DO NOTHING
+            "#,
+            user_id.as_ref(),
+            &code_hashes
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))
+    }
+
+    #[tracing::instrument(name = "Using recovery code in PostgreSQL", skip_all)]
+    async fn use_code(
+        &mut self,
+        user_id: &UserId,
+        code: &RecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        // Deleting the code is what uses it, so concurrent requests cannot both succeed
+        let result = sqlx::query!(
+            "DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2",
+            user_id.as_ref(),
+            hash_code(code)
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(RecoveryCodeStoreError::CodeNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Counting recovery codes in PostgreSQL", skip_all)]
+    async fn count_codes(&self, user_id: &UserId) -> Result<usize, RecoveryCodeStoreError> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM recovery_codes WHERE user_id = $1"#,
+            user_id.as_ref()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))?;
+
+        usize::try_from(count).map_err(|e| RecoveryCodeStoreError::UnexpectedError(e.into()))
+    }
+}
+
+// Codes are random enough that a fast hash keeps them from being read back out of the
+// database, like the hashed email tokens
+fn hash_code(code: &RecoveryCode) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(code.as_ref().expose_secret().as_bytes())
+    )
+}
@@ -0,0 +1,116 @@
+use sqlx::PgPool;
+
+use crate::{
+    domain::{
+        data_stores::{TotpEnrollment, TotpStore, TotpStoreError},
+        TotpSecret, UserId,
+    },
+    utils::secret_cipher::SecretCipher,
+};
+
+pub struct PostgresTotpStore {
+    pool: PgPool,
+    cipher: SecretCipher,
+}
+
+impl PostgresTotpStore {
+    pub fn new(pool: PgPool, cipher: SecretCipher) -> Self {
+        Self { pool, cipher }
+    }
+}
+
+#[async_trait::async_trait]
+impl TotpStore for PostgresTotpStore {
+    #[tracing::instrument(name = "Setting pending TOTP secret in PostgreSQL", skip_all)]
+    async fn set_pending_secret(
+        &mut self,
+        user_id: &UserId,
+        secret: TotpSecret,
+    ) -> Result<(), TotpStoreError> {
+        // Secrets are bound to their user, so they cannot be copied to another account
+        let encrypted_secret = self
+            .cipher
+            .encrypt(secret.as_ref(), user_id.as_ref().as_bytes())
+            .map_err(TotpStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO totp_secrets (user_id, encrypted_secret) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET encrypted_secret = EXCLUDED.encrypted_secret, last_used_step = NULL
+            WHERE NOT totp_secrets.confirmed
+            "#,
+            user_id.as_ref(),
+            encrypted_secret
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TotpStoreError::AlreadyConfirmed),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving TOTP secret from PostgreSQL", skip_all)]
+    async fn get_secret(&self, user_id: &UserId) -> Result<TotpEnrollment, TotpStoreError> {
+        let row = sqlx::query!(
+            "SELECT encrypted_secret, confirmed FROM totp_secrets WHERE user_id = $1",
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| TotpStoreError::UnexpectedError(e.into()))?
+        .ok_or(TotpStoreError::SecretNotFound)?;
+
+        let secret = self
+            .cipher
+            .decrypt(&row.encrypted_secret, user_id.as_ref().as_bytes())
+            .map_err(TotpStoreError::UnexpectedError)?;
+
+        Ok(TotpEnrollment {
+            secret: TotpSecret::from_bytes(secret),
+            confirmed: row.confirmed,
+        })
+    }
+
+    #[tracing::instrument(name = "Confirming TOTP secret in PostgreSQL", skip_all)]
+    async fn confirm_secret(&mut self, user_id: &UserId) -> Result<(), TotpStoreError> {
+        let result = sqlx::query!(
+            "UPDATE totp_secrets SET confirmed = TRUE WHERE user_id = $1",
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TotpStoreError::SecretNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Recording used TOTP time step in PostgreSQL", skip_all)]
+    async fn use_time_step(&mut self, user_id: &UserId, step: u64) -> Result<(), TotpStoreError> {
+        let step = i64::try_from(step).map_err(|e| TotpStoreError::UnexpectedError(e.into()))?;
+
+        // A single conditional update, so concurrent requests cannot both use a code
+        let result = sqlx::query!(
+            r#"
+            UPDATE totp_secrets SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id.as_ref(),
+            step
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| TotpStoreError::UnexpectedError(e.into()))?;
+
+        match result.rows_affected() {
+            0 => Err(TotpStoreError::CodeReused),
+            _ => Ok(()),
+        }
+    }
+}
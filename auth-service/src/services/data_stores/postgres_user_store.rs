@@ -6,7 +6,7 @@ use sqlx::PgPool;
 
 use crate::domain::{
     data_stores::{UserStore, UserStoreError},
-    Email, Password, User,
+    Email, Password, TwoFAMethod, User, UserId,
 };
 
 pub struct PostgresUserStore {
@@ -28,14 +28,23 @@ impl UserStore for PostgresUserStore {
             .map_err(UserStoreError::UnexpectedError)?;
 
         sqlx::query!(
-            "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, email, password_hash, requires_2fa, two_fa_method, email_verified, magic_link_enabled) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            user.id.as_ref(),
             user.email.as_ref().expose_secret(),
             password_hash.expose_secret(),
-            user.requires_2fa
+            user.requires_2fa,
+            user.two_fa_method.as_ref().map(AsRef::as_ref),
+            user.email_verified,
+            user.magic_link_enabled
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            e => UserStoreError::UnexpectedError(e.into()),
+        })?;
 
         Ok(())
     }
@@ -43,7 +52,7 @@ impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         sqlx::query!(
-            "SELECT email, password_hash, requires_2fa FROM users WHERE email = $1",
+            "SELECT user_id, email, password_hash, requires_2fa, two_fa_method, email_verified, magic_link_enabled FROM users WHERE lower(email) = lower($1)",
             email.as_ref().expose_secret()
         )
         .fetch_optional(&self.pool)
@@ -51,11 +60,50 @@ impl UserStore for PostgresUserStore {
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
         .map(|row| {
             Ok(User {
+                id: row.user_id.into(),
                 email: Email::parse(Secret::new(row.email))
                     .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
                 password: Password::parse(Secret::new(row.password_hash))
                     .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
                 requires_2fa: row.requires_2fa,
+                two_fa_method: row
+                    .two_fa_method
+                    .as_deref()
+                    .map(TwoFAMethod::parse)
+                    .transpose()
+                    .map_err(UserStoreError::UnexpectedError)?,
+                email_verified: row.email_verified,
+                magic_link_enabled: row.magic_link_enabled,
+            })
+        })
+        .ok_or(UserStoreError::UserNotFound)?
+    }
+
+    #[tracing::instrument(name = "Retrieving user by id from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, user_id: &UserId) -> Result<User, UserStoreError> {
+        sqlx::query!(
+            "SELECT user_id, email, password_hash, requires_2fa, two_fa_method, email_verified, magic_link_enabled FROM users WHERE user_id = $1",
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .map(|row| {
+            Ok(User {
+                id: row.user_id.into(),
+                email: Email::parse(Secret::new(row.email))
+                    .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
+                password: Password::parse(Secret::new(row.password_hash))
+                    .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
+                requires_2fa: row.requires_2fa,
+                two_fa_method: row
+                    .two_fa_method
+                    .as_deref()
+                    .map(TwoFAMethod::parse)
+                    .transpose()
+                    .map_err(UserStoreError::UnexpectedError)?,
+                email_verified: row.email_verified,
+                magic_link_enabled: row.magic_link_enabled,
             })
         })
         .ok_or(UserStoreError::UserNotFound)?
@@ -68,7 +116,7 @@ impl UserStore for PostgresUserStore {
         password: &Password,
     ) -> Result<(), UserStoreError> {
         let row = sqlx::query!(
-            "SELECT password_hash FROM users WHERE email = $1",
+            "SELECT password_hash FROM users WHERE lower(email) = lower($1)",
             email.as_ref().expose_secret()
         )
         .fetch_optional(&self.pool)
@@ -92,7 +140,47 @@ impl UserStore for PostgresUserStore {
         self.validate_user(email, password).await?;
 
         match sqlx::query!(
-            "DELETE FROM users WHERE email = $1",
+            "DELETE FROM users WHERE lower(email) = lower($1)",
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .rows_affected()
+        {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Retrieving session epoch from PostgreSQL", skip_all)]
+    async fn get_session_epoch(&self, user_id: &UserId) -> Result<i64, UserStoreError> {
+        sqlx::query_scalar!(
+            "SELECT session_epoch FROM users WHERE user_id = $1",
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    #[tracing::instrument(name = "Incrementing session epoch in PostgreSQL", skip_all)]
+    async fn increment_session_epoch(&mut self, user_id: &UserId) -> Result<i64, UserStoreError> {
+        sqlx::query_scalar!(
+            "UPDATE users SET session_epoch = nextval('session_epochs') WHERE user_id = $1 RETURNING session_epoch",
+            user_id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    #[tracing::instrument(name = "Marking email verified in PostgreSQL", skip_all)]
+    async fn mark_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match sqlx::query!(
+            "UPDATE users SET email_verified = TRUE WHERE lower(email) = lower($1)",
             email.as_ref().expose_secret()
         )
         .execute(&self.pool)
@@ -104,6 +192,117 @@ impl UserStore for PostgresUserStore {
             _ => Ok(()),
         }
     }
+
+    #[tracing::instrument(name = "Updating password in PostgreSQL", skip_all)]
+    async fn update_password(
+        &mut self,
+        user_id: &UserId,
+        password: Password,
+    ) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        match sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+            password_hash.expose_secret(),
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .rows_affected()
+        {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating user email in PostgreSQL", skip_all)]
+    async fn update_email(
+        &mut self,
+        user_id: &UserId,
+        new_email: &Email,
+    ) -> Result<(), UserStoreError> {
+        match sqlx::query!(
+            "UPDATE users SET email = $1, email_verified = TRUE WHERE user_id = $2",
+            new_email.as_ref().expose_secret(),
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(UserStoreError::UserNotFound),
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                Err(UserStoreError::UserAlreadyExists)
+            }
+            Err(e) => Err(UserStoreError::UnexpectedError(e.into())),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating 2FA requirement in PostgreSQL", skip_all)]
+    async fn set_requires_2fa(
+        &mut self,
+        user_id: &UserId,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        match sqlx::query!(
+            "UPDATE users SET requires_2fa = $1 WHERE user_id = $2",
+            requires_2fa,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .rows_affected()
+        {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating 2FA method in PostgreSQL", skip_all)]
+    async fn set_two_fa_method(
+        &mut self,
+        user_id: &UserId,
+        method: Option<TwoFAMethod>,
+    ) -> Result<(), UserStoreError> {
+        match sqlx::query!(
+            "UPDATE users SET two_fa_method = $1 WHERE user_id = $2",
+            method.as_ref().map(AsRef::as_ref),
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .rows_affected()
+        {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Updating magic link login in PostgreSQL", skip_all)]
+    async fn set_magic_link_enabled(
+        &mut self,
+        user_id: &UserId,
+        enabled: bool,
+    ) -> Result<(), UserStoreError> {
+        match sqlx::query!(
+            "UPDATE users SET magic_link_enabled = $1 WHERE user_id = $2",
+            enabled,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .rows_affected()
+        {
+            0 => Err(UserStoreError::UserNotFound),
+            _ => Ok(()),
+        }
+    }
 }
 
 // Helper function to verify if a given password matches an expected hash
@@ -116,7 +315,7 @@ async fn verify_password_hash(
     let result = tokio::task::spawn_blocking(move || {
         current_span.in_scope(|| {
             let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(&expected_password_hash.expose_secret())?;
+                PasswordHash::new(expected_password_hash.expose_secret())?;
 
             Argon2::default()
                 .verify_password(
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use chrono::Utc;
 use color_eyre::eyre::Context;
 use redis::AsyncCommands;
 use tokio::sync::RwLock;
@@ -9,7 +10,6 @@ use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
 pub struct RedisBannedTokenStore {
     pub conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
     pub key_prefix: Option<String>,
-    pub token_ttl: u64,
     pub key_prefix_base: String,
 }
 
@@ -17,13 +17,11 @@ impl RedisBannedTokenStore {
     #[tracing::instrument(name = "New Redis Banned Token Store with Config", skip_all)]
     pub fn new_with_config(
         conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
-        token_ttl: u64,
         key_prefix_base: String,
     ) -> Self {
         Self {
             conn,
             key_prefix: None,
-            token_ttl,
             key_prefix_base,
         }
     }
@@ -31,14 +29,12 @@ impl RedisBannedTokenStore {
     #[tracing::instrument(name = "New Redis Banned Token Store with Config and Prefix", skip_all)]
     pub fn new_with_config_and_prefix(
         conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
-        token_ttl: u64,
         key_prefix_base: String,
         prefix: String,
     ) -> Self {
         Self {
             conn,
             key_prefix: Some(prefix),
-            token_ttl,
             key_prefix_base,
         }
     }
@@ -47,14 +43,22 @@ impl RedisBannedTokenStore {
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "Store Banned Token", skip_all)]
-    async fn store_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        let key = self.get_key(&token);
-
-        let ttl: u64 = self
-            .token_ttl
-            .try_into()
-            .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64")
+    async fn store_token(
+        &mut self,
+        token_id: String,
+        expires_at: u64,
+    ) -> Result<(), BannedTokenStoreError> {
+        // Keep the ban exactly as long as the token could still be presented
+        let now = u64::try_from(Utc::now().timestamp())
+            .wrap_err("failed to cast current time to u64")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
+        let ttl = match expires_at.checked_sub(now) {
+            Some(ttl) if ttl > 0 => ttl,
+            // The token has already expired, so there is nothing left to ban
+            _ => return Ok(()),
+        };
+
+        let key = self.get_key(&token_id);
 
         let _: () = self
             .conn
@@ -68,8 +72,8 @@ impl BannedTokenStore for RedisBannedTokenStore {
     }
 
     #[tracing::instrument(name = "Check Banned Token", skip_all)]
-    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
-        let key = self.get_key(token);
+    async fn contains_token(&self, token_id: &str) -> Result<bool, BannedTokenStoreError> {
+        let key = self.get_key(token_id);
 
         let is_banned_bool: bool = self
             .conn
@@ -85,10 +89,10 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
 impl RedisBannedTokenStore {
     #[tracing::instrument(name = "Get Banned Token Key", skip_all)]
-    fn get_key(&self, token: &str) -> String {
+    fn get_key(&self, token_id: &str) -> String {
         match &self.key_prefix {
-            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, token),
-            None => format!("{}{}", self.key_prefix_base, token),
+            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, token_id),
+            None => format!("{}{}", self.key_prefix_base, token_id),
         }
     }
 }
@@ -111,18 +115,21 @@ mod tests {
         let conn = Arc::new(RwLock::new(conn));
         RedisBannedTokenStore::new_with_config_and_prefix(
             conn,
-            settings.redis.banned_token_ttl_seconds,
             settings.redis.banned_token_key_prefix,
             format!("test_{}:", test_prefix),
         )
     }
 
+    fn expires_in(seconds: i64) -> u64 {
+        (Utc::now().timestamp() + seconds) as u64
+    }
+
     #[tokio::test]
     async fn test_store_token_success() {
         let mut store = create_test_store("store_token_success").await;
         let token = "test_token_123".to_string();
 
-        let result = store.store_token(token.clone()).await;
+        let result = store.store_token(token.clone(), expires_in(600)).await;
         assert!(result.is_ok());
 
         let contains_result = store.contains_token(&token).await;
@@ -152,9 +159,18 @@ mod tests {
         let token2 = "token_2".to_string();
         let token3 = "token_3".to_string();
 
-        assert!(store.store_token(token1.clone()).await.is_ok());
-        assert!(store.store_token(token2.clone()).await.is_ok());
-        assert!(store.store_token(token3.clone()).await.is_ok());
+        assert!(store
+            .store_token(token1.clone(), expires_in(600))
+            .await
+            .is_ok());
+        assert!(store
+            .store_token(token2.clone(), expires_in(600))
+            .await
+            .is_ok());
+        assert!(store
+            .store_token(token3.clone(), expires_in(600))
+            .await
+            .is_ok());
 
         assert!(store.contains_token(&token1).await.unwrap());
         assert!(store.contains_token(&token2).await.unwrap());
@@ -178,8 +194,14 @@ mod tests {
         let mut store = create_test_store("store_duplicate_token").await;
         let token = "duplicate_token".to_string();
 
-        assert!(store.store_token(token.clone()).await.is_ok());
-        assert!(store.store_token(token.clone()).await.is_ok()); // Should not fail
+        assert!(store
+            .store_token(token.clone(), expires_in(600))
+            .await
+            .is_ok());
+        assert!(store
+            .store_token(token.clone(), expires_in(600))
+            .await
+            .is_ok()); // Should not fail
 
         assert!(store.contains_token(&token).await.unwrap());
 
@@ -194,7 +216,10 @@ mod tests {
         let mut store = create_test_store("empty_token").await;
         let empty_token = "".to_string();
 
-        assert!(store.store_token(empty_token.clone()).await.is_ok());
+        assert!(store
+            .store_token(empty_token.clone(), expires_in(600))
+            .await
+            .is_ok());
         assert!(store.contains_token(&empty_token).await.unwrap());
 
         // Clean up
@@ -208,7 +233,10 @@ mod tests {
         let mut store = create_test_store("special_characters_in_token").await;
         let special_token = "token_with_special!@#$%^&*()_+{}|:<>?[]\";".to_string();
 
-        assert!(store.store_token(special_token.clone()).await.is_ok());
+        assert!(store
+            .store_token(special_token.clone(), expires_in(600))
+            .await
+            .is_ok());
         assert!(store.contains_token(&special_token).await.unwrap());
 
         // Clean up
@@ -222,7 +250,10 @@ mod tests {
         let mut store = create_test_store("long_token").await;
         let long_token = "a".repeat(1000);
 
-        assert!(store.store_token(long_token.clone()).await.is_ok());
+        assert!(store
+            .store_token(long_token.clone(), expires_in(600))
+            .await
+            .is_ok());
         assert!(store.contains_token(&long_token).await.unwrap());
 
         // Clean up
@@ -230,4 +261,35 @@ mod tests {
         let mut conn = store.conn.write().await;
         let _: () = conn.del(&key).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_ban_expires_with_token() {
+        let mut store = create_test_store("ban_expires_with_token").await;
+        let token_id = "expiring_token".to_string();
+
+        store
+            .store_token(token_id.clone(), expires_in(300))
+            .await
+            .unwrap();
+
+        let key = store.get_key(&token_id);
+        let ttl: i64 = store.conn.write().await.ttl(&key).await.unwrap();
+        assert!((299..=300).contains(&ttl), "unexpected TTL: {}", ttl);
+
+        // Clean up
+        let mut conn = store.conn.write().await;
+        let _: () = conn.del(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_is_not_stored() {
+        let mut store = create_test_store("expired_token_is_not_stored").await;
+        let token_id = "expired_token".to_string();
+
+        assert!(store
+            .store_token(token_id.clone(), expires_in(-10))
+            .await
+            .is_ok());
+        assert!(!store.contains_token(&token_id).await.unwrap());
+    }
 }
@@ -0,0 +1,316 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, Context};
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{
+        EmailChange, EmailChangeStore, EmailChangeStoreError, EmailChangeTokens, EmailToken,
+    },
+    Email,
+};
+
+pub struct RedisEmailChangeStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    key_prefix_base: String,
+}
+
+impl RedisEmailChangeStore {
+    #[tracing::instrument(name = "New Redis Email Change Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(name = "New Redis Email Change Store with Config and Prefix", skip_all)]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailChangeStore for RedisEmailChangeStore {
+    #[tracing::instrument(name = "Add Email Change", skip_all)]
+    async fn add_change(
+        &mut self,
+        change: EmailChange,
+        ttl_seconds: u64,
+    ) -> Result<EmailChangeTokens, EmailChangeStoreError> {
+        let tokens = EmailChangeTokens {
+            confirm_token: EmailToken::default(),
+            cancel_token: EmailToken::default(),
+        };
+        let record = ChangeRecord {
+            new_email: change.new_email.as_ref().expose_secret().to_owned(),
+            confirm_token_hash: hash_token(&tokens.confirm_token),
+            cancel_token_hash: hash_token(&tokens.cancel_token),
+        };
+        let record = serde_json::to_string(&record)
+            .wrap_err("failed to serialize email change")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let old_email = change.old_email.as_ref().expose_secret();
+
+        // Replacing the record of the user is what invalidates the tokens of a previous change
+        let mut conn = self.conn.write().await;
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(self.get_change_key(&change.old_email), record, ttl_seconds)
+            .ignore()
+            .set_ex(
+                self.get_token_key(TokenKind::Confirm, &tokens.confirm_token),
+                old_email,
+                ttl_seconds,
+            )
+            .ignore()
+            .set_ex(
+                self.get_token_key(TokenKind::Cancel, &tokens.cancel_token),
+                old_email,
+                ttl_seconds,
+            )
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .wrap_err("failed to set email change in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(tokens)
+    }
+
+    #[tracing::instrument(name = "Confirm Email Change", skip_all)]
+    async fn confirm_change(
+        &mut self,
+        confirm_token: &EmailToken,
+    ) -> Result<EmailChange, EmailChangeStoreError> {
+        self.take_change(TokenKind::Confirm, confirm_token).await
+    }
+
+    #[tracing::instrument(name = "Cancel Email Change", skip_all)]
+    async fn cancel_change(
+        &mut self,
+        cancel_token: &EmailToken,
+    ) -> Result<EmailChange, EmailChangeStoreError> {
+        self.take_change(TokenKind::Cancel, cancel_token).await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangeRecord {
+    new_email: String,
+    confirm_token_hash: String,
+    cancel_token_hash: String,
+}
+
+#[derive(Clone, Copy)]
+enum TokenKind {
+    Confirm,
+    Cancel,
+}
+
+impl RedisEmailChangeStore {
+    // Remove the pending change a token belongs to, provided it is still the current change
+    // of its user. Both tokens of the change stop working either way.
+    #[tracing::instrument(name = "Take Email Change", skip_all)]
+    async fn take_change(
+        &mut self,
+        kind: TokenKind,
+        token: &EmailToken,
+    ) -> Result<EmailChange, EmailChangeStoreError> {
+        let mut conn = self.conn.write().await;
+
+        // Reading and deleting the token at once keeps it single-use under concurrent requests
+        let old_email: Option<String> = conn
+            .get_del(self.get_token_key(kind, token))
+            .await
+            .wrap_err("failed to consume email change token in Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let old_email = Email::parse(Secret::new(
+            old_email.ok_or(EmailChangeStoreError::ChangeNotFound)?,
+        ))
+        .map_err(|e| EmailChangeStoreError::UnexpectedError(eyre!(e)))?;
+
+        let change_key = self.get_change_key(&old_email);
+        let record: Option<String> = conn
+            .get(&change_key)
+            .await
+            .wrap_err("failed to get email change from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+        let record: ChangeRecord = match record {
+            Some(record) => serde_json::from_str(&record)
+                .wrap_err("failed to deserialize email change")
+                .map_err(EmailChangeStoreError::UnexpectedError)?,
+            None => return Err(EmailChangeStoreError::ChangeNotFound),
+        };
+
+        let (token_hash, other_token_key) = match kind {
+            TokenKind::Confirm => (
+                &record.confirm_token_hash,
+                self.get_hashed_token_key(TokenKind::Cancel, &record.cancel_token_hash),
+            ),
+            TokenKind::Cancel => (
+                &record.cancel_token_hash,
+                self.get_hashed_token_key(TokenKind::Confirm, &record.confirm_token_hash),
+            ),
+        };
+        // The token belongs to a change that has since been replaced
+        if *token_hash != hash_token(token) {
+            return Err(EmailChangeStoreError::ChangeNotFound);
+        }
+
+        let _: () = conn
+            .del(&[change_key, other_token_key])
+            .await
+            .wrap_err("failed to delete email change from Redis")
+            .map_err(EmailChangeStoreError::UnexpectedError)?;
+
+        Ok(EmailChange {
+            old_email,
+            new_email: Email::parse(Secret::new(record.new_email))
+                .map_err(|e| EmailChangeStoreError::UnexpectedError(eyre!(e)))?,
+        })
+    }
+
+    #[tracing::instrument(name = "Get Email Change Key", skip_all)]
+    fn get_change_key(&self, email: &Email) -> String {
+        self.with_prefix(format!(
+            "{}user:{}",
+            self.key_prefix_base,
+            email.canonical()
+        ))
+    }
+
+    #[tracing::instrument(name = "Get Email Change Token Key", skip_all)]
+    fn get_token_key(&self, kind: TokenKind, token: &EmailToken) -> String {
+        self.get_hashed_token_key(kind, &hash_token(token))
+    }
+
+    fn get_hashed_token_key(&self, kind: TokenKind, token_hash: &str) -> String {
+        let kind = match kind {
+            TokenKind::Confirm => "confirm",
+            TokenKind::Cancel => "cancel",
+        };
+        self.with_prefix(format!("{}{}:{}", self.key_prefix_base, kind, token_hash))
+    }
+
+    fn with_prefix(&self, key: String) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}", prefix, key),
+            None => key,
+        }
+    }
+}
+
+// Tokens are only stored hashed, so they cannot be read back from Redis and used
+fn hash_token(token: &EmailToken) -> String {
+    format!("{:x}", Sha256::digest(token.as_ref().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    async fn create_test_store(test_prefix: &str) -> RedisEmailChangeStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisEmailChangeStore::new_with_config_and_prefix(
+            conn,
+            settings.redis.email_change_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    fn create_change(old_email: &str, new_email: &str) -> EmailChange {
+        EmailChange {
+            old_email: Email::parse(Secret::new(old_email.to_owned())).unwrap(),
+            new_email: Email::parse(Secret::new(new_email.to_owned())).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_confirm_change() {
+        let mut store = create_test_store("confirm_change").await;
+        let change = create_change("confirm_old@example.com", "confirm_new@example.com");
+
+        let tokens = store.add_change(change.clone(), 60).await.unwrap();
+
+        let confirmed = store.confirm_change(&tokens.confirm_token).await.unwrap();
+        assert_eq!(confirmed, change);
+
+        // Neither token works once the change is confirmed
+        let result = store.confirm_change(&tokens.confirm_token).await;
+        assert_eq!(result.unwrap_err(), EmailChangeStoreError::ChangeNotFound);
+        let result = store.cancel_change(&tokens.cancel_token).await;
+        assert_eq!(result.unwrap_err(), EmailChangeStoreError::ChangeNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_change() {
+        let mut store = create_test_store("cancel_change").await;
+        let change = create_change("cancel_old@example.com", "cancel_new@example.com");
+
+        let tokens = store.add_change(change.clone(), 60).await.unwrap();
+
+        // A token only serves its own action
+        let result = store.confirm_change(&tokens.cancel_token).await;
+        assert_eq!(result.unwrap_err(), EmailChangeStoreError::ChangeNotFound);
+
+        let cancelled = store.cancel_change(&tokens.cancel_token).await.unwrap();
+        assert_eq!(cancelled, change);
+
+        let result = store.confirm_change(&tokens.confirm_token).await;
+        assert_eq!(result.unwrap_err(), EmailChangeStoreError::ChangeNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_new_change_replaces_previous() {
+        let mut store = create_test_store("new_change_replaces_previous").await;
+        let first = create_change("replace_old@example.com", "replace_first@example.com");
+        let second = create_change("replace_old@example.com", "replace_second@example.com");
+
+        let first_tokens = store.add_change(first, 60).await.unwrap();
+        let second_tokens = store.add_change(second.clone(), 60).await.unwrap();
+
+        let result = store.confirm_change(&first_tokens.confirm_token).await;
+        assert_eq!(result.unwrap_err(), EmailChangeStoreError::ChangeNotFound);
+
+        let confirmed = store
+            .confirm_change(&second_tokens.confirm_token)
+            .await
+            .unwrap();
+        assert_eq!(confirmed, second);
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_not_stored_in_plain_text() {
+        let store = create_test_store("tokens_are_not_stored_in_plain_text").await;
+        let token = EmailToken::default();
+
+        let key = store.get_token_key(TokenKind::Confirm, &token);
+        assert!(!key.contains(token.as_ref()));
+    }
+}
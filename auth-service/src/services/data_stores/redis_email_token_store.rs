@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, Context};
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{EmailToken, EmailTokenPurpose, EmailTokenStore, EmailTokenStoreError},
+    Email,
+};
+
+pub struct RedisEmailTokenStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    key_prefix_base: String,
+}
+
+impl RedisEmailTokenStore {
+    #[tracing::instrument(name = "New Redis Email Token Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(name = "New Redis Email Token Store with Config and Prefix", skip_all)]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTokenStore for RedisEmailTokenStore {
+    #[tracing::instrument(name = "Add Email Token", skip_all)]
+    async fn add_token(
+        &mut self,
+        token: EmailToken,
+        purpose: EmailTokenPurpose,
+        email: Email,
+        ttl_seconds: u64,
+    ) -> Result<(), EmailTokenStoreError> {
+        let key = self.get_key(&token, purpose);
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, email.as_ref().expose_secret(), ttl_seconds)
+            .await
+            .wrap_err("failed to set email token in Redis")
+            .map_err(EmailTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Consume Email Token", skip_all)]
+    async fn consume_token(
+        &mut self,
+        token: &EmailToken,
+        purpose: EmailTokenPurpose,
+    ) -> Result<Email, EmailTokenStoreError> {
+        let key = self.get_key(token, purpose);
+        // Reading and deleting the token at once keeps it single-use under concurrent requests
+        let email: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(&key)
+            .await
+            .wrap_err("failed to consume email token in Redis")
+            .map_err(EmailTokenStoreError::UnexpectedError)?;
+
+        let email = email.ok_or(EmailTokenStoreError::TokenNotFound)?;
+        Email::parse(Secret::new(email))
+            .map_err(|e| EmailTokenStoreError::UnexpectedError(eyre!(e)))
+    }
+}
+
+impl RedisEmailTokenStore {
+    // Tokens are only stored hashed, so they cannot be read back from Redis and used
+    #[tracing::instrument(name = "Get Email Token Key", skip_all)]
+    fn get_key(&self, token: &EmailToken, purpose: EmailTokenPurpose) -> String {
+        let key = format!(
+            "{}{}:{:x}",
+            self.key_prefix_base,
+            purpose.as_ref(),
+            Sha256::digest(token.as_ref().as_bytes())
+        );
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}", prefix, key),
+            None => key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    async fn create_test_store(test_prefix: &str) -> RedisEmailTokenStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisEmailTokenStore::new_with_config_and_prefix(
+            conn,
+            settings.redis.email_token_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_and_consume_token() {
+        let mut store = create_test_store("add_and_consume_token").await;
+        let email = Email::parse(Secret::new("test_email_token@example.com".to_owned())).unwrap();
+        let token = EmailToken::default();
+
+        store
+            .add_token(
+                token.clone(),
+                EmailTokenPurpose::VerifyEmail,
+                email.clone(),
+                60,
+            )
+            .await
+            .unwrap();
+
+        let consumed = store
+            .consume_token(&token, EmailTokenPurpose::VerifyEmail)
+            .await
+            .unwrap();
+        assert_eq!(consumed, email);
+
+        // Tokens are single-use
+        let result = store
+            .consume_token(&token, EmailTokenPurpose::VerifyEmail)
+            .await;
+        assert_eq!(result.unwrap_err(), EmailTokenStoreError::TokenNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_token_only_serves_its_purpose() {
+        let mut store = create_test_store("token_only_serves_its_purpose").await;
+        let email = Email::parse(Secret::new("test_purpose@example.com".to_owned())).unwrap();
+        let token = EmailToken::default();
+
+        store
+            .add_token(token.clone(), EmailTokenPurpose::VerifyEmail, email, 60)
+            .await
+            .unwrap();
+
+        let result = store
+            .consume_token(&token, EmailTokenPurpose::ResetPassword)
+            .await;
+        assert_eq!(result.unwrap_err(), EmailTokenStoreError::TokenNotFound);
+
+        // Clean up
+        store
+            .consume_token(&token, EmailTokenPurpose::VerifyEmail)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token_is_not_stored_in_plain_text() {
+        let store = create_test_store("token_is_not_stored_in_plain_text").await;
+        let token = EmailToken::default();
+
+        let key = store.get_key(&token, EmailTokenPurpose::ResetPassword);
+        assert!(!key.contains(token.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_consume_unknown_token() {
+        let mut store = create_test_store("consume_unknown_token").await;
+
+        let result = store
+            .consume_token(&EmailToken::default(), EmailTokenPurpose::VerifyEmail)
+            .await;
+        assert_eq!(result.unwrap_err(), EmailTokenStoreError::TokenNotFound);
+    }
+
+    #[test]
+    fn test_email_token_roundtrip() {
+        let token = EmailToken::default();
+        let parsed = EmailToken::parse(token.as_ref().to_owned()).unwrap();
+        assert_eq!(parsed, token);
+
+        assert!(EmailToken::parse("not-a-token".to_owned()).is_err());
+    }
+}
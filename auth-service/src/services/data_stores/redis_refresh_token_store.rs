@@ -0,0 +1,345 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{RefreshToken, RefreshTokenFamily, RefreshTokenStore, RefreshTokenStoreError},
+    UserId,
+};
+
+// Replace the record of a family only while it is still the one the rotation read. Of
+// concurrent rotations of one token only the first swaps the record, however many
+// instances they run on, and the others see that the token was already used.
+const SWAP_FAMILY_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+pub struct RedisRefreshTokenStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    ttl_seconds: u64,
+    key_prefix_base: String,
+}
+
+impl RedisRefreshTokenStore {
+    #[tracing::instrument(name = "New Redis Refresh Token Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(
+        name = "New Redis Refresh Token Store with Config and Prefix",
+        skip_all
+    )]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    #[tracing::instrument(name = "Create Refresh Token Family", skip_all)]
+    async fn create_family(
+        &mut self,
+        family: RefreshTokenFamily,
+    ) -> Result<RefreshToken, RefreshTokenStoreError> {
+        let token = RefreshToken::default();
+        self.set_family(&token, &family).await?;
+        Ok(token)
+    }
+
+    #[tracing::instrument(name = "Rotate Refresh Token", skip_all)]
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(RefreshTokenFamily, RefreshToken), RefreshTokenStoreError> {
+        let key = self.get_key(token.family_id());
+
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get(&key)
+            .await
+            .wrap_err("failed to get refresh token family from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(RefreshTokenStoreError::FamilyNotFound)?;
+        let record: FamilyRecord = serde_json::from_str(&value)
+            .wrap_err("failed to deserialize refresh token family")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        let user_id = record
+            .user_id
+            .map(|user_id| UserId::parse(&user_id))
+            .transpose()
+            .wrap_err("invalid user id in refresh token family")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        // Only the most recently issued token of a family may be used
+        if record.token_id != token.token_id() {
+            return Err(RefreshTokenStoreError::TokenReused(user_id));
+        }
+
+        // Families created before user ids were introduced only name the email of their
+        // user, so they can no longer be rotated and their holders have to log in again
+        let user_id = user_id.ok_or(RefreshTokenStoreError::FamilyNotFound)?;
+        let family = RefreshTokenFamily {
+            user_id,
+            session_epoch: record.session_epoch,
+        };
+
+        let new_token = token.rotate();
+        let swapped: bool = redis::Script::new(SWAP_FAMILY_SCRIPT)
+            .key(&key)
+            .arg(&value)
+            .arg(serialize_family(&new_token, &family)?)
+            .arg(self.ttl_seconds)
+            .invoke_async(&mut *self.conn.write().await)
+            .await
+            .wrap_err("failed to swap refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+
+        // Another rotation of the same token got there first
+        if !swapped {
+            return Err(RefreshTokenStoreError::TokenReused(Some(family.user_id)));
+        }
+
+        Ok((family, new_token))
+    }
+
+    #[tracing::instrument(name = "Revoke Refresh Token Family", skip_all)]
+    async fn revoke_family(&mut self, family_id: &str) -> Result<(), RefreshTokenStoreError> {
+        let key = self.get_key(family_id);
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&key)
+            .await
+            .wrap_err("failed to delete refresh token family from Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FamilyRecord {
+    #[serde(default)]
+    user_id: Option<String>,
+    // Families created before session epochs were introduced default to an epoch no user
+    // has, so they can no longer be rotated and their holders have to log in again
+    #[serde(default)]
+    session_epoch: i64,
+    token_id: String,
+}
+
+impl RedisRefreshTokenStore {
+    #[tracing::instrument(name = "Set Refresh Token Family", skip_all)]
+    async fn set_family(
+        &mut self,
+        token: &RefreshToken,
+        family: &RefreshTokenFamily,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let key = self.get_key(token.family_id());
+        let serialized_family = serialize_family(token, family)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(&key, serialized_family, self.ttl_seconds)
+            .await
+            .wrap_err("failed to set refresh token family in Redis")
+            .map_err(RefreshTokenStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get Refresh Token Family Key", skip_all)]
+    fn get_key(&self, family_id: &str) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, family_id),
+            None => format!("{}{}", self.key_prefix_base, family_id),
+        }
+    }
+}
+
+// Record of a family whose latest token is `token`
+fn serialize_family(
+    token: &RefreshToken,
+    family: &RefreshTokenFamily,
+) -> Result<String, RefreshTokenStoreError> {
+    let record = FamilyRecord {
+        user_id: Some(family.user_id.to_string()),
+        session_epoch: family.session_epoch,
+        token_id: token.token_id().to_owned(),
+    };
+    serde_json::to_string(&record)
+        .wrap_err("failed to serialize refresh token family")
+        .map_err(RefreshTokenStoreError::UnexpectedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    fn test_family() -> RefreshTokenFamily {
+        RefreshTokenFamily {
+            user_id: UserId::default(),
+            session_epoch: 1,
+        }
+    }
+
+    async fn create_test_store(test_prefix: &str) -> RedisRefreshTokenStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisRefreshTokenStore::new_with_config_and_prefix(
+            conn,
+            settings.auth.refresh_token_ttl_seconds,
+            settings.redis.refresh_token_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_create_family_and_rotate() {
+        let mut store = create_test_store("create_family_and_rotate").await;
+        let family = test_family();
+
+        let token = store.create_family(family.clone()).await.unwrap();
+
+        let (rotated_family, rotated_token) = store.rotate_token(&token).await.unwrap();
+        assert_eq!(rotated_family, family);
+        assert_eq!(rotated_token.family_id(), token.family_id());
+        assert_ne!(rotated_token.token_id(), token.token_id());
+
+        // Clean up
+        store.revoke_family(token.family_id()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotate_already_rotated_token_is_reuse() {
+        let mut store = create_test_store("rotate_already_rotated_token").await;
+        let token = store.create_family(test_family()).await.unwrap();
+        let (_, rotated_token) = store.rotate_token(&token).await.unwrap();
+
+        let result = store.rotate_token(&token).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            RefreshTokenStoreError::TokenReused(Some(_))
+        ));
+
+        // The latest token of the family is still accepted until the family is revoked
+        assert!(store.rotate_token(&rotated_token).await.is_ok());
+
+        // Clean up
+        store.revoke_family(token.family_id()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_rotations_of_one_token() {
+        let mut store = create_test_store("concurrent_rotations").await;
+        let mut other_store = create_test_store("concurrent_rotations").await;
+        let token = store.create_family(test_family()).await.unwrap();
+
+        // Like two instances refreshing with the same token at once
+        let (result, other_result) =
+            tokio::join!(store.rotate_token(&token), other_store.rotate_token(&token));
+        let mut results = [result, other_result];
+        results.sort_by_key(|result| result.is_err());
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1].as_ref().unwrap_err(),
+            RefreshTokenStoreError::TokenReused(Some(_))
+        ));
+
+        // Clean up
+        store.revoke_family(token.family_id()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = create_test_store("revoke_family").await;
+        let token = store.create_family(test_family()).await.unwrap();
+        store.revoke_family(token.family_id()).await.unwrap();
+
+        let result = store.rotate_token(&token).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenStoreError::FamilyNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_unknown_family() {
+        let mut store = create_test_store("rotate_unknown_family").await;
+
+        let result = store.rotate_token(&RefreshToken::default()).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenStoreError::FamilyNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_family_without_user_id() {
+        let mut store = create_test_store("rotate_family_without_user_id").await;
+        let token = RefreshToken::default();
+        let record = serde_json::json!({
+            "email": "test_legacy@example.com",
+            "session_epoch": 1,
+            "token_id": token.token_id(),
+        });
+        let _: () = store
+            .conn
+            .write()
+            .await
+            .set_ex(store.get_key(token.family_id()), record.to_string(), 60)
+            .await
+            .unwrap();
+
+        let result = store.rotate_token(&token).await;
+        assert_eq!(result.unwrap_err(), RefreshTokenStoreError::FamilyNotFound);
+
+        // Clean up
+        store.revoke_family(token.family_id()).await.unwrap();
+    }
+
+    #[test]
+    fn test_refresh_token_roundtrip() {
+        let token = RefreshToken::default();
+        let parsed = RefreshToken::parse(token.to_string()).unwrap();
+        assert_eq!(parsed, token);
+
+        assert!(RefreshToken::parse("not-a-refresh-token".to_string()).is_err());
+    }
+}
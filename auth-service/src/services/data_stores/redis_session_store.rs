@@ -0,0 +1,328 @@
+use std::{cmp::Reverse, sync::Arc};
+
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use redis::AsyncCommands;
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{SessionStore, SessionStoreError},
+    Session, UserId,
+};
+
+// Sessions of a user are kept in a single hash, keyed by session id
+pub struct RedisSessionStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    ttl_seconds: u64,
+    key_prefix_base: String,
+}
+
+impl RedisSessionStore {
+    #[tracing::instrument(name = "New Redis Session Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(name = "New Redis Session Store with Config and Prefix", skip_all)]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisSessionStore {
+    #[tracing::instrument(name = "Add Session", skip_all)]
+    async fn add_session(
+        &mut self,
+        user_id: &UserId,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        self.set_session(user_id, &session).await
+    }
+
+    #[tracing::instrument(name = "Get Sessions", skip_all)]
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let key = self.get_key(user_id);
+        let values: Vec<String> = self
+            .conn
+            .write()
+            .await
+            .hvals(&key)
+            .await
+            .wrap_err("failed to get sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let mut sessions = values
+            .iter()
+            .map(|value| {
+                serde_json::from_str::<Session>(value)
+                    .wrap_err("failed to deserialize session")
+                    .map_err(SessionStoreError::UnexpectedError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The hash lives as long as the most recently seen session, so older sessions
+        // whose refresh token family has already expired are skipped here
+        let seen_after = Utc::now().timestamp() - self.ttl_seconds as i64;
+        sessions.retain(|session| session.last_seen > seen_after);
+        sessions.sort_by_key(|session| Reverse(session.last_seen));
+
+        Ok(sessions)
+    }
+
+    #[tracing::instrument(name = "Touch Session", skip_all)]
+    async fn touch_session(
+        &mut self,
+        user_id: &UserId,
+        session_id: &str,
+        last_seen: i64,
+    ) -> Result<(), SessionStoreError> {
+        let key = self.get_key(user_id);
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .hget(&key, session_id)
+            .await
+            .wrap_err("failed to get session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let mut session: Session = match value {
+            Some(value) => serde_json::from_str(&value)
+                .wrap_err("failed to deserialize session")
+                .map_err(SessionStoreError::UnexpectedError)?,
+            None => return Err(SessionStoreError::SessionNotFound),
+        };
+
+        session.last_seen = last_seen;
+        self.set_session(user_id, &session).await
+    }
+
+    #[tracing::instrument(name = "Remove Session", skip_all)]
+    async fn remove_session(
+        &mut self,
+        user_id: &UserId,
+        session_id: &str,
+    ) -> Result<(), SessionStoreError> {
+        let key = self.get_key(user_id);
+        let removed: u64 = self
+            .conn
+            .write()
+            .await
+            .hdel(&key, session_id)
+            .await
+            .wrap_err("failed to delete session from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        if removed == 0 {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Remove All Sessions", skip_all)]
+    async fn remove_all_sessions(&mut self, user_id: &UserId) -> Result<(), SessionStoreError> {
+        let key = self.get_key(user_id);
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&key)
+            .await
+            .wrap_err("failed to delete sessions from Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        Ok(())
+    }
+}
+
+impl RedisSessionStore {
+    #[tracing::instrument(name = "Set Session", skip_all)]
+    async fn set_session(
+        &mut self,
+        user_id: &UserId,
+        session: &Session,
+    ) -> Result<(), SessionStoreError> {
+        let key = self.get_key(user_id);
+        let serialized_session = serde_json::to_string(session)
+            .wrap_err("failed to serialize session")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let ttl_seconds = i64::try_from(self.ttl_seconds)
+            .wrap_err("failed to cast session TTL to i64")
+            .map_err(SessionStoreError::UnexpectedError)?;
+
+        let mut conn = self.conn.write().await;
+        let _: () = redis::pipe()
+            .atomic()
+            .hset(&key, &session.id, serialized_session)
+            .ignore()
+            .expire(&key, ttl_seconds)
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .wrap_err("failed to set session in Redis")
+            .map_err(SessionStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get Session Key", skip_all)]
+    fn get_key(&self, user_id: &UserId) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, user_id),
+            None => format!("{}{}", self.key_prefix_base, user_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    fn test_session(id: &str, last_seen: i64) -> Session {
+        Session {
+            id: id.to_owned(),
+            created_at: last_seen,
+            last_seen,
+            user_agent: Some("test-agent".to_owned()),
+            ip_address: Some("127.0.0.1".to_owned()),
+            two_fa_used: false,
+        }
+    }
+
+    async fn create_test_store(test_prefix: &str) -> RedisSessionStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisSessionStore::new_with_config_and_prefix(
+            conn,
+            settings.auth.refresh_token_ttl_seconds,
+            settings.redis.session_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_sessions() {
+        let mut store = create_test_store("add_and_get_sessions").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_session(&user_id, test_session("older", now - 10))
+            .await
+            .unwrap();
+        store
+            .add_session(&user_id, test_session("newer", now))
+            .await
+            .unwrap();
+
+        // Most recently seen sessions come first
+        let sessions = store.get_sessions(&user_id).await.unwrap();
+        assert_eq!(
+            sessions,
+            vec![test_session("newer", now), test_session("older", now - 10)]
+        );
+
+        // Clean up
+        store.remove_all_sessions(&user_id).await.unwrap();
+        assert!(store.get_sessions(&user_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let mut store = create_test_store("touch_session").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_session(&user_id, test_session("session", now - 10))
+            .await
+            .unwrap();
+        store.touch_session(&user_id, "session", now).await.unwrap();
+
+        let sessions = store.get_sessions(&user_id).await.unwrap();
+        assert_eq!(sessions[0].created_at, now - 10);
+        assert_eq!(sessions[0].last_seen, now);
+
+        let result = store.touch_session(&user_id, "unknown", now).await;
+        assert_eq!(result.unwrap_err(), SessionStoreError::SessionNotFound);
+
+        // Clean up
+        store.remove_all_sessions(&user_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remove_session() {
+        let mut store = create_test_store("remove_session").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_session(&user_id, test_session("first", now))
+            .await
+            .unwrap();
+        store
+            .add_session(&user_id, test_session("second", now))
+            .await
+            .unwrap();
+
+        store.remove_session(&user_id, "first").await.unwrap();
+        let sessions = store.get_sessions(&user_id).await.unwrap();
+        assert_eq!(sessions, vec![test_session("second", now)]);
+
+        let result = store.remove_session(&user_id, "first").await;
+        assert_eq!(result.unwrap_err(), SessionStoreError::SessionNotFound);
+
+        // Clean up
+        store.remove_all_sessions(&user_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_sessions_are_not_listed() {
+        let mut store = create_test_store("expired_sessions").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+        let stale = now - store.ttl_seconds as i64 - 1;
+
+        store
+            .add_session(&user_id, test_session("stale", stale))
+            .await
+            .unwrap();
+        store
+            .add_session(&user_id, test_session("live", now))
+            .await
+            .unwrap();
+
+        let sessions = store.get_sessions(&user_id).await.unwrap();
+        assert_eq!(sessions, vec![test_session("live", now)]);
+
+        // Clean up
+        store.remove_all_sessions(&user_id).await.unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::AsyncCommands;
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{SigningKeyStore, SigningKeyStoreError},
+    KeyPromotion,
+};
+
+// Promotions are appended to a single list that never expires
+pub struct RedisSigningKeyStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    key_prefix_base: String,
+}
+
+impl RedisSigningKeyStore {
+    #[tracing::instrument(name = "New Redis Signing Key Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(name = "New Redis Signing Key Store with Config and Prefix", skip_all)]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SigningKeyStore for RedisSigningKeyStore {
+    #[tracing::instrument(name = "Add Signing Key Promotion", skip_all)]
+    async fn add_promotion(&mut self, promotion: KeyPromotion) -> Result<(), SigningKeyStoreError> {
+        let record = serde_json::to_string(&promotion)
+            .wrap_err("failed to serialize signing key promotion")
+            .map_err(SigningKeyStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .rpush(self.get_key(), record)
+            .await
+            .wrap_err("failed to add signing key promotion to Redis")
+            .map_err(SigningKeyStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get Signing Key Promotions", skip_all)]
+    async fn get_promotions(&self, skip: usize) -> Result<Vec<KeyPromotion>, SigningKeyStoreError> {
+        let start = isize::try_from(skip)
+            .wrap_err("failed to cast promotion index to isize")
+            .map_err(SigningKeyStoreError::UnexpectedError)?;
+        let records: Vec<String> = self
+            .conn
+            .write()
+            .await
+            .lrange(self.get_key(), start, -1)
+            .await
+            .wrap_err("failed to get signing key promotions from Redis")
+            .map_err(SigningKeyStoreError::UnexpectedError)?;
+
+        records
+            .iter()
+            .map(|record| {
+                serde_json::from_str(record)
+                    .wrap_err("failed to deserialize signing key promotion")
+                    .map_err(SigningKeyStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+}
+
+impl RedisSigningKeyStore {
+    #[tracing::instrument(name = "Get Signing Key Promotions Key", skip_all)]
+    fn get_key(&self) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}promotions", prefix, self.key_prefix_base),
+            None => format!("{}promotions", self.key_prefix_base),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    async fn create_test_store(test_prefix: &str) -> RedisSigningKeyStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        // The list is never cleared, so every run gets its own
+        RedisSigningKeyStore::new_with_config_and_prefix(
+            conn,
+            settings.redis.signing_key_key_prefix,
+            format!("test_{}_{}:", test_prefix, uuid::Uuid::new_v4()),
+        )
+    }
+
+    fn promotion(kid: &str, promoted_at: i64) -> KeyPromotion {
+        KeyPromotion {
+            kid: kid.to_owned(),
+            promoted_at,
+            overlap_seconds: 660,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_promotions_in_order() {
+        let mut store = create_test_store("add_and_get_promotions").await;
+        assert!(store.get_promotions(0).await.unwrap().is_empty());
+
+        store.add_promotion(promotion("first", 100)).await.unwrap();
+        store.add_promotion(promotion("second", 200)).await.unwrap();
+
+        assert_eq!(
+            store.get_promotions(0).await.unwrap(),
+            vec![promotion("first", 100), promotion("second", 200)]
+        );
+        assert_eq!(
+            store.get_promotions(1).await.unwrap(),
+            vec![promotion("second", 200)]
+        );
+        assert!(store.get_promotions(2).await.unwrap().is_empty());
+    }
+}
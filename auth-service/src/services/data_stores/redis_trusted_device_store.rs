@@ -0,0 +1,305 @@
+use std::{cmp::Reverse, sync::Arc};
+
+use chrono::Utc;
+use color_eyre::eyre::Context;
+use redis::AsyncCommands;
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{TrustedDeviceStore, TrustedDeviceStoreError},
+    TrustedDevice, UserId,
+};
+
+// Trusted devices of a user are kept in a single hash, keyed by device id
+pub struct RedisTrustedDeviceStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    ttl_seconds: u64,
+    key_prefix_base: String,
+}
+
+impl RedisTrustedDeviceStore {
+    #[tracing::instrument(name = "New Redis Trusted Device Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(
+        name = "New Redis Trusted Device Store with Config and Prefix",
+        skip_all
+    )]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        ttl_seconds: u64,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            ttl_seconds,
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TrustedDeviceStore for RedisTrustedDeviceStore {
+    #[tracing::instrument(name = "Add Trusted Device", skip_all)]
+    async fn add_device(
+        &mut self,
+        user_id: &UserId,
+        device: TrustedDevice,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        let key = self.get_key(user_id);
+        let serialized_device = serde_json::to_string(&device)
+            .wrap_err("failed to serialize trusted device")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+
+        let ttl_seconds = i64::try_from(self.ttl_seconds)
+            .wrap_err("failed to cast trusted device TTL to i64")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+
+        let mut conn = self.conn.write().await;
+        let _: () = redis::pipe()
+            .atomic()
+            .hset(&key, &device.id, serialized_device)
+            .ignore()
+            .expire(&key, ttl_seconds)
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .wrap_err("failed to set trusted device in Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get Trusted Devices", skip_all)]
+    async fn get_devices(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<TrustedDevice>, TrustedDeviceStoreError> {
+        let key = self.get_key(user_id);
+        let values: Vec<String> = self
+            .conn
+            .write()
+            .await
+            .hvals(&key)
+            .await
+            .wrap_err("failed to get trusted devices from Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+
+        let mut devices = values
+            .iter()
+            .map(|value| parse_device(value))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The hash lives as long as the most recently trusted device, so devices whose
+        // trust has already expired are skipped here
+        let now = Utc::now().timestamp();
+        devices.retain(|device| device.expires_at > now);
+        devices.sort_by_key(|device| Reverse(device.created_at));
+
+        Ok(devices)
+    }
+
+    #[tracing::instrument(name = "Check Trusted Device", skip_all)]
+    async fn is_trusted(
+        &self,
+        user_id: &UserId,
+        device_id: &str,
+    ) -> Result<bool, TrustedDeviceStoreError> {
+        let key = self.get_key(user_id);
+        let value: Option<String> = self
+            .conn
+            .write()
+            .await
+            .hget(&key, device_id)
+            .await
+            .wrap_err("failed to get trusted device from Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+
+        match value {
+            Some(value) => Ok(parse_device(&value)?.expires_at > Utc::now().timestamp()),
+            None => Ok(false),
+        }
+    }
+
+    #[tracing::instrument(name = "Remove Trusted Device", skip_all)]
+    async fn remove_device(
+        &mut self,
+        user_id: &UserId,
+        device_id: &str,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        let key = self.get_key(user_id);
+        let removed: u64 = self
+            .conn
+            .write()
+            .await
+            .hdel(&key, device_id)
+            .await
+            .wrap_err("failed to delete trusted device from Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+
+        if removed == 0 {
+            return Err(TrustedDeviceStoreError::DeviceNotFound);
+        }
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Remove All Trusted Devices", skip_all)]
+    async fn remove_all_devices(
+        &mut self,
+        user_id: &UserId,
+    ) -> Result<(), TrustedDeviceStoreError> {
+        let key = self.get_key(user_id);
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&key)
+            .await
+            .wrap_err("failed to delete trusted devices from Redis")
+            .map_err(TrustedDeviceStoreError::UnexpectedError)?;
+        Ok(())
+    }
+}
+
+impl RedisTrustedDeviceStore {
+    #[tracing::instrument(name = "Get Trusted Device Key", skip_all)]
+    fn get_key(&self, user_id: &UserId) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, user_id),
+            None => format!("{}{}", self.key_prefix_base, user_id),
+        }
+    }
+}
+
+fn parse_device(value: &str) -> Result<TrustedDevice, TrustedDeviceStoreError> {
+    serde_json::from_str(value)
+        .wrap_err("failed to deserialize trusted device")
+        .map_err(TrustedDeviceStoreError::UnexpectedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    fn test_device(id: &str, created_at: i64) -> TrustedDevice {
+        TrustedDevice {
+            id: id.to_owned(),
+            created_at,
+            expires_at: created_at + 3600,
+            user_agent: Some("test-agent".to_owned()),
+            ip_address: Some("127.0.0.1".to_owned()),
+        }
+    }
+
+    async fn create_test_store(test_prefix: &str) -> RedisTrustedDeviceStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisTrustedDeviceStore::new_with_config_and_prefix(
+            conn,
+            settings.auth.trusted_device_days * 86400,
+            settings.redis.trusted_device_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_devices() {
+        let mut store = create_test_store("add_and_get_devices").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_device(&user_id, test_device("older", now - 10))
+            .await
+            .unwrap();
+        store
+            .add_device(&user_id, test_device("newer", now))
+            .await
+            .unwrap();
+
+        // Most recently trusted devices come first
+        let devices = store.get_devices(&user_id).await.unwrap();
+        assert_eq!(
+            devices,
+            vec![test_device("newer", now), test_device("older", now - 10)]
+        );
+        assert!(store.is_trusted(&user_id, "older").await.unwrap());
+        assert!(!store.is_trusted(&user_id, "unknown").await.unwrap());
+        assert!(!store.is_trusted(&UserId::default(), "older").await.unwrap());
+
+        // Clean up
+        store.remove_all_devices(&user_id).await.unwrap();
+        assert!(store.get_devices(&user_id).await.unwrap().is_empty());
+        assert!(!store.is_trusted(&user_id, "older").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_remove_device() {
+        let mut store = create_test_store("remove_device").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_device(&user_id, test_device("first", now))
+            .await
+            .unwrap();
+        store
+            .add_device(&user_id, test_device("second", now))
+            .await
+            .unwrap();
+
+        store.remove_device(&user_id, "first").await.unwrap();
+        let devices = store.get_devices(&user_id).await.unwrap();
+        assert_eq!(devices, vec![test_device("second", now)]);
+        assert!(!store.is_trusted(&user_id, "first").await.unwrap());
+
+        let result = store.remove_device(&user_id, "first").await;
+        assert_eq!(result.unwrap_err(), TrustedDeviceStoreError::DeviceNotFound);
+
+        // Clean up
+        store.remove_all_devices(&user_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expired_devices_are_not_trusted() {
+        let mut store = create_test_store("expired_devices").await;
+        let user_id = UserId::default();
+        let now = Utc::now().timestamp();
+
+        store
+            .add_device(&user_id, test_device("expired", now - 3601))
+            .await
+            .unwrap();
+        store
+            .add_device(&user_id, test_device("live", now))
+            .await
+            .unwrap();
+
+        let devices = store.get_devices(&user_id).await.unwrap();
+        assert_eq!(devices, vec![test_device("live", now)]);
+        assert!(!store.is_trusted(&user_id, "expired").await.unwrap());
+
+        // Clean up
+        store.remove_all_devices(&user_id).await.unwrap();
+    }
+}
@@ -2,12 +2,10 @@ use std::sync::Arc;
 
 use color_eyre::eyre::Context;
 use redis::AsyncCommands;
-use secrecy::ExposeSecret;
-use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    data_stores::{LoginAttemptId, TwoFACodeHash, TwoFACodeStore, TwoFACodeStoreError},
     Email,
 };
 
@@ -56,82 +54,273 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         &mut self,
         email: Email,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        code_hash: TwoFACodeHash,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = self.get_key(&email);
-        let two_fa_tuple = TwoFATuple(
-            login_attempt_id.as_ref().to_string(),
-            code.as_ref().to_string(),
-        );
-
-        let serialized_tuple = serde_json::to_string(&two_fa_tuple)
-            .wrap_err("failed to serialize 2FA tuple")
+        let key = self.get_key(&email, &login_attempt_id);
+        let login_attempts_key = self.get_login_attempts_key(&email);
+
+        // Other pending login attempts of the email are left alone, so each can complete.
+        // The index of them lives as long as the newest one.
+        let mut conn = self.conn.write().await;
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(&key, code_hash.as_ref(), self.ttl_seconds)
+            .ignore()
+            .sadd(&login_attempts_key, login_attempt_id.as_ref())
+            .ignore()
+            .expire(&login_attempts_key, self.ttl_seconds as i64)
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .wrap_err("failed to set 2FA code in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(())
+    }
 
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .set_ex(&key, serialized_tuple, self.ttl_seconds)
+    #[tracing::instrument(name = "Replace Two FA Code", skip_all)]
+    async fn replace_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code_hash: TwoFACodeHash,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let key = self.get_key(email, login_attempt_id);
+        let mut conn = self.conn.write().await;
+        let ttl_milliseconds = remaining_ttl(&mut conn, &key).await?;
+
+        let _: () = conn
+            .pset_ex(&key, code_hash.as_ref(), ttl_milliseconds)
             .await
-            .wrap_err("failed to set 2FA code in Redis")
+            .wrap_err("failed to replace 2FA code in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
         Ok(())
     }
 
     #[tracing::instrument(name = "Remove Two FA Code", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = self.get_key(email);
-        let _: () = self
-            .conn
-            .write()
-            .await
-            .del(&key)
+    async fn remove_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let keys = self.get_login_attempt_keys(email, login_attempt_id);
+        let mut conn = self.conn.write().await;
+        let _: () = redis::pipe()
+            .atomic()
+            .del(&keys)
+            .ignore()
+            .srem(
+                self.get_login_attempts_key(email),
+                login_attempt_id.as_ref(),
+            )
+            .ignore()
+            .query_async(&mut *conn)
             .await
             .wrap_err("failed to delete 2FA code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
         Ok(())
     }
 
-    #[tracing::instrument(name = "Get Two FA Code", skip_all)]
-    async fn get_code(
+    #[tracing::instrument(name = "Remove Two FA Codes", skip_all)]
+    async fn remove_codes(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+        let login_attempts_key = self.get_login_attempts_key(email);
+        let mut conn = self.conn.write().await;
+        let login_attempt_ids: Vec<String> = conn
+            .smembers(&login_attempts_key)
+            .await
+            .wrap_err("failed to get login attempts from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let mut keys = vec![login_attempts_key];
+        for login_attempt_id in login_attempt_ids {
+            // Ids only ever get into the index parsed, so this never skips one
+            if let Ok(login_attempt_id) = LoginAttemptId::parse(login_attempt_id) {
+                keys.extend(self.get_login_attempt_keys(email, &login_attempt_id));
+            }
+        }
+
+        let _: () = conn
+            .del(&keys)
+            .await
+            .wrap_err("failed to delete 2FA codes from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Get Two FA Code Hash", skip_all)]
+    async fn get_code_hash(
         &self,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = self.get_key(email);
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFACodeHash, TwoFACodeStoreError> {
+        let key = self.get_key(email, login_attempt_id);
         match self.conn.write().await.get::<_, String>(&key).await {
-            Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
-                    .wrap_err("failed to deserialize 2FA tuple")
-                    .map_err(TwoFACodeStoreError::UnexpectedError)?;
+            Ok(value) => TwoFACodeHash::parse(value).map_err(TwoFACodeStoreError::UnexpectedError),
+            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
 
-                let login_attempt_id =
-                    LoginAttemptId::parse(data.0).map_err(TwoFACodeStoreError::UnexpectedError)?;
+    #[tracing::instrument(name = "Record Failed Two FA Attempt", skip_all)]
+    async fn record_failed_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<u32, TwoFACodeStoreError> {
+        self.increment_counter(FAILED_ATTEMPTS, email, login_attempt_id)
+            .await
+    }
 
-                let email_code =
-                    TwoFACode::parse(data.1).map_err(TwoFACodeStoreError::UnexpectedError)?;
+    #[tracing::instrument(name = "Record Failed Two FA Attempt of Email", skip_all)]
+    async fn record_failed_email_attempt(
+        &mut self,
+        email: &Email,
+    ) -> Result<u32, TwoFACodeStoreError> {
+        let key = self.get_email_failed_attempts_key(email);
+        let (count,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, self.ttl_seconds as i64)
+            .ignore()
+            .query_async(&mut *self.conn.write().await)
+            .await
+            .wrap_err("failed to increment failed 2FA attempts of email in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(count)
+    }
 
-                Ok((login_attempt_id, email_code))
-            }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
-        }
+    #[tracing::instrument(name = "Reset Failed Two FA Attempts of Email", skip_all)]
+    async fn reset_failed_email_attempts(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let key = self.get_email_failed_attempts_key(email);
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .del(&key)
+            .await
+            .wrap_err("failed to delete failed 2FA attempts of email from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Record Two FA Code Resend", skip_all)]
+    async fn record_resend(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<u32, TwoFACodeStoreError> {
+        self.increment_counter(RESENDS, email, login_attempt_id)
+            .await
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+// Counters kept for each login attempt, next to its code
+const FAILED_ATTEMPTS: &str = "failed_attempts";
+const RESENDS: &str = "resends";
+
+// Milliseconds until the code of a login attempt expires
+async fn remaining_ttl(
+    conn: &mut redis::aio::MultiplexedConnection,
+    key: &str,
+) -> Result<u64, TwoFACodeStoreError> {
+    let ttl_milliseconds: i64 = conn
+        .pttl(key)
+        .await
+        .wrap_err("failed to get 2FA code TTL from Redis")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+    match ttl_milliseconds > 0 {
+        true => Ok(ttl_milliseconds as u64),
+        false => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+    }
+}
 
 impl RedisTwoFACodeStore {
+    async fn increment_counter(
+        &mut self,
+        counter: &str,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<u32, TwoFACodeStoreError> {
+        let key = self.get_key(email, login_attempt_id);
+        let counter_key = self.get_counter_key(counter, email, login_attempt_id);
+        let mut conn = self.conn.write().await;
+
+        // The count expires with the code it belongs to
+        let ttl_milliseconds = remaining_ttl(&mut conn, &key).await?;
+        let (count,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&counter_key, 1)
+            .pexpire(&counter_key, ttl_milliseconds as i64)
+            .ignore()
+            .query_async(&mut *conn)
+            .await
+            .wrap_err("failed to increment 2FA counter in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        Ok(count)
+    }
+
     #[tracing::instrument(name = "Get Two FA Code Key", skip_all)]
-    fn get_key(&self, email: &Email) -> String {
+    fn get_key(&self, email: &Email, login_attempt_id: &LoginAttemptId) -> String {
+        self.prefixed(format!(
+            "{}{}:{}",
+            self.key_prefix_base,
+            email.canonical(),
+            login_attempt_id.as_ref()
+        ))
+    }
+
+    fn get_counter_key(
+        &self,
+        counter: &str,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> String {
+        self.prefixed(format!(
+            "{}{}:{}:{}",
+            self.key_prefix_base,
+            counter,
+            email.canonical(),
+            login_attempt_id.as_ref()
+        ))
+    }
+
+    // Key of the count of wrong guesses of the email, across its login attempts
+    fn get_email_failed_attempts_key(&self, email: &Email) -> String {
+        self.prefixed(format!(
+            "{}{}:{}",
+            self.key_prefix_base,
+            FAILED_ATTEMPTS,
+            email.canonical()
+        ))
+    }
+
+    // Key of the set of pending login attempts of the email
+    fn get_login_attempts_key(&self, email: &Email) -> String {
+        self.prefixed(format!(
+            "{}login_attempts:{}",
+            self.key_prefix_base,
+            email.canonical()
+        ))
+    }
+
+    // Every key a login attempt keeps, its code and its counters
+    fn get_login_attempt_keys(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+    ) -> [String; 3] {
+        [
+            self.get_key(email, login_attempt_id),
+            self.get_counter_key(FAILED_ATTEMPTS, email, login_attempt_id),
+            self.get_counter_key(RESENDS, email, login_attempt_id),
+        ]
+    }
+
+    fn prefixed(&self, key: String) -> String {
         match &self.key_prefix {
-            Some(prefix) => format!(
-                "{}{}{}",
-                prefix,
-                self.key_prefix_base,
-                email.as_ref().expose_secret()
-            ),
-            None => format!("{}{}", self.key_prefix_base, email.as_ref().expose_secret()),
+            Some(prefix) => format!("{}{}", prefix, key),
+            None => key,
         }
     }
 }
@@ -142,6 +331,7 @@ mod tests {
     use crate::config::Settings;
     use crate::domain::data_stores::{LoginAttemptId, TwoFACode};
     use crate::domain::Email;
+    use crate::utils::two_fa_code::hash_code;
     use secrecy::Secret;
     use std::sync::Arc;
     use tokio::sync::RwLock;
@@ -163,12 +353,24 @@ mod tests {
         )
     }
 
+    fn code_hash() -> TwoFACodeHash {
+        hash_code(
+            "test-key",
+            &LoginAttemptId::default(),
+            &TwoFACode::default(),
+        )
+    }
+
+    fn parse_email(email: &str) -> Email {
+        Email::parse(Secret::new(email.to_string())).unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_and_get_code() {
         let mut store = create_test_store("add_and_get_code").await;
-        let email = Email::parse(Secret::new("test_add_get@example.com".to_string())).unwrap();
+        let email = parse_email("test_add_get@example.com");
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
+        let code = code_hash();
 
         // Add code
         let result = store
@@ -177,24 +379,30 @@ mod tests {
         assert!(result.is_ok());
 
         // Get code
-        let result = store.get_code(&email).await;
-        assert!(result.is_ok());
-        let (retrieved_id, retrieved_code) = result.unwrap();
-        assert_eq!(retrieved_id, login_attempt_id);
-        assert_eq!(retrieved_code, code);
+        let result = store.get_code_hash(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), code);
+
+        // Only the login attempt the code was added for has it
+        let result = store
+            .get_code_hash(&email, &LoginAttemptId::default())
+            .await;
+        assert_eq!(
+            result.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
 
         // Clean up
-        let key = store.get_key(&email);
-        let mut conn = store.conn.write().await;
-        let _: () = conn.del(&key).await.unwrap();
+        store.remove_codes(&email).await.unwrap();
     }
 
     #[tokio::test]
     async fn test_get_nonexistent_code() {
         let store = create_test_store("get_nonexistent_code").await;
-        let email = Email::parse(Secret::new("nonexistent_get@example.com".to_string())).unwrap();
+        let email = parse_email("nonexistent_get@example.com");
 
-        let result = store.get_code(&email).await;
+        let result = store
+            .get_code_hash(&email, &LoginAttemptId::default())
+            .await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
@@ -205,102 +413,239 @@ mod tests {
     #[tokio::test]
     async fn test_remove_code() {
         let mut store = create_test_store("remove_code").await;
-        let email = Email::parse(Secret::new("test_remove@example.com".to_string())).unwrap();
+        let email = parse_email("test_remove@example.com");
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
+        let code = code_hash();
 
         // Add code
         store
-            .add_code(email.clone(), login_attempt_id, code)
+            .add_code(email.clone(), login_attempt_id.clone(), code)
             .await
             .unwrap();
 
         // Remove code
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&email, &login_attempt_id).await;
         assert!(result.is_ok());
 
         // Verify code is removed
-        let result = store.get_code(&email).await;
+        let result = store.get_code_hash(&email, &login_attempt_id).await;
         assert!(result.is_err());
         assert_eq!(
             result.unwrap_err(),
             TwoFACodeStoreError::LoginAttemptIdNotFound
         );
+
+        // Clean up
+        store.remove_codes(&email).await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_overwrite_existing_code() {
-        let mut store = create_test_store("overwrite_existing_code").await;
-        let email = Email::parse(Secret::new("test_overwrite@example.com".to_string())).unwrap();
+    async fn test_concurrent_login_attempts() {
+        let mut store = create_test_store("concurrent_login_attempts").await;
+        let email = parse_email("test_concurrent@example.com");
         let login_attempt_id1 = LoginAttemptId::default();
-        let code1 = TwoFACode::default();
+        let code1 = code_hash();
         let login_attempt_id2 = LoginAttemptId::default();
-        let code2 = TwoFACode::default();
+        let code2 = code_hash();
 
-        // Add first code
         store
             .add_code(email.clone(), login_attempt_id1.clone(), code1.clone())
             .await
             .unwrap();
-
-        // Overwrite with second code
         store
             .add_code(email.clone(), login_attempt_id2.clone(), code2.clone())
             .await
             .unwrap();
 
-        // Should get the second code
-        let result = store.get_code(&email).await;
+        // A second login attempt leaves the first one pending
+        let result = store.get_code_hash(&email, &login_attempt_id1).await;
+        assert_eq!(result.unwrap(), code1);
+        let result = store.get_code_hash(&email, &login_attempt_id2).await;
+        assert_eq!(result.unwrap(), code2);
+
+        // And finishing one leaves the other
+        store.remove_code(&email, &login_attempt_id2).await.unwrap();
+        let result = store.get_code_hash(&email, &login_attempt_id1).await;
+        assert_eq!(result.unwrap(), code1);
+
+        // Clean up
+        store.remove_codes(&email).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remove_codes() {
+        let mut store = create_test_store("remove_codes").await;
+        let email = parse_email("test_remove_all@example.com");
+        let other_email = parse_email("test_remove_other@example.com");
+        let login_attempt_ids = [LoginAttemptId::default(), LoginAttemptId::default()];
+        let other_login_attempt_id = LoginAttemptId::default();
+
+        for login_attempt_id in &login_attempt_ids {
+            store
+                .add_code(email.clone(), login_attempt_id.clone(), code_hash())
+                .await
+                .unwrap();
+            store
+                .record_failed_attempt(&email, login_attempt_id)
+                .await
+                .unwrap();
+        }
+        store
+            .add_code(
+                other_email.clone(),
+                other_login_attempt_id.clone(),
+                code_hash(),
+            )
+            .await
+            .unwrap();
+
+        store.remove_codes(&email).await.unwrap();
+
+        // Nothing of the login attempts of the email is left behind
+        let mut conn = store.conn.write().await;
+        for login_attempt_id in &login_attempt_ids {
+            for key in store.get_login_attempt_keys(&email, login_attempt_id) {
+                let exists: bool = conn.exists(&key).await.unwrap();
+                assert!(!exists, "{}", key);
+            }
+        }
+        let exists: bool = conn
+            .exists(store.get_login_attempts_key(&email))
+            .await
+            .unwrap();
+        assert!(!exists);
+        drop(conn);
+
+        // Other emails keep theirs
+        let result = store
+            .get_code_hash(&other_email, &other_login_attempt_id)
+            .await;
         assert!(result.is_ok());
-        let (retrieved_id, retrieved_code) = result.unwrap();
-        assert_eq!(retrieved_id, login_attempt_id2);
-        assert_eq!(retrieved_code, code2);
-        assert_ne!(retrieved_id, login_attempt_id1);
-        assert_ne!(retrieved_code, code1);
 
         // Clean up
-        let key = store.get_key(&email);
+        store.remove_codes(&other_email).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replace_code() {
+        let mut store = create_test_store("replace_code").await;
+        let email = parse_email("test_replace@example.com");
+        let login_attempt_id = LoginAttemptId::default();
+        let code = code_hash();
+
+        // Only pending login attempts get a new code
+        let result = store
+            .replace_code(&email, &login_attempt_id, code.clone())
+            .await;
+        assert_eq!(
+            result.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+
+        store
+            .add_code(email.clone(), login_attempt_id.clone(), code_hash())
+            .await
+            .unwrap();
+        store
+            .record_failed_attempt(&email, &login_attempt_id)
+            .await
+            .unwrap();
+        store
+            .replace_code(&email, &login_attempt_id, code.clone())
+            .await
+            .unwrap();
+
+        // The new code replaces the old one, but failed attempts carry over
+        let result = store.get_code_hash(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), code);
+        let result = store.record_failed_attempt(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), 2);
         let mut conn = store.conn.write().await;
-        let _: () = conn.del(&key).await.unwrap();
+        let ttl: i64 = conn
+            .ttl(store.get_key(&email, &login_attempt_id))
+            .await
+            .unwrap();
+        assert!(ttl > 0 && ttl <= store.ttl_seconds as i64);
+        drop(conn);
+
+        // Clean up
+        store.remove_codes(&email).await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_multiple_emails() {
-        let mut store = create_test_store("multiple_emails").await;
-        let email1 = Email::parse(Secret::new("test_multi1@example.com".to_string())).unwrap();
-        let email2 = Email::parse(Secret::new("test_multi2@example.com".to_string())).unwrap();
-        let login_attempt_id1 = LoginAttemptId::default();
-        let login_attempt_id2 = LoginAttemptId::default();
-        let code1 = TwoFACode::default();
-        let code2 = TwoFACode::default();
+    async fn test_record_failed_attempt() {
+        let mut store = create_test_store("record_failed_attempt").await;
+        let email = parse_email("test_failed@example.com");
+        let login_attempt_id = LoginAttemptId::default();
+
+        // Without a pending code there is nothing to count against
+        assert_eq!(
+            store
+                .record_failed_attempt(&email, &login_attempt_id)
+                .await
+                .unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
 
-        // Add codes for both emails
         store
-            .add_code(email1.clone(), login_attempt_id1.clone(), code1.clone())
+            .add_code(email.clone(), login_attempt_id.clone(), code_hash())
             .await
             .unwrap();
+        let result = store.record_failed_attempt(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), 1);
+        let result = store.record_failed_attempt(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), 2);
+
+        // Each login attempt counts on its own
+        let other_login_attempt_id = LoginAttemptId::default();
         store
-            .add_code(email2.clone(), login_attempt_id2.clone(), code2.clone())
+            .add_code(email.clone(), other_login_attempt_id.clone(), code_hash())
             .await
             .unwrap();
+        let result = store
+            .record_failed_attempt(&email, &other_login_attempt_id)
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        // Resends are counted apart from failed attempts
+        let result = store.record_resend(&email, &login_attempt_id).await;
+        assert_eq!(result.unwrap(), 1);
+
+        // Removing the code removes its counters
+        store.remove_code(&email, &login_attempt_id).await.unwrap();
+        let mut conn = store.conn.write().await;
+        for key in store.get_login_attempt_keys(&email, &login_attempt_id) {
+            let exists: bool = conn.exists(&key).await.unwrap();
+            assert!(!exists, "{}", key);
+        }
+        drop(conn);
 
-        // Verify both codes exist and are correct
-        let result1 = store.get_code(&email1).await;
-        assert!(result1.is_ok());
-        let (retrieved_id1, retrieved_code1) = result1.unwrap();
-        assert_eq!(retrieved_id1, login_attempt_id1);
-        assert_eq!(retrieved_code1, code1);
+        // Clean up
+        store.remove_codes(&email).await.unwrap();
+    }
 
-        let result2 = store.get_code(&email2).await;
-        assert!(result2.is_ok());
-        let (retrieved_id2, retrieved_code2) = result2.unwrap();
-        assert_eq!(retrieved_id2, login_attempt_id2);
-        assert_eq!(retrieved_code2, code2);
+    #[tokio::test]
+    async fn test_record_failed_email_attempt() {
+        let mut store = create_test_store("record_failed_email_attempt").await;
+        let email = parse_email("test_failed_email@example.com");
+
+        // Wrong guesses of every login attempt of the email add up
+        for expected in 1..=3 {
+            let result = store.record_failed_email_attempt(&email).await;
+            assert_eq!(result.unwrap(), expected);
+        }
+        let other_email = parse_email("test_failed_other_email@example.com");
+        let result = store.record_failed_email_attempt(&other_email).await;
+        assert_eq!(result.unwrap(), 1);
+
+        store.reset_failed_email_attempts(&email).await.unwrap();
+        let result = store.record_failed_email_attempt(&email).await;
+        assert_eq!(result.unwrap(), 1);
 
         // Clean up
-        let mut conn = store.conn.write().await;
-        let _: () = conn
-            .del(&[store.get_key(&email1), store.get_key(&email2)])
+        store.reset_failed_email_attempts(&email).await.unwrap();
+        store
+            .reset_failed_email_attempts(&other_email)
             .await
             .unwrap();
     }
@@ -308,11 +653,12 @@ mod tests {
     #[tokio::test]
     async fn test_remove_nonexistent_code() {
         let mut store = create_test_store("remove_nonexistent_code").await;
-        let email =
-            Email::parse(Secret::new("nonexistent_remove@example.com".to_string())).unwrap();
+        let email = parse_email("nonexistent_remove@example.com");
 
         // Should not error when removing non-existent code
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&email, &LoginAttemptId::default()).await;
+        assert!(result.is_ok());
+        let result = store.remove_codes(&email).await;
         assert!(result.is_ok());
     }
 }
@@ -0,0 +1,220 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::Context;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::domain::{
+    data_stores::{LoginAttemptId, WebauthnChallengeStore, WebauthnChallengeStoreError},
+    UserId, WebauthnCeremony, WebauthnChallenge,
+};
+
+pub struct RedisWebauthnChallengeStore {
+    conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+    key_prefix: Option<String>,
+    key_prefix_base: String,
+}
+
+impl RedisWebauthnChallengeStore {
+    #[tracing::instrument(name = "New Redis WebAuthn Challenge Store with Config", skip_all)]
+    pub fn new_with_config(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: None,
+            key_prefix_base,
+        }
+    }
+
+    #[tracing::instrument(
+        name = "New Redis WebAuthn Challenge Store with Config and Prefix",
+        skip_all
+    )]
+    pub fn new_with_config_and_prefix(
+        conn: Arc<RwLock<redis::aio::MultiplexedConnection>>,
+        key_prefix_base: String,
+        prefix: String,
+    ) -> Self {
+        Self {
+            conn,
+            key_prefix: Some(prefix),
+            key_prefix_base,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebauthnChallengeStore for RedisWebauthnChallengeStore {
+    #[tracing::instrument(name = "Add WebAuthn Challenge", skip_all)]
+    async fn add_challenge(
+        &mut self,
+        challenge: WebauthnChallenge,
+        ceremony: WebauthnCeremony,
+        ttl_seconds: u64,
+    ) -> Result<(), WebauthnChallengeStoreError> {
+        let record = serde_json::to_string(&CeremonyRecord::from(ceremony))
+            .wrap_err("failed to serialize WebAuthn ceremony")
+            .map_err(WebauthnChallengeStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(self.get_key(&challenge), record, ttl_seconds)
+            .await
+            .wrap_err("failed to set WebAuthn challenge in Redis")
+            .map_err(WebauthnChallengeStoreError::UnexpectedError)?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Take WebAuthn Challenge", skip_all)]
+    async fn take_challenge(
+        &mut self,
+        challenge: &WebauthnChallenge,
+    ) -> Result<WebauthnCeremony, WebauthnChallengeStoreError> {
+        // Reading and deleting the challenge at once keeps it single-use under concurrent
+        // requests
+        let record: Option<String> = self
+            .conn
+            .write()
+            .await
+            .get_del(self.get_key(challenge))
+            .await
+            .wrap_err("failed to take WebAuthn challenge from Redis")
+            .map_err(WebauthnChallengeStoreError::UnexpectedError)?;
+
+        let record = record.ok_or(WebauthnChallengeStoreError::ChallengeNotFound)?;
+        serde_json::from_str::<CeremonyRecord>(&record)
+            .wrap_err("failed to deserialize WebAuthn ceremony")
+            .map_err(WebauthnChallengeStoreError::UnexpectedError)?
+            .try_into()
+            .map_err(WebauthnChallengeStoreError::UnexpectedError)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "ceremony", rename_all = "snake_case")]
+enum CeremonyRecord {
+    Registration {
+        user_id: String,
+    },
+    SecondFactor {
+        user_id: String,
+        login_attempt_id: String,
+    },
+    Passwordless,
+}
+
+impl From<WebauthnCeremony> for CeremonyRecord {
+    fn from(ceremony: WebauthnCeremony) -> Self {
+        match ceremony {
+            WebauthnCeremony::Registration { user_id } => Self::Registration {
+                user_id: user_id.to_string(),
+            },
+            WebauthnCeremony::SecondFactor {
+                user_id,
+                login_attempt_id,
+            } => Self::SecondFactor {
+                user_id: user_id.to_string(),
+                login_attempt_id: login_attempt_id.as_ref().to_owned(),
+            },
+            WebauthnCeremony::Passwordless => Self::Passwordless,
+        }
+    }
+}
+
+impl TryFrom<CeremonyRecord> for WebauthnCeremony {
+    type Error = color_eyre::eyre::Report;
+
+    fn try_from(record: CeremonyRecord) -> Result<Self, Self::Error> {
+        Ok(match record {
+            CeremonyRecord::Registration { user_id } => Self::Registration {
+                user_id: UserId::parse(&user_id)?,
+            },
+            CeremonyRecord::SecondFactor {
+                user_id,
+                login_attempt_id,
+            } => Self::SecondFactor {
+                user_id: UserId::parse(&user_id)?,
+                login_attempt_id: LoginAttemptId::parse(login_attempt_id)?,
+            },
+            CeremonyRecord::Passwordless => Self::Passwordless,
+        })
+    }
+}
+
+impl RedisWebauthnChallengeStore {
+    #[tracing::instrument(name = "Get WebAuthn Challenge Key", skip_all)]
+    fn get_key(&self, challenge: &WebauthnChallenge) -> String {
+        match &self.key_prefix {
+            Some(prefix) => format!("{}{}{}", prefix, self.key_prefix_base, challenge.as_ref()),
+            None => format!("{}{}", self.key_prefix_base, challenge.as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    async fn create_test_store(test_prefix: &str) -> RedisWebauthnChallengeStore {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let conn = Arc::new(RwLock::new(conn));
+        RedisWebauthnChallengeStore::new_with_config_and_prefix(
+            conn,
+            settings.redis.webauthn_challenge_key_prefix,
+            format!("test_{}:", test_prefix),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_and_take_challenge() {
+        let mut store = create_test_store("add_and_take_challenge").await;
+
+        for ceremony in [
+            WebauthnCeremony::Registration {
+                user_id: UserId::default(),
+            },
+            WebauthnCeremony::SecondFactor {
+                user_id: UserId::default(),
+                login_attempt_id: LoginAttemptId::default(),
+            },
+            WebauthnCeremony::Passwordless,
+        ] {
+            let challenge = WebauthnChallenge::default();
+            store
+                .add_challenge(challenge.clone(), ceremony.clone(), 60)
+                .await
+                .unwrap();
+
+            assert_eq!(store.take_challenge(&challenge).await.unwrap(), ceremony);
+
+            // Challenges are single-use
+            assert_eq!(
+                store.take_challenge(&challenge).await.unwrap_err(),
+                WebauthnChallengeStoreError::ChallengeNotFound
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_take_unknown_challenge() {
+        let mut store = create_test_store("take_unknown_challenge").await;
+
+        let result = store.take_challenge(&WebauthnChallenge::default()).await;
+        assert_eq!(
+            result.unwrap_err(),
+            WebauthnChallengeStoreError::ChallengeNotFound
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Context, Result};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    config::HttpEmailConfig,
+    domain::{Email, EmailClient, EmailMessage},
+};
+
+#[derive(Debug, Error)]
+pub enum HttpEmailClientError {
+    #[error("Email provider request timed out")]
+    Timeout,
+    #[error("Failed to reach email provider")]
+    Network(#[source] reqwest::Error),
+    #[error("Email provider rejected the email with status {status}: {message}")]
+    Rejected { status: StatusCode, message: String },
+    #[error("Email provider unavailable, last response had status {status}")]
+    Unavailable { status: StatusCode },
+}
+
+// Client for Postmark-compatible email APIs
+pub struct HttpEmailClient {
+    http_client: reqwest::Client,
+    base_url: String,
+    api_token: Secret<String>,
+    sender: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl HttpEmailClient {
+    pub fn new(config: &HttpEmailConfig, sender: &str) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .build()
+            .wrap_err("failed to build HTTP client for email provider")?;
+
+        Ok(Self {
+            http_client,
+            base_url: config.base_url.trim_end_matches('/').to_owned(),
+            api_token: config.api_token.clone(),
+            sender: sender.to_owned(),
+            max_retries: config.max_retries,
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+        })
+    }
+
+    async fn send(&self, request: &SendEmailRequest<'_>) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            // Timeouts and network errors are not retried, as the email may have been sent
+            let response = self.post_email(request).await?;
+            let status = response.status();
+
+            if status.is_success() {
+                return Ok(());
+            }
+
+            if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                let message = response
+                    .json::<ErrorResponse>()
+                    .await
+                    .map(|body| body.message)
+                    .unwrap_or_default();
+                return Err(HttpEmailClientError::Rejected { status, message }.into());
+            }
+
+            if attempt >= self.max_retries {
+                return Err(HttpEmailClientError::Unavailable { status }.into());
+            }
+
+            let backoff = self.retry_backoff * 2u32.saturating_pow(attempt);
+            tracing::warn!(
+                "Email provider responded with status {}, retrying in {:?}",
+                status,
+                backoff
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
+    }
+
+    async fn post_email(
+        &self,
+        request: &SendEmailRequest<'_>,
+    ) -> Result<reqwest::Response, HttpEmailClientError> {
+        self.http_client
+            .post(format!("{}/email", self.base_url))
+            .header("X-Postmark-Server-Token", self.api_token.expose_secret())
+            .json(request)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    HttpEmailClientError::Timeout
+                } else {
+                    HttpEmailClientError::Network(e)
+                }
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for HttpEmailClient {
+    #[tracing::instrument(name = "Send Multipart Email over HTTP", skip_all)]
+    async fn send_multipart_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let request = SendEmailRequest {
+            from: &self.sender,
+            to: recipient.as_ref().expose_secret(),
+            subject: &message.subject,
+            text_body: &message.text_body,
+            html_body: &message.html_body,
+        };
+
+        self.send(&request).await
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text_body: &'a str,
+    html_body: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    message: String,
+}
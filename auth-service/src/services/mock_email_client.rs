@@ -1,4 +1,4 @@
-use crate::domain::{Email, EmailClient};
+use crate::domain::{Email, EmailClient, EmailMessage};
 use color_eyre::eyre::Result;
 use secrecy::ExposeSecret;
 
@@ -6,13 +6,13 @@ pub struct MockEmailClient;
 
 #[async_trait::async_trait]
 impl EmailClient for MockEmailClient {
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
+    async fn send_multipart_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
         // Our mock email client will simply log the recipient, subject, and content to standard output
         tracing::debug!(
             "Sending email to {} with subject: {} and content: {}",
             recipient.as_ref().expose_secret(),
-            subject,
-            content
+            message.subject,
+            message.text_body
         );
 
         Ok(())
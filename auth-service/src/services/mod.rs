@@ -1,7 +1,11 @@
 pub mod data_stores;
+pub mod http_email_client;
 pub mod mock_email_client;
 pub mod recaptcha_service;
+pub mod smtp_email_client;
 
 pub use data_stores::*;
+pub use http_email_client::*;
 pub use mock_email_client::*;
 pub use recaptcha_service::*;
+pub use smtp_email_client::*;
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Context, Result};
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use crate::{
+    config::{SmtpConfig, SmtpSecurity},
+    domain::{Email, EmailClient, EmailMessage},
+};
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(config: &SmtpConfig, sender: &str) -> Result<Self> {
+        let sender = sender.parse::<Mailbox>().wrap_err("invalid email sender")?;
+
+        let builder = match config.security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+                .wrap_err("failed to configure SMTP transport")?,
+            SmtpSecurity::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+                    .wrap_err("failed to configure SMTP transport")?
+            }
+            SmtpSecurity::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+            }
+        };
+
+        let mut builder = builder
+            .port(config.port)
+            .timeout(Some(Duration::from_secs(config.timeout_seconds)));
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.username.clone(),
+                config.password.expose_secret().clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Send Multipart Email over SMTP", skip_all)]
+    async fn send_multipart_email(&self, recipient: &Email, message: &EmailMessage) -> Result<()> {
+        let message = Message::builder()
+            .from(self.sender.clone())
+            .to(parse_recipient(recipient)?)
+            .subject(&message.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.clone(),
+                message.html_body.clone(),
+            ))
+            .wrap_err("failed to build email")?;
+
+        self.send(message).await
+    }
+}
+
+impl SmtpEmailClient {
+    async fn send(&self, message: Message) -> Result<()> {
+        self.transport
+            .send(message)
+            .await
+            .wrap_err("failed to send email over SMTP")?;
+
+        Ok(())
+    }
+}
+
+fn parse_recipient(recipient: &Email) -> Result<Mailbox> {
+    recipient
+        .as_ref()
+        .expose_secret()
+        .parse::<Mailbox>()
+        .wrap_err("invalid email recipient")
+}
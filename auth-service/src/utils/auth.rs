@@ -1,18 +1,32 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap};
 use axum_extra::extract::cookie::{Cookie, SameSite};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::Utc;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
-use secrecy::ExposeSecret;
+use jsonwebtoken::{decode, decode_header, encode, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
 
-use crate::app_state::BannedTokenStoreType;
-use crate::config::AuthConfig;
-use crate::domain::email::Email;
+use crate::app_state::{BannedTokenStoreType, KeyRingType, UserStoreType};
+use crate::config::{AdminConfig, AuthConfig};
+use crate::domain::{AuthAPIError, RefreshToken, UserId};
+use crate::utils::{key_ring::sync_key_ring, signing_key::SigningKey};
 
-// Create cookie with a new JWT auth token
+// Create cookie with a new JWT auth token for the given session, signed by the currently active key
 #[tracing::instrument(name = "Generate Auth Cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email, auth_config: &AuthConfig) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email, auth_config)?;
+pub async fn generate_auth_cookie(
+    user_id: &UserId,
+    session_epoch: i64,
+    session_id: &str,
+    key_ring: &KeyRingType,
+    auth_config: &AuthConfig,
+) -> Result<Cookie<'static>> {
+    sync_key_ring(key_ring).await?;
+    let key_ring = key_ring.read().await;
+    let signing_key = key_ring.signing_key(Utc::now().timestamp())?;
+    let token = generate_auth_token(user_id, session_epoch, session_id, signing_key, auth_config)?;
     Ok(create_auth_cookie(
         token,
         auth_config.jwt_cookie_name.clone(),
@@ -31,14 +45,66 @@ fn create_auth_cookie(token: String, cookie_name: String) -> Cookie<'static> {
     cookie
 }
 
+// Create cookie and set the value to the passed-in refresh token
+#[tracing::instrument(name = "Create Refresh Cookie", skip_all)]
+pub fn create_refresh_cookie(
+    token: &RefreshToken,
+    auth_config: &AuthConfig,
+) -> Result<Cookie<'static>> {
+    let max_age = i64::try_from(auth_config.refresh_token_ttl_seconds)
+        .wrap_err("failed to cast refresh token TTL to i64")?;
+
+    let cookie = Cookie::build((auth_config.refresh_cookie_name.clone(), token.to_string()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(max_age))
+        .build();
+
+    Ok(cookie)
+}
+
+// Create cookie remembering a trusted device, kept for as long as the device is trusted
+#[tracing::instrument(name = "Create Trusted Device Cookie", skip_all)]
+pub fn create_trusted_device_cookie(
+    value: String,
+    auth_config: &AuthConfig,
+) -> Result<Cookie<'static>> {
+    let max_age = i64::try_from(auth_config.trusted_device_days * 86400)
+        .wrap_err("failed to cast trusted device TTL to i64")?;
+
+    let cookie = Cookie::build((auth_config.trusted_device_cookie_name.clone(), value))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(max_age))
+        .build();
+
+    Ok(cookie)
+}
+
 // Create JWT auth token
 #[tracing::instrument(name = "Generate Auth Token", skip_all)]
-fn generate_auth_token(email: &Email, auth_config: &AuthConfig) -> Result<String> {
+fn generate_auth_token(
+    user_id: &UserId,
+    session_epoch: i64,
+    session_id: &str,
+    signing_key: &SigningKey,
+    auth_config: &AuthConfig,
+) -> Result<String> {
     let delta = chrono::Duration::try_seconds(auth_config.token_ttl_seconds)
         .wrap_err("failed to create token duration")?;
 
+    let now = Utc::now();
+
+    // Cast issue time to a usize, which is what Claims expects
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("failed to cast issue time to usize")?;
+
     // Create JWT expiration time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(eyre!("failed to add 10 minutes to current time"))?
         .timestamp();
@@ -49,21 +115,54 @@ fn generate_auth_token(email: &Email, auth_config: &AuthConfig) -> Result<String
         exp
     ))?;
 
-    let sub = email.as_ref().expose_secret().to_owned();
-
-    let claims = Claims { sub, exp };
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp,
+        iss: auth_config.issuer.clone(),
+        aud: auth_config.audience.clone(),
+        iat,
+        nbf: iat,
+        jti: Some(Uuid::new_v4().to_string()),
+        sid: Some(session_id.to_owned()),
+        session_epoch,
+    };
 
-    create_token(&claims, auth_config)
+    create_token(&claims, signing_key)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid by decoding it using the key named by its `kid` header
 #[tracing::instrument(name = "Validate Token", skip_all)]
 pub async fn validate_token(
     token: &str,
     banned_token_store: &BannedTokenStoreType,
+    user_store: &UserStoreType,
+    key_ring: &KeyRingType,
     auth_config: &AuthConfig,
 ) -> Result<Claims> {
-    match banned_token_store.read().await.contains_token(token).await {
+    // Reject tokens whose key is unknown or already retired
+    let header = decode_header(token).wrap_err("failed to decode token")?;
+    sync_key_ring(key_ring).await?;
+    let claims = {
+        let key_ring = key_ring.read().await;
+        let verification_key = key_ring
+            .verification_key(header.kid.as_deref(), Utc::now().timestamp())
+            .ok_or(eyre!("failed to decode token"))?;
+
+        decode::<Claims>(
+            token,
+            verification_key.decoding_key(),
+            &token_validation(verification_key, auth_config),
+        )
+        .map(|data| data.claims)
+        .wrap_err("failed to decode token")?
+    };
+
+    match banned_token_store
+        .read()
+        .await
+        .contains_token(&claims.token_id(token))
+        .await
+    {
         Ok(value) => {
             if value {
                 return Err(eyre!("token is banned"));
@@ -72,30 +171,142 @@ pub async fn validate_token(
         Err(e) => return Err(e.into()),
     }
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(auth_config.jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    // Revoking a session bans its id, which rejects every token issued for it
+    if let Some(session_id) = &claims.sid {
+        if banned_token_store
+            .read()
+            .await
+            .contains_token(session_id)
+            .await?
+        {
+            return Err(eyre!("session is revoked"));
+        }
+    }
+
+    // Reject tokens issued before the user last logged out everywhere, or whose user is gone
+    let user_id = claims.user_id()?;
+    let session_epoch = user_store
+        .read()
+        .await
+        .get_session_epoch(&user_id)
+        .await
+        .wrap_err("failed to get session epoch")?;
+    if claims.session_epoch != session_epoch {
+        return Err(eyre!("token is revoked"));
+    }
+
+    Ok(claims)
+}
+
+// Ban a validated token until it would have expired on its own
+#[tracing::instrument(name = "Ban Token", skip_all)]
+pub async fn ban_token(
+    token: &str,
+    claims: &Claims,
+    banned_token_store: &BannedTokenStoreType,
+    auth_config: &AuthConfig,
+) -> Result<()> {
+    // Tokens are still accepted for `leeway_seconds` after `exp`
+    let expires_at = claims.exp as u64 + auth_config.leeway_seconds;
+
+    banned_token_store
+        .write()
+        .await
+        .store_token(claims.token_id(token), expires_at)
+        .await?;
+    Ok(())
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
+// Ban the id of a session until every token issued for it so far has expired. The
+// session's refresh token family must be revoked too, so no new tokens get issued.
+#[tracing::instrument(name = "Ban Session", skip_all)]
+pub async fn ban_session(
+    session_id: &str,
+    banned_token_store: &BannedTokenStoreType,
+    auth_config: &AuthConfig,
+) -> Result<()> {
+    let token_ttl_seconds =
+        u64::try_from(auth_config.token_ttl_seconds).wrap_err("failed to cast token TTL to u64")?;
+    let expires_at = Utc::now().timestamp() as u64 + token_ttl_seconds + auth_config.leeway_seconds;
+
+    banned_token_store
+        .write()
+        .await
+        .store_token(session_id.to_owned(), expires_at)
+        .await?;
+    Ok(())
+}
+
+// Only accept tokens issued by us for our audience, allowing for some clock skew
+fn token_validation(verification_key: &SigningKey, auth_config: &AuthConfig) -> Validation {
+    let mut validation = verification_key.validation();
+    validation.set_issuer(&[&auth_config.issuer]);
+    validation.set_audience(&[&auth_config.audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    validation.validate_nbf = true;
+    validation.leeway = auth_config.leeway_seconds;
+    validation
+}
+
+// Create JWT auth token by encoding claims using the signing key
 #[tracing::instrument(name = "Create Token", skip_all)]
-fn create_token(claims: &Claims, auth_config: &AuthConfig) -> Result<String> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(auth_config.jwt_secret.as_bytes()),
-    )
-    .wrap_err("failed to create token")
+fn create_token(claims: &Claims, signing_key: &SigningKey) -> Result<String> {
+    encode(&signing_key.header(), &claims, signing_key.encoding_key())
+        .wrap_err("failed to create token")
+}
+
+// Check the `Authorization: Bearer` header of an admin request against the configured API key
+#[tracing::instrument(name = "Authorize Admin", skip_all)]
+pub fn authorize_admin(
+    headers: &HeaderMap,
+    admin_config: &AdminConfig,
+) -> Result<(), AuthAPIError> {
+    let api_key = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    // An empty API key disables the admin routes
+    if admin_config.api_key.is_empty()
+        || !bool::from(api_key.as_bytes().ct_eq(admin_config.api_key.as_bytes()))
+    {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    // Id of the user the token was issued to
     pub sub: String,
     pub exp: usize,
+    pub iss: String,
+    pub aud: String,
+    pub iat: usize,
+    pub nbf: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    // Id of the session the token was issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub session_epoch: i64,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Result<UserId> {
+        UserId::parse(&self.sub).wrap_err("token subject is not a valid user id")
+    }
+
+    // Identifier under which the token is banned. Tokens issued before `jti` was
+    // introduced are identified by a digest of the token itself.
+    pub fn token_id(&self, token: &str) -> String {
+        match &self.jti {
+            Some(jti) => jti.clone(),
+            None => URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes())),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -103,17 +314,76 @@ mod tests {
     use super::*;
     use crate::{
         config::{AuthConfig, Settings},
-        services::RedisBannedTokenStore,
+        domain::{Email, Password, User, UserStore},
+        services::{HashmapUserStore, RedisBannedTokenStore},
+        utils::key_ring::KeyRing,
     };
     use secrecy::Secret;
     use std::sync::Arc;
     use tokio::sync::RwLock;
 
+    const TEST_SESSION_ID: &str = "test-session";
+    // Ids of the users of the test user store
+    const TEST_USER_IDS: [&str; 3] = [
+        "00000000-0000-4000-8000-000000000001",
+        "00000000-0000-4000-8000-000000000002",
+        "00000000-0000-4000-8000-000000000003",
+    ];
+
+    fn test_user_id(index: usize) -> UserId {
+        UserId::parse(TEST_USER_IDS[index]).unwrap()
+    }
+
     fn create_test_auth_config() -> AuthConfig {
         let settings = Settings::new().expect("Failed to load test configuration");
         settings.auth
     }
 
+    fn create_test_key_ring(auth_config: &AuthConfig) -> KeyRingType {
+        Arc::new(RwLock::new(
+            KeyRing::from_config(auth_config).expect("Failed to load key ring"),
+        ))
+    }
+
+    async fn create_test_user_store() -> UserStoreType {
+        let mut user_store = HashmapUserStore::default();
+        for (index, email) in ["test@example.com", "test1@example.com", "test2@example.com"]
+            .into_iter()
+            .enumerate()
+        {
+            let email = Email::parse(Secret::new(email.to_owned())).unwrap();
+            let password = Password::parse(Secret::new("Password123!".to_owned())).unwrap();
+            let mut user = User::new(email, password, false);
+            user.id = test_user_id(index);
+            user_store.add_user(user).await.unwrap();
+        }
+        Arc::new(RwLock::new(user_store))
+    }
+
+    async fn generate_test_token(
+        user_id: &UserId,
+        user_store: &UserStoreType,
+        key_ring: &KeyRingType,
+        auth_config: &AuthConfig,
+    ) -> String {
+        let session_epoch = user_store
+            .read()
+            .await
+            .get_session_epoch(user_id)
+            .await
+            .unwrap();
+        let key_ring = key_ring.read().await;
+        let signing_key = key_ring.signing_key(Utc::now().timestamp()).unwrap();
+        generate_auth_token(
+            user_id,
+            session_epoch,
+            TEST_SESSION_ID,
+            signing_key,
+            auth_config,
+        )
+        .unwrap()
+    }
+
     async fn create_test_banned_token_store(test_name: &str) -> BannedTokenStoreType {
         let settings = Settings::new().expect("Failed to load test configuration");
         let conn = crate::get_redis_connection(
@@ -127,7 +397,6 @@ mod tests {
         Arc::new(RwLock::new(
             RedisBannedTokenStore::new_with_config_and_prefix(
                 conn,
-                settings.redis.banned_token_ttl_seconds,
                 settings.redis.banned_token_key_prefix,
                 format!("test_{}:", test_name),
             ),
@@ -136,9 +405,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = test_user_id(0);
         let auth_config = create_test_auth_config();
-        let cookie = generate_auth_cookie(&email, &auth_config).unwrap();
+        let key_ring = create_test_key_ring(&auth_config);
+        let cookie = generate_auth_cookie(&user_id, 1, TEST_SESSION_ID, &key_ring, &auth_config)
+            .await
+            .unwrap();
         assert_eq!(cookie.name(), auth_config.jwt_cookie_name);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -160,24 +432,35 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = test_user_id(0);
         let auth_config = create_test_auth_config();
-        let result = generate_auth_token(&email, &auth_config).unwrap();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let result = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = test_user_id(0);
         let auth_config = create_test_auth_config();
-        let token = generate_auth_token(&email, &auth_config).unwrap();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
         let banned_token_store =
             create_test_banned_token_store("validate_token_with_valid_token").await;
 
-        let result = validate_token(&token, &banned_token_store, &auth_config)
-            .await
-            .unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.sub, TEST_USER_IDS[0]);
+        assert_eq!(result.user_id().unwrap(), user_id);
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -191,61 +474,397 @@ mod tests {
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
         let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
         let banned_token_store =
             create_test_banned_token_store("validate_token_with_invalid_token").await;
 
-        let result = validate_token(&token, &banned_token_store, &auth_config).await;
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().to_string(), "failed to decode token");
     }
 
+    #[tokio::test]
+    async fn test_validate_token_signed_with_other_key() {
+        let user_id = test_user_id(0);
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let other_key = SigningKey::from_secret(b"some-other-secret");
+        let token =
+            generate_auth_token(&user_id, 1, TEST_SESSION_ID, &other_key, &auth_config).unwrap();
+        let banned_token_store =
+            create_test_banned_token_store("validate_token_signed_with_other_key").await;
+
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "failed to decode token");
+    }
+
+    fn create_test_claims(auth_config: &AuthConfig) -> Claims {
+        let now = Utc::now().timestamp() as usize;
+        Claims {
+            sub: TEST_USER_IDS[0].to_owned(),
+            exp: now + 600,
+            iss: auth_config.issuer.clone(),
+            aud: auth_config.audience.clone(),
+            iat: now,
+            nbf: now,
+            jti: Some(Uuid::new_v4().to_string()),
+            sid: None,
+            // First user of the test user store
+            session_epoch: 1,
+        }
+    }
+
+    async fn validate_test_claims(claims: &Claims, test_name: &str) -> Result<Claims> {
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token = {
+            let key_ring = key_ring.read().await;
+            let signing_key = key_ring.signing_key(Utc::now().timestamp()).unwrap();
+            create_token(claims, signing_key).unwrap()
+        };
+        let banned_token_store = create_test_banned_token_store(test_name).await;
+        validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_generate_auth_token_sets_standard_claims() {
+        let user_id = test_user_id(0);
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token1 = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let token2 = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let banned_token_store = create_test_banned_token_store("standard_claims").await;
+
+        let claims1 = validate_token(
+            &token1,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+        let claims2 = validate_token(
+            &token2,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(claims1.iss, auth_config.issuer);
+        assert_eq!(claims1.aud, auth_config.audience);
+        assert_eq!(claims1.nbf, claims1.iat);
+        assert_eq!(
+            claims1.exp,
+            claims1.iat + auth_config.token_ttl_seconds as usize
+        );
+        assert!(claims1.jti.is_some());
+        assert_ne!(claims1.jti, claims2.jti);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_wrong_issuer() {
+        let mut claims = create_test_claims(&create_test_auth_config());
+        claims.iss = "other-service".to_owned();
+
+        let result = validate_test_claims(&claims, "validate_token_with_wrong_issuer").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_email_subject() {
+        // Tokens issued before user ids were introduced carry the email of their user
+        let mut claims = create_test_claims(&create_test_auth_config());
+        claims.sub = "test@example.com".to_owned();
+
+        let result = validate_test_claims(&claims, "validate_token_with_email_subject").await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "token subject is not a valid user id"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_with_wrong_audience() {
+        let mut claims = create_test_claims(&create_test_auth_config());
+        claims.aud = "other-audience".to_owned();
+
+        let result = validate_test_claims(&claims, "validate_token_with_wrong_audience").await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_not_yet_valid() {
+        let auth_config = create_test_auth_config();
+        let mut claims = create_test_claims(&auth_config);
+
+        // Within the leeway the token is accepted, beyond it the token is rejected
+        claims.nbf += auth_config.leeway_seconds as usize / 2;
+        let result = validate_test_claims(&claims, "validate_token_nbf_within_leeway").await;
+        assert!(result.is_ok());
+
+        claims.nbf += auth_config.leeway_seconds as usize;
+        let result = validate_test_claims(&claims, "validate_token_not_yet_valid").await;
+        assert!(result.is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
-        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let user_id = test_user_id(0);
         let auth_config = create_test_auth_config();
-        let token = generate_auth_token(&email, &auth_config).unwrap();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
         let banned_token_store =
             create_test_banned_token_store("validate_token_with_banned_token").await;
 
         // First ban the token
-        banned_token_store
-            .write()
-            .await
-            .store_token(token.clone())
+        let claims = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+        ban_token(&token, &claims, &banned_token_store, &auth_config)
             .await
             .unwrap();
 
         // Then try to validate it
-        let result = validate_token(&token, &banned_token_store, &auth_config).await;
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().to_string(), "token is banned");
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_unbanned_token() {
-        let email1 = Email::parse(Secret::new("test1@example.com".to_owned())).unwrap();
-        let email2 = Email::parse(Secret::new("test2@example.com".to_owned())).unwrap();
+        let user_id1 = test_user_id(1);
+        let user_id2 = test_user_id(2);
         let auth_config = create_test_auth_config();
-        let token1 = generate_auth_token(&email1, &auth_config).unwrap();
-        let token2 = generate_auth_token(&email2, &auth_config).unwrap();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token1 = generate_test_token(&user_id1, &user_store, &key_ring, &auth_config).await;
+        let token2 = generate_test_token(&user_id2, &user_store, &key_ring, &auth_config).await;
         let banned_token_store =
             create_test_banned_token_store("validate_token_with_valid_unbanned_token").await;
 
         // Ban only token1
-        banned_token_store
-            .write()
-            .await
-            .store_token(token1.clone())
+        let claims1 = validate_token(
+            &token1,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+        ban_token(&token1, &claims1, &banned_token_store, &auth_config)
             .await
             .unwrap();
 
         // token2 should still be valid
-        let result = validate_token(&token2, &banned_token_store, &auth_config).await;
+        let result = validate_token(
+            &token2,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
         assert!(result.is_ok());
 
         // token1 should be banned
-        let result = validate_token(&token1, &banned_token_store, &auth_config).await;
+        let result = validate_token(
+            &token1,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().to_string(), "token is banned");
     }
+
+    #[tokio::test]
+    async fn test_ban_token_without_jti() {
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let mut claims = create_test_claims(&auth_config);
+        claims.jti = None;
+        let token = {
+            let key_ring = key_ring.read().await;
+            let signing_key = key_ring.signing_key(Utc::now().timestamp()).unwrap();
+            create_token(&claims, signing_key).unwrap()
+        };
+        let banned_token_store = create_test_banned_token_store("ban_token_without_jti").await;
+
+        // Tokens issued before `jti` was introduced are still accepted and can be banned
+        let claims = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await
+        .unwrap();
+        assert!(claims.jti.is_none());
+        ban_token(&token, &claims, &banned_token_store, &auth_config)
+            .await
+            .unwrap();
+
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "token is banned");
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_session_epoch_incremented() {
+        let user_id = test_user_id(0);
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let banned_token_store =
+            create_test_banned_token_store("validate_token_after_session_epoch_incremented").await;
+
+        user_store
+            .write()
+            .await
+            .increment_session_epoch(&user_id)
+            .await
+            .unwrap();
+
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "token is revoked");
+
+        // Tokens issued after the increment are accepted again
+        let token = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let result = validate_token(
+            &token,
+            &banned_token_store,
+            &user_store,
+            &key_ring,
+            &auth_config,
+        )
+        .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_banned_session() {
+        let user_id = test_user_id(0);
+        let auth_config = create_test_auth_config();
+        let key_ring = create_test_key_ring(&auth_config);
+        let user_store = create_test_user_store().await;
+        let token1 = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let token2 = generate_test_token(&user_id, &user_store, &key_ring, &auth_config).await;
+        let banned_token_store =
+            create_test_banned_token_store("validate_token_of_banned_session").await;
+
+        ban_session(TEST_SESSION_ID, &banned_token_store, &auth_config)
+            .await
+            .unwrap();
+
+        // Every token of the session is rejected, not only a single one
+        for token in [token1, token2] {
+            let result = validate_token(
+                &token,
+                &banned_token_store,
+                &user_store,
+                &key_ring,
+                &auth_config,
+            )
+            .await;
+            assert_eq!(result.unwrap_err().to_string(), "session is revoked");
+        }
+    }
+
+    #[test]
+    fn test_authorize_admin() {
+        let admin_config = AdminConfig {
+            api_key: "admin-key".to_owned(),
+        };
+
+        let mut headers = HeaderMap::new();
+        assert!(matches!(
+            authorize_admin(&headers, &admin_config),
+            Err(AuthAPIError::MissingToken)
+        ));
+
+        headers.insert(AUTHORIZATION, "Bearer wrong-key".parse().unwrap());
+        assert!(matches!(
+            authorize_admin(&headers, &admin_config),
+            Err(AuthAPIError::InvalidToken)
+        ));
+
+        headers.insert(AUTHORIZATION, "Bearer admin-key".parse().unwrap());
+        assert!(authorize_admin(&headers, &admin_config).is_ok());
+    }
+
+    #[test]
+    fn test_authorize_admin_disabled_without_api_key() {
+        let admin_config = AdminConfig {
+            api_key: String::new(),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer ".parse().unwrap());
+        assert!(matches!(
+            authorize_admin(&headers, &admin_config),
+            Err(AuthAPIError::InvalidToken)
+        ));
+    }
 }
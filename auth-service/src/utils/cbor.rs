@@ -0,0 +1,311 @@
+use color_eyre::eyre::{eyre, Result};
+
+// Nesting deeper than WebAuthn structures ever go is rejected, so hostile input cannot
+// exhaust the stack
+const MAX_DEPTH: usize = 16;
+
+/// The subset of CBOR (RFC 8949) WebAuthn uses for attestation objects and COSE keys.
+/// Floats, tags and indefinite lengths are not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Decode a value from the start of `input`, returning it and the number of bytes read
+    pub fn decode_prefix(input: &[u8]) -> Result<(Self, usize)> {
+        let mut decoder = Decoder { input, position: 0 };
+        let value = decoder.value(0)?;
+        Ok((value, decoder.position))
+    }
+
+    /// Decode a value that makes up all of `input`
+    pub fn decode(input: &[u8]) -> Result<Self> {
+        let (value, length) = Self::decode_prefix(input)?;
+        match length == input.len() {
+            true => Ok(value),
+            false => Err(eyre!("trailing bytes after CBOR value")),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.encode_into(&mut output);
+        output
+    }
+
+    fn encode_into(&self, output: &mut Vec<u8>) {
+        match self {
+            Self::Integer(n) if *n >= 0 => write_head(output, 0, *n as u64),
+            Self::Integer(n) => write_head(output, 1, (-1 - *n) as u64),
+            Self::Bytes(bytes) => {
+                write_head(output, 2, bytes.len() as u64);
+                output.extend_from_slice(bytes);
+            }
+            Self::Text(text) => {
+                write_head(output, 3, text.len() as u64);
+                output.extend_from_slice(text.as_bytes());
+            }
+            Self::Array(items) => {
+                write_head(output, 4, items.len() as u64);
+                items.iter().for_each(|item| item.encode_into(output));
+            }
+            Self::Map(entries) => {
+                write_head(output, 5, entries.len() as u64);
+                for (key, value) in entries {
+                    key.encode_into(output);
+                    value.encode_into(output);
+                }
+            }
+            Self::Bool(false) => output.push(0xf4),
+            Self::Bool(true) => output.push(0xf5),
+            Self::Null => output.push(0xf6),
+        }
+    }
+
+    /// Value of the entry of a map with the given key
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Self::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Self::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+fn write_head(output: &mut Vec<u8>, major_type: u8, argument: u64) {
+    let major_type = major_type << 5;
+    match argument {
+        0..=23 => output.push(major_type | argument as u8),
+        24..=0xff => output.extend([major_type | 24, argument as u8]),
+        0x100..=0xffff => {
+            output.push(major_type | 25);
+            output.extend((argument as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            output.push(major_type | 26);
+            output.extend((argument as u32).to_be_bytes());
+        }
+        _ => {
+            output.push(major_type | 27);
+            output.extend(argument.to_be_bytes());
+        }
+    }
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(eyre!("CBOR value is nested too deeply"));
+        }
+
+        let initial = self.take(1)?[0];
+        let major_type = initial >> 5;
+        let additional = initial & 0x1f;
+
+        if major_type == 7 {
+            return match additional {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 => Ok(Value::Null),
+                _ => Err(eyre!("unsupported CBOR simple value or float")),
+            };
+        }
+
+        let argument = self.argument(additional)?;
+        match major_type {
+            0 => Ok(Value::Integer(argument as i128)),
+            1 => Ok(Value::Integer(-1 - argument as i128)),
+            2 => Ok(Value::Bytes(self.take(self.length(argument)?)?.to_vec())),
+            3 => {
+                let bytes = self.take(self.length(argument)?)?;
+                let text = std::str::from_utf8(bytes).map_err(|_| eyre!("invalid CBOR text"))?;
+                Ok(Value::Text(text.to_owned()))
+            }
+            4 => {
+                // Every item takes at least a byte, which bounds the allocation
+                let count = self.length(argument)?;
+                let mut items = Vec::with_capacity(count);
+                for _ in 0..count {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(items))
+            }
+            5 => {
+                let count = self.length(argument)?;
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key = self.value(depth + 1)?;
+                    let value = self.value(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Ok(Value::Map(entries))
+            }
+            _ => Err(eyre!("unsupported CBOR tag")),
+        }
+    }
+
+    fn argument(&mut self, additional: u8) -> Result<u64> {
+        let size = match additional {
+            0..=23 => return Ok(additional as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(eyre!("unsupported CBOR length encoding")),
+        };
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0u64, |argument, byte| argument << 8 | *byte as u64))
+    }
+
+    fn length(&self, argument: u64) -> Result<usize> {
+        match usize::try_from(argument) {
+            Ok(length) if length <= self.input.len() - self.position => Ok(length),
+            _ => Err(eyre!("CBOR length exceeds input")),
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8]> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.input.len())
+            .ok_or_else(|| eyre!("truncated CBOR input"))?;
+        let bytes = &self.input[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_and_encode() {
+        // Examples from appendix A of RFC 8949
+        for (encoded, value) in [
+            ("00", Value::Integer(0)),
+            ("17", Value::Integer(23)),
+            ("1818", Value::Integer(24)),
+            ("1903e8", Value::Integer(1000)),
+            ("1b000000e8d4a51000", Value::Integer(1_000_000_000_000)),
+            ("20", Value::Integer(-1)),
+            ("3863", Value::Integer(-100)),
+            ("4401020304", Value::Bytes(vec![1, 2, 3, 4])),
+            ("6449455446", Value::Text("IETF".to_owned())),
+            ("f4", Value::Bool(false)),
+            ("f6", Value::Null),
+            (
+                "8301820203820405",
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+                    Value::Array(vec![Value::Integer(4), Value::Integer(5)]),
+                ]),
+            ),
+            (
+                "a201020304",
+                Value::Map(vec![
+                    (Value::Integer(1), Value::Integer(2)),
+                    (Value::Integer(3), Value::Integer(4)),
+                ]),
+            ),
+        ] {
+            assert_eq!(Value::decode(&hex(encoded)).unwrap(), value);
+            assert_eq!(value.encode(), hex(encoded));
+        }
+    }
+
+    #[test]
+    fn test_decode_prefix_reports_length() {
+        let (value, length) = Value::decode_prefix(&hex("4401020304ff")).unwrap();
+        assert_eq!(value, Value::Bytes(vec![1, 2, 3, 4]));
+        assert_eq!(length, 5);
+        assert!(Value::decode(&hex("4401020304ff")).is_err());
+    }
+
+    #[test]
+    fn test_reject_malformed_input() {
+        for encoded in [
+            "",
+            // Byte string longer than the input
+            "4501020304",
+            // Array claiming more items than there are bytes
+            "9bffffffffffffffff",
+            // Tag and float
+            "c11a514b67b0",
+            "f93c00",
+            // Indefinite length
+            "5f42010243030405ff",
+            // Invalid UTF-8
+            "62c328",
+        ] {
+            assert!(Value::decode(&hex(encoded)).is_err(), "{}", encoded);
+        }
+
+        let nested = [vec![0x81; MAX_DEPTH + 2], vec![0x00]].concat();
+        assert!(Value::decode(&nested).is_err());
+    }
+
+    #[test]
+    fn test_map_lookup() {
+        let map = Value::Map(vec![
+            (
+                Value::Text("fmt".to_owned()),
+                Value::Text("none".to_owned()),
+            ),
+            (Value::Integer(-2), Value::Bytes(vec![7])),
+        ]);
+        assert_eq!(
+            map.get(&Value::Text("fmt".to_owned()))
+                .and_then(Value::as_text),
+            Some("none")
+        );
+        assert_eq!(
+            map.get(&Value::Integer(-2)).and_then(Value::as_bytes),
+            Some([7u8].as_slice())
+        );
+        assert!(map.get(&Value::Integer(3)).is_none());
+    }
+}
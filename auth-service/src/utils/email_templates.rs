@@ -0,0 +1,392 @@
+use askama::Template;
+use color_eyre::eyre::{Context, Result};
+
+use crate::{
+    config::EmailConfig,
+    domain::{EmailMessage, EmailToken},
+};
+
+// Emails sent by the service, each rendered to an HTML and a plain-text body
+pub enum EmailTemplate<'a> {
+    TwoFACode { code: &'a str },
+    VerifyEmail { link: &'a str },
+    ResetPassword { link: &'a str },
+    MagicLink { link: &'a str },
+    PasswordChanged,
+    ConfirmEmailChange { link: &'a str },
+    EmailChangeRequested { link: &'a str, new_email: &'a str },
+    RecoveryCodeUsed { remaining: usize },
+}
+
+impl EmailTemplate<'_> {
+    pub fn subject(&self, config: &EmailConfig) -> String {
+        match self {
+            Self::TwoFACode { .. } => format!("Your {} login code", config.product_name),
+            Self::VerifyEmail { .. } => {
+                format!("Verify your email address for {}", config.product_name)
+            }
+            Self::ResetPassword { .. } => {
+                format!("Reset your {} password", config.product_name)
+            }
+            Self::MagicLink { .. } => format!("Your {} login link", config.product_name),
+            Self::PasswordChanged => format!("Your {} password was changed", config.product_name),
+            Self::ConfirmEmailChange { .. } => {
+                format!("Confirm your new email address for {}", config.product_name)
+            }
+            Self::EmailChangeRequested { .. } => {
+                format!(
+                    "Your {} email address is being changed",
+                    config.product_name
+                )
+            }
+            Self::RecoveryCodeUsed { .. } => {
+                format!("A {} recovery code was used", config.product_name)
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Render Email", skip_all)]
+    pub fn render(&self, config: &EmailConfig) -> Result<EmailMessage> {
+        let (html_body, text_body) = match *self {
+            Self::TwoFACode { code } => (
+                TwoFACodeHtml { config, code }.render(),
+                TwoFACodeText { config, code }.render(),
+            ),
+            Self::VerifyEmail { link } => (
+                VerifyEmailHtml { config, link }.render(),
+                VerifyEmailText { config, link }.render(),
+            ),
+            Self::ResetPassword { link } => (
+                ResetPasswordHtml { config, link }.render(),
+                ResetPasswordText { config, link }.render(),
+            ),
+            Self::MagicLink { link } => (
+                MagicLinkHtml { config, link }.render(),
+                MagicLinkText { config, link }.render(),
+            ),
+            Self::PasswordChanged => (
+                PasswordChangedHtml { config }.render(),
+                PasswordChangedText { config }.render(),
+            ),
+            Self::ConfirmEmailChange { link } => (
+                ConfirmEmailChangeHtml { config, link }.render(),
+                ConfirmEmailChangeText { config, link }.render(),
+            ),
+            Self::EmailChangeRequested { link, new_email } => (
+                EmailChangeRequestedHtml {
+                    config,
+                    link,
+                    new_email,
+                }
+                .render(),
+                EmailChangeRequestedText {
+                    config,
+                    link,
+                    new_email,
+                }
+                .render(),
+            ),
+            Self::RecoveryCodeUsed { remaining } => (
+                RecoveryCodeUsedHtml { config, remaining }.render(),
+                RecoveryCodeUsedText { config, remaining }.render(),
+            ),
+        };
+
+        Ok(EmailMessage {
+            subject: self.subject(config),
+            html_body: html_body.wrap_err("failed to render HTML email")?,
+            text_body: text_body.wrap_err("failed to render plain-text email")?,
+        })
+    }
+}
+
+// Link to the frontend carrying an email token in the `param` query parameter
+pub fn email_link(config: &EmailConfig, param: &str, token: &EmailToken) -> String {
+    format!(
+        "{}/?{}={}",
+        config.link_base_url.trim_end_matches('/'),
+        param,
+        token.as_ref()
+    )
+}
+
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    config: &'a EmailConfig,
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    config: &'a EmailConfig,
+    code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/verify_email.html")]
+struct VerifyEmailHtml<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/verify_email.txt")]
+struct VerifyEmailText<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/reset_password.html")]
+struct ResetPasswordHtml<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/reset_password.txt")]
+struct ResetPasswordText<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/magic_link.html")]
+struct MagicLinkHtml<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/magic_link.txt")]
+struct MagicLinkText<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/password_changed.html")]
+struct PasswordChangedHtml<'a> {
+    config: &'a EmailConfig,
+}
+
+#[derive(Template)]
+#[template(path = "emails/password_changed.txt")]
+struct PasswordChangedText<'a> {
+    config: &'a EmailConfig,
+}
+
+#[derive(Template)]
+#[template(path = "emails/confirm_email_change.html")]
+struct ConfirmEmailChangeHtml<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/confirm_email_change.txt")]
+struct ConfirmEmailChangeText<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/email_change_requested.html")]
+struct EmailChangeRequestedHtml<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+    new_email: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/email_change_requested.txt")]
+struct EmailChangeRequestedText<'a> {
+    config: &'a EmailConfig,
+    link: &'a str,
+    new_email: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/recovery_code_used.html")]
+struct RecoveryCodeUsedHtml<'a> {
+    config: &'a EmailConfig,
+    remaining: usize,
+}
+
+#[derive(Template)]
+#[template(path = "emails/recovery_code_used.txt")]
+struct RecoveryCodeUsedText<'a> {
+    config: &'a EmailConfig,
+    remaining: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    #[test]
+    fn test_render_two_fa_code_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+
+        let message = EmailTemplate::TwoFACode { code: "123456" }
+            .render(config)
+            .unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("Your {} login code", config.product_name)
+        );
+        for body in [&message.html_body, &message.text_body] {
+            assert!(body.contains("123456"));
+            assert!(body.contains(&config.product_name));
+            assert!(body.contains(&config.support_link));
+        }
+        assert!(message.html_body.starts_with("<!DOCTYPE html>"));
+        assert!(!message.text_body.contains('<'));
+    }
+
+    #[test]
+    fn test_render_verify_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+        let link = "http://localhost/auth/?verify_email_token=abc&x=1";
+
+        let message = EmailTemplate::VerifyEmail { link }.render(config).unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("Verify your email address for {}", config.product_name)
+        );
+        assert!(message.text_body.contains(link));
+        assert!(message
+            .html_body
+            .contains("href=\"http://localhost/auth/?verify_email_token=abc&amp;x=1\""));
+    }
+
+    #[test]
+    fn test_render_reset_password_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+        let link = "http://localhost/auth/?reset_password_token=abc";
+
+        let message = EmailTemplate::ResetPassword { link }
+            .render(config)
+            .unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("Reset your {} password", config.product_name)
+        );
+        assert!(message.text_body.contains(link));
+        assert!(message.html_body.contains(link));
+    }
+
+    #[test]
+    fn test_render_magic_link_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+        let link = "http://localhost/auth/?magic_link_token=abc";
+
+        let message = EmailTemplate::MagicLink { link }.render(config).unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("Your {} login link", config.product_name)
+        );
+        for body in [&message.html_body, &message.text_body] {
+            assert!(body.contains(link));
+            assert!(body.contains("only be used once"));
+        }
+    }
+
+    #[test]
+    fn test_render_password_changed_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+
+        let message = EmailTemplate::PasswordChanged.render(config).unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("Your {} password was changed", config.product_name)
+        );
+        for body in [&message.html_body, &message.text_body] {
+            assert!(body.contains("was just changed"));
+            assert!(body.contains(&config.support_link));
+        }
+    }
+
+    #[test]
+    fn test_render_email_change_emails() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+        let confirm_link = "http://localhost/auth/?confirm_email_change_token=abc";
+        let cancel_link = "http://localhost/auth/?cancel_email_change_token=def";
+
+        let message = EmailTemplate::ConfirmEmailChange { link: confirm_link }
+            .render(config)
+            .unwrap();
+        assert_eq!(
+            message.subject,
+            format!("Confirm your new email address for {}", config.product_name)
+        );
+        assert!(message.text_body.contains(confirm_link));
+        assert!(message.html_body.contains(confirm_link));
+
+        let message = EmailTemplate::EmailChangeRequested {
+            link: cancel_link,
+            new_email: "new@example.com",
+        }
+        .render(config)
+        .unwrap();
+        assert_eq!(
+            message.subject,
+            format!(
+                "Your {} email address is being changed",
+                config.product_name
+            )
+        );
+        for body in [&message.html_body, &message.text_body] {
+            assert!(body.contains(cancel_link));
+            assert!(body.contains("new@example.com"));
+        }
+    }
+
+    #[test]
+    fn test_render_recovery_code_used_email() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let config = &settings.email;
+
+        let message = EmailTemplate::RecoveryCodeUsed { remaining: 7 }
+            .render(config)
+            .unwrap();
+
+        assert_eq!(
+            message.subject,
+            format!("A {} recovery code was used", config.product_name)
+        );
+        for body in [&message.html_body, &message.text_body] {
+            assert!(body.contains("7 recovery codes left"));
+            assert!(body.contains(&config.support_link));
+        }
+    }
+
+    #[test]
+    fn test_html_email_escapes_variables() {
+        let settings = Settings::new().expect("Failed to load configuration");
+        let mut config = settings.email;
+        config.product_name = "<script>".to_owned();
+
+        let message = EmailTemplate::TwoFACode { code: "123456" }
+            .render(&config)
+            .unwrap();
+
+        assert!(!message.html_body.contains("<script>"));
+        assert!(message.html_body.contains("&lt;script&gt;"));
+        assert!(message.text_body.contains("<script>"));
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{eyre, Report, Result};
+use jsonwebtoken::jwk::Jwk;
+use thiserror::Error;
+
+use crate::app_state::{KeyRingType, SigningKeyStoreType};
+use crate::config::AuthConfig;
+use crate::domain::KeyPromotion;
+use crate::utils::signing_key::SigningKey;
+
+#[derive(Debug, Error)]
+pub enum KeyRingError {
+    #[error("Signing key not found")]
+    KeyNotFound,
+    #[error("Signing key is retired")]
+    KeyRetired,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for KeyRingError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::KeyNotFound, Self::KeyNotFound)
+                | (Self::KeyRetired, Self::KeyRetired)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+struct KeyRingEntry {
+    key: SigningKey,
+    activates_at: i64,
+    retires_at: Option<i64>,
+}
+
+impl KeyRingEntry {
+    fn is_retired(&self, now: i64) -> bool {
+        self.retires_at.is_some_and(|retires_at| retires_at <= now)
+    }
+
+    fn is_active(&self, now: i64) -> bool {
+        self.activates_at <= now && !self.is_retired(now)
+    }
+}
+
+// The set of keys used for JWTs. The most recently activated key signs new tokens,
+// while every key that has not retired yet is still accepted when verifying them,
+// so rotating keys does not invalidate sessions that are already live.
+pub struct KeyRing {
+    keys: Vec<KeyRingEntry>,
+    promotion_store: Option<SigningKeyStoreType>,
+    // Number of promotions from the store applied to `keys`
+    applied_promotions: usize,
+}
+
+impl KeyRing {
+    // Build the key ring described by the auth configuration.
+    // Without explicit key pairs, tokens are signed with HS256 using the JWT secret.
+    #[tracing::instrument(name = "Load Key Ring", skip_all)]
+    pub fn from_config(auth_config: &AuthConfig) -> Result<Self> {
+        if auth_config.signing_keys.is_empty() {
+            return Ok(Self::new(vec![(
+                SigningKey::from_secret(auth_config.jwt_secret.as_bytes()),
+                i64::MIN,
+                None,
+            )]));
+        }
+
+        let mut kids = HashSet::new();
+        let mut keys = Vec::with_capacity(auth_config.signing_keys.len());
+        for key_config in &auth_config.signing_keys {
+            if !kids.insert(key_config.kid.as_str()) {
+                return Err(eyre!("duplicate signing key id: {}", key_config.kid));
+            }
+            keys.push((
+                SigningKey::from_key_config(key_config)?,
+                key_config.activates_at.unwrap_or(i64::MIN),
+                key_config.retires_at,
+            ));
+        }
+
+        Ok(Self::new(keys))
+    }
+
+    fn new(keys: Vec<(SigningKey, i64, Option<i64>)>) -> Self {
+        let keys = keys
+            .into_iter()
+            .map(|(key, activates_at, retires_at)| KeyRingEntry {
+                key,
+                activates_at,
+                retires_at,
+            })
+            .collect();
+        Self {
+            keys,
+            promotion_store: None,
+            applied_promotions: 0,
+        }
+    }
+
+    // Share promotions with every other instance through the given store. Promotions
+    // already in it are applied once the key ring is first synced.
+    pub fn with_promotion_store(mut self, promotion_store: SigningKeyStoreType) -> Self {
+        self.promotion_store = Some(promotion_store);
+        self
+    }
+
+    // Key used to sign new tokens at the given time
+    pub fn signing_key(&self, now: i64) -> Result<&SigningKey> {
+        self.active_entry(now)
+            .map(|entry| &entry.key)
+            .ok_or(eyre!("no active signing key"))
+    }
+
+    // Key used to verify a token carrying the given `kid`. Keys that are scheduled
+    // but not yet active are accepted too, so instances whose clocks run slightly
+    // ahead do not cause rejected tokens.
+    pub fn verification_key(&self, kid: Option<&str>, now: i64) -> Option<&SigningKey> {
+        self.keys
+            .iter()
+            .find(|entry| entry.key.kid() == kid && !entry.is_retired(now))
+            .map(|entry| &entry.key)
+    }
+
+    // Public keys of every key that is not retired yet
+    pub fn jwks(&self, now: i64) -> Vec<Jwk> {
+        self.keys
+            .iter()
+            .filter(|entry| !entry.is_retired(now))
+            .filter_map(|entry| entry.key.jwk().cloned())
+            .collect()
+    }
+
+    // Make the key with the given `kid` the signing key from now on. The previous signing
+    // key retires once `overlap_seconds` have passed, which should be at least the token
+    // lifetime plus the leeway so tokens it already signed stay valid until they expire.
+    // With a promotion store, the promotion is recorded there for every instance.
+    #[tracing::instrument(name = "Promote Signing Key", skip(self))]
+    pub async fn promote(
+        &mut self,
+        kid: &str,
+        now: i64,
+        overlap_seconds: i64,
+    ) -> Result<(), KeyRingError> {
+        // Check the key against the key ring the other instances have
+        self.sync().await.map_err(KeyRingError::UnexpectedError)?;
+
+        let promotion = KeyPromotion {
+            kid: kid.to_owned(),
+            promoted_at: now,
+            overlap_seconds,
+        };
+        self.promotable_index(&promotion)?;
+
+        match &self.promotion_store {
+            Some(promotion_store) => {
+                promotion_store
+                    .write()
+                    .await
+                    .add_promotion(promotion)
+                    .await
+                    .map_err(|e| KeyRingError::UnexpectedError(e.into()))?;
+                // Promotions made elsewhere in the meantime are applied in store order
+                self.sync().await.map_err(KeyRingError::UnexpectedError)
+            }
+            None => self.apply_promotion(&promotion),
+        }
+    }
+
+    // Apply the promotions recorded since the key ring was last synced
+    async fn sync(&mut self) -> Result<()> {
+        let promotions = self.pending_promotions().await?;
+        self.apply_promotions(self.applied_promotions, promotions);
+        Ok(())
+    }
+
+    async fn pending_promotions(&self) -> Result<Vec<KeyPromotion>> {
+        match &self.promotion_store {
+            Some(promotion_store) => Ok(promotion_store
+                .read()
+                .await
+                .get_promotions(self.applied_promotions)
+                .await?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    // Apply promotions fetched starting at index `from`, skipping any that were applied
+    // while they were being fetched
+    fn apply_promotions(&mut self, from: usize, promotions: Vec<KeyPromotion>) {
+        for (index, promotion) in (from..).zip(promotions) {
+            if index < self.applied_promotions {
+                continue;
+            }
+            // Only happens when instances are configured with different keys
+            if let Err(e) = self.apply_promotion(&promotion) {
+                tracing::warn!(kid = %promotion.kid, "Skipping signing key promotion: {}", e);
+            }
+            self.applied_promotions = index + 1;
+        }
+    }
+
+    fn apply_promotion(&mut self, promotion: &KeyPromotion) -> Result<(), KeyRingError> {
+        let index = self.promotable_index(promotion)?;
+
+        let now = promotion.promoted_at;
+        let retires_at = now.saturating_add(promotion.overlap_seconds);
+        for (i, entry) in self.keys.iter_mut().enumerate() {
+            if i != index && entry.is_active(now) {
+                entry.retires_at = Some(entry.retires_at.map_or(retires_at, |r| r.min(retires_at)));
+            }
+        }
+
+        let promoted = &mut self.keys[index];
+        promoted.activates_at = now;
+        promoted.retires_at = None;
+
+        Ok(())
+    }
+
+    fn promotable_index(&self, promotion: &KeyPromotion) -> Result<usize, KeyRingError> {
+        let index = self
+            .keys
+            .iter()
+            .position(|entry| entry.key.kid() == Some(promotion.kid.as_str()))
+            .ok_or(KeyRingError::KeyNotFound)?;
+
+        if self.keys[index].is_retired(promotion.promoted_at) {
+            return Err(KeyRingError::KeyRetired);
+        }
+
+        Ok(index)
+    }
+
+    fn active_entry(&self, now: i64) -> Option<&KeyRingEntry> {
+        self.keys
+            .iter()
+            .filter(|entry| entry.is_active(now))
+            .max_by_key(|entry| entry.activates_at)
+    }
+}
+
+// Pick up the promotions other instances have made. The store is read without holding
+// the key ring for writing, so verifying tokens only waits on it when there is news.
+#[tracing::instrument(name = "Sync Key Ring", skip_all)]
+pub async fn sync_key_ring(key_ring: &KeyRingType) -> Result<()> {
+    let (from, promotions) = {
+        let key_ring = key_ring.read().await;
+        (
+            key_ring.applied_promotions,
+            key_ring.pending_promotions().await?,
+        )
+    };
+
+    if !promotions.is_empty() {
+        key_ring.write().await.apply_promotions(from, promotions);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Settings, SigningKeyConfig};
+    use crate::services::RedisSigningKeyStore;
+    use jsonwebtoken::Algorithm;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn ed25519_key(kid: &str) -> SigningKey {
+        SigningKey::from_key_config(&SigningKeyConfig {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::EdDSA,
+            private_key: include_str!("../../tests/keys/ed25519_private.pem").to_owned(),
+            public_key: include_str!("../../tests/keys/ed25519_public.pem").to_owned(),
+            activates_at: None,
+            retires_at: None,
+        })
+        .unwrap()
+    }
+
+    fn test_key_ring() -> KeyRing {
+        KeyRing::new(vec![
+            (ed25519_key("old"), NOW - 200, Some(NOW - 100)),
+            (ed25519_key("current"), NOW - 100, None),
+            (ed25519_key("next"), NOW + 100, None),
+        ])
+    }
+
+    #[test]
+    fn test_signing_key_is_most_recently_activated() {
+        let key_ring = test_key_ring();
+        assert_eq!(key_ring.signing_key(NOW).unwrap().kid(), Some("current"));
+        assert_eq!(key_ring.signing_key(NOW + 100).unwrap().kid(), Some("next"));
+    }
+
+    #[test]
+    fn test_verification_key_by_kid() {
+        let key_ring = test_key_ring();
+        assert!(key_ring.verification_key(Some("current"), NOW).is_some());
+        assert!(key_ring.verification_key(Some("next"), NOW).is_some());
+        assert!(key_ring.verification_key(Some("old"), NOW).is_none());
+        assert!(key_ring.verification_key(Some("unknown"), NOW).is_none());
+        assert!(key_ring.verification_key(None, NOW).is_none());
+    }
+
+    #[test]
+    fn test_jwks_excludes_retired_keys() {
+        let kids: Vec<_> = test_key_ring()
+            .jwks(NOW)
+            .into_iter()
+            .filter_map(|jwk| jwk.common.key_id)
+            .collect();
+        assert_eq!(kids, vec!["current", "next"]);
+    }
+
+    #[tokio::test]
+    async fn test_promote_retires_previous_key_after_overlap() {
+        let mut key_ring = test_key_ring();
+
+        key_ring.promote("next", NOW, 600).await.unwrap();
+        assert_eq!(key_ring.signing_key(NOW).unwrap().kid(), Some("next"));
+
+        // The previous key keeps verifying tokens during the overlap window only
+        assert!(key_ring
+            .verification_key(Some("current"), NOW + 599)
+            .is_some());
+        assert!(key_ring
+            .verification_key(Some("current"), NOW + 600)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_promote_unknown_or_retired_key() {
+        let mut key_ring = test_key_ring();
+        assert_eq!(
+            key_ring.promote("unknown", NOW, 600).await.unwrap_err(),
+            KeyRingError::KeyNotFound
+        );
+        assert_eq!(
+            key_ring.promote("old", NOW, 600).await.unwrap_err(),
+            KeyRingError::KeyRetired
+        );
+        assert_eq!(key_ring.signing_key(NOW).unwrap().kid(), Some("current"));
+    }
+
+    #[tokio::test]
+    async fn test_promotions_are_shared_through_store() {
+        let settings = Settings::new().expect("Failed to load test configuration");
+        let conn = crate::get_redis_connection(
+            settings.redis.hostname.clone(),
+            settings.redis.password.clone(),
+        )
+        .await
+        .expect("Failed to get Redis connection");
+        let promotion_store: SigningKeyStoreType = Arc::new(RwLock::new(
+            RedisSigningKeyStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(conn)),
+                settings.redis.signing_key_key_prefix,
+                format!("test_shared_promotions_{}:", uuid::Uuid::new_v4()),
+            ),
+        ));
+
+        let mut key_ring = test_key_ring().with_promotion_store(promotion_store.clone());
+        key_ring.promote("next", NOW, 600).await.unwrap();
+
+        // Another instance, or this one after a restart, picks the promotion up
+        let other_key_ring = Arc::new(RwLock::new(
+            test_key_ring().with_promotion_store(promotion_store),
+        ));
+        sync_key_ring(&other_key_ring).await.unwrap();
+        let other_key_ring = other_key_ring.read().await;
+        assert_eq!(other_key_ring.signing_key(NOW).unwrap().kid(), Some("next"));
+        assert!(other_key_ring
+            .verification_key(Some("current"), NOW + 600)
+            .is_none());
+    }
+
+    #[test]
+    fn test_no_active_signing_key() {
+        let key_ring = KeyRing::new(vec![(ed25519_key("next"), NOW + 100, None)]);
+        assert!(key_ring.signing_key(NOW).is_err());
+    }
+}
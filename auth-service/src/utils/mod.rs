@@ -1,2 +1,12 @@
 pub mod auth;
+pub mod cbor;
+pub mod client_info;
+pub mod email_templates;
+pub mod key_ring;
+pub mod secret_cipher;
+pub mod signing_key;
+pub mod totp;
 pub mod tracing;
+pub mod trusted_device;
+pub mod two_fa_code;
+pub mod webauthn;
@@ -0,0 +1,89 @@
+use color_eyre::eyre::{eyre, Result};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sha2::{Digest, Sha256};
+
+/// Encrypts secrets that have to be read back, such as TOTP secrets, before they are
+/// stored. Ciphertexts are bound to a context, e.g. the id of their user, so one cannot
+/// be moved to another row and decrypted there.
+pub struct SecretCipher {
+    key: LessSafeKey,
+}
+
+impl SecretCipher {
+    /// The AES-256 key is derived from a configured passphrase of any length
+    pub fn new(passphrase: &str) -> Self {
+        let key_bytes = Sha256::digest(passphrase.as_bytes());
+        let key = UnboundKey::new(&AES_256_GCM, &key_bytes).expect("AES-256 keys are 32 bytes");
+        Self {
+            key: LessSafeKey::new(key),
+        }
+    }
+
+    /// Encrypt with a random nonce, which is prepended to the ciphertext
+    pub fn encrypt(&self, plaintext: &[u8], context: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(context),
+                &mut sealed,
+            )
+            .map_err(|_| eyre!("failed to encrypt secret"))?;
+
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8], context: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < NONCE_LEN {
+            return Err(eyre!("encrypted secret is too short"));
+        }
+        let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| eyre!("invalid nonce of encrypted secret"))?;
+
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(context), &mut sealed)
+            .map_err(|_| eyre!("failed to decrypt secret"))?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let cipher = SecretCipher::new("test passphrase");
+        let ciphertext = cipher.encrypt(b"secret", b"user").unwrap();
+
+        assert!(!ciphertext.windows(6).any(|window| window == b"secret"));
+        assert_eq!(cipher.decrypt(&ciphertext, b"user").unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_nonces_are_random() {
+        let cipher = SecretCipher::new("test passphrase");
+        let first = cipher.encrypt(b"secret", b"user").unwrap();
+        let second = cipher.encrypt(b"secret", b"user").unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_decrypt_fails_with_other_context_or_key() {
+        let cipher = SecretCipher::new("test passphrase");
+        let ciphertext = cipher.encrypt(b"secret", b"user").unwrap();
+
+        assert!(cipher.decrypt(&ciphertext, b"other user").is_err());
+        assert!(SecretCipher::new("other passphrase")
+            .decrypt(&ciphertext, b"user")
+            .is_err());
+        assert!(cipher.decrypt(&ciphertext[..4], b"user").is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use color_eyre::eyre::{eyre, Context, Result};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+
+use crate::config::SigningKeyConfig;
+
+// DER prefix of an Ed25519 SubjectPublicKeyInfo, followed by the 32 byte public key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+// A key used to sign and verify JWTs, along with its public JWK when it is asymmetric
+pub struct SigningKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    pub fn from_key_config(key_config: &SigningKeyConfig) -> Result<Self> {
+        let private_key = key_config.private_key.as_bytes();
+        let public_key = key_config.public_key.as_bytes();
+
+        let (encoding_key, decoding_key, algorithm_parameters, key_algorithm) = match key_config
+            .algorithm
+        {
+            Algorithm::RS256 => (
+                EncodingKey::from_rsa_pem(private_key)
+                    .wrap_err("failed to parse RSA private key")?,
+                DecodingKey::from_rsa_pem(public_key).wrap_err("failed to parse RSA public key")?,
+                rsa_jwk_parameters(&key_config.public_key)?,
+                KeyAlgorithm::RS256,
+            ),
+            Algorithm::EdDSA => (
+                EncodingKey::from_ed_pem(private_key)
+                    .wrap_err("failed to parse Ed25519 private key")?,
+                DecodingKey::from_ed_pem(public_key)
+                    .wrap_err("failed to parse Ed25519 public key")?,
+                ed25519_jwk_parameters(&key_config.public_key)?,
+                KeyAlgorithm::EdDSA,
+            ),
+            algorithm => return Err(eyre!("unsupported JWT signing algorithm: {:?}", algorithm)),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(key_config.kid.clone()),
+                ..Default::default()
+            },
+            algorithm: algorithm_parameters,
+        };
+
+        Ok(Self {
+            kid: Some(key_config.kid.clone()),
+            algorithm: key_config.algorithm,
+            encoding_key,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    // Public key in JWK form. Symmetric keys are never published.
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    // JWT header carrying the algorithm and key id of this key
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid.clone();
+        header
+    }
+
+    pub fn validation(&self) -> Validation {
+        Validation::new(self.algorithm)
+    }
+}
+
+fn rsa_jwk_parameters(public_key_pem: &str) -> Result<AlgorithmParameters> {
+    let public_key = RsaPublicKey::from_public_key_pem(public_key_pem.trim())
+        .wrap_err("failed to parse RSA public key")?;
+
+    Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+    }))
+}
+
+fn ed25519_jwk_parameters(public_key_pem: &str) -> Result<AlgorithmParameters> {
+    let pem = pem::parse(public_key_pem.trim()).wrap_err("failed to parse Ed25519 public key")?;
+
+    let x = pem
+        .contents()
+        .strip_prefix(&ED25519_SPKI_PREFIX[..])
+        .filter(|x| x.len() == 32)
+        .ok_or_else(|| eyre!("public key is not an Ed25519 SubjectPublicKeyInfo"))?;
+
+    Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(x),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, encode};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn key_config(algorithm: Algorithm, private_key: &str, public_key: &str) -> SigningKeyConfig {
+        SigningKeyConfig {
+            kid: "test-key".to_owned(),
+            algorithm,
+            private_key: private_key.to_owned(),
+            public_key: public_key.to_owned(),
+            activates_at: None,
+            retires_at: None,
+        }
+    }
+
+    fn rsa_key_config() -> SigningKeyConfig {
+        key_config(
+            Algorithm::RS256,
+            include_str!("../../tests/keys/rsa_private.pem"),
+            include_str!("../../tests/keys/rsa_public.pem"),
+        )
+    }
+
+    fn ed25519_key_config() -> SigningKeyConfig {
+        key_config(
+            Algorithm::EdDSA,
+            include_str!("../../tests/keys/ed25519_private.pem"),
+            include_str!("../../tests/keys/ed25519_public.pem"),
+        )
+    }
+
+    fn sign_and_verify_with_jwk(signing_key: &SigningKey) {
+        let claims = TestClaims {
+            sub: "test@example.com".to_owned(),
+            exp: 4_102_444_800,
+        };
+        let token = encode(&signing_key.header(), &claims, signing_key.encoding_key()).unwrap();
+
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.kid.as_deref(), Some("test-key"));
+
+        // Downstream services only need the published JWK to verify the token
+        let decoding_key = DecodingKey::from_jwk(signing_key.jwk().unwrap()).unwrap();
+        let decoded = decode::<TestClaims>(&token, &decoding_key, &signing_key.validation())
+            .unwrap()
+            .claims;
+        assert_eq!(decoded, claims);
+    }
+
+    #[test]
+    fn test_rs256_key_signs_and_publishes_jwk() {
+        let signing_key = SigningKey::from_key_config(&rsa_key_config()).unwrap();
+        let jwk = signing_key.jwk().unwrap();
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(_)));
+        assert_eq!(jwk.common.key_id.as_deref(), Some("test-key"));
+        sign_and_verify_with_jwk(&signing_key);
+    }
+
+    #[test]
+    fn test_eddsa_key_signs_and_publishes_jwk() {
+        let signing_key = SigningKey::from_key_config(&ed25519_key_config()).unwrap();
+        let jwk = signing_key.jwk().unwrap();
+        assert!(matches!(
+            jwk.algorithm,
+            AlgorithmParameters::OctetKeyPair(_)
+        ));
+        sign_and_verify_with_jwk(&signing_key);
+    }
+
+    #[test]
+    fn test_secret_key_is_not_published() {
+        let signing_key = SigningKey::from_secret(b"secret");
+        assert!(signing_key.jwk().is_none());
+        assert!(signing_key.kid().is_none());
+    }
+
+    #[test]
+    fn test_unsupported_algorithm() {
+        let mut config = ed25519_key_config();
+        config.algorithm = Algorithm::HS512;
+        assert!(SigningKey::from_key_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_key_type() {
+        let mut config = rsa_key_config();
+        config.algorithm = Algorithm::EdDSA;
+        assert!(SigningKey::from_key_config(&config).is_err());
+    }
+}
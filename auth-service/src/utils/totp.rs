@@ -0,0 +1,140 @@
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+use crate::domain::{TotpSecret, TwoFACode};
+
+// RFC 6238 defaults, which is all most authenticator apps support
+pub const TIME_STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+
+/// Time step a Unix timestamp falls in
+pub fn time_step(unix_seconds: u64) -> u64 {
+    unix_seconds / TIME_STEP_SECONDS
+}
+
+/// Code of the authenticator for a time step, per RFC 4226 with the step as counter
+pub fn code_at(secret: &TotpSecret, step: u64) -> String {
+    let mut mac =
+        Hmac::<Sha1>::new_from_slice(secret.as_ref()).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Find the time step within `drift_steps` of the current one that `code` was generated
+/// for. The step of an accepted code has to be recorded, so the code cannot be replayed.
+pub fn verify_code(
+    secret: &TotpSecret,
+    code: &TwoFACode,
+    unix_seconds: u64,
+    drift_steps: u64,
+) -> Option<u64> {
+    let current = time_step(unix_seconds);
+    // Every step is checked, so how long verification takes does not reveal which matched
+    (current.saturating_sub(drift_steps)..=current + drift_steps).fold(None, |matched, step| {
+        let matches: bool = code_at(secret, step)
+            .as_bytes()
+            .ct_eq(code.as_ref().as_bytes())
+            .into();
+        matched.or(matches.then_some(step))
+    })
+}
+
+/// URI that authenticator apps import the secret from, usually shown as a QR code
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &TotpSecret) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret.to_base32().expose_secret(),
+        percent_encode(issuer),
+        DIGITS,
+        TIME_STEP_SECONDS
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rfc_secret() -> TotpSecret {
+        TotpSecret::from_bytes(b"12345678901234567890".to_vec())
+    }
+
+    fn code(code: &str) -> TwoFACode {
+        TwoFACode::parse(code.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_code_at_matches_rfc_6238_vectors() {
+        // SHA1 test vectors from RFC 6238 appendix B, truncated to 6 digits
+        for (unix_seconds, expected) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(code_at(&rfc_secret(), time_step(unix_seconds)), expected);
+        }
+    }
+
+    #[test]
+    fn test_verify_code_within_drift() {
+        let secret = rfc_secret();
+        let now = 1111111111;
+        let step = time_step(now);
+
+        assert_eq!(verify_code(&secret, &code("050471"), now, 0), Some(step));
+        // The code of the previous step is only accepted with a drift window
+        assert_eq!(verify_code(&secret, &code("081804"), now, 0), None);
+        assert_eq!(
+            verify_code(&secret, &code("081804"), now, 1),
+            Some(step - 1)
+        );
+
+        let next = code_at(&secret, step + 1);
+        assert_eq!(verify_code(&secret, &code(&next), now, 1), Some(step + 1));
+        let later = code_at(&secret, step + 2);
+        assert_eq!(verify_code(&secret, &code(&later), now, 1), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let uri = provisioning_uri("Auth Service", "alice@example.com", &rfc_secret());
+        assert_eq!(
+            uri,
+            "otpauth://totp/Auth%20Service:alice%40example.com\
+             ?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Auth%20Service\
+             &algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::domain::UserId;
+
+/// Value of the trusted device cookie, `<device_id>.<signature>`. The signature binds the
+/// device to its user, so the cookie vouches for nobody else and cannot be forged.
+pub fn sign_device(key: &str, user_id: &UserId, device_id: &str) -> String {
+    format!("{}.{}", device_id, signature(key, user_id, device_id))
+}
+
+/// Id of the device a trusted device cookie was signed for, if it was signed for the user
+pub fn verify_device(key: &str, user_id: &UserId, value: &str) -> Option<String> {
+    let (device_id, signature_hex) = value.split_once('.')?;
+    let device_id = uuid::Uuid::parse_str(device_id).ok()?.to_string();
+    let expected = signature(key, user_id, &device_id);
+    match bool::from(expected.as_bytes().ct_eq(signature_hex.as_bytes())) {
+        true => Some(device_id),
+        false => None,
+    }
+}
+
+fn signature(key: &str, user_id: &UserId, device_id: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(user_id.to_string().as_bytes());
+    mac.update(b":");
+    mac.update(device_id.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test-trusted-device-key";
+
+    #[test]
+    fn test_verify_device() {
+        let user_id = UserId::default();
+        let device_id = uuid::Uuid::new_v4().to_string();
+        let value = sign_device(KEY, &user_id, &device_id);
+
+        assert_eq!(verify_device(KEY, &user_id, &value), Some(device_id));
+        assert_eq!(verify_device(KEY, &UserId::default(), &value), None);
+        assert_eq!(verify_device("other-key", &user_id, &value), None);
+    }
+
+    #[test]
+    fn test_tampered_value_is_rejected() {
+        let user_id = UserId::default();
+        let device_id = uuid::Uuid::new_v4().to_string();
+        let value = sign_device(KEY, &user_id, &device_id);
+        let (_, signature) = value.split_once('.').unwrap();
+
+        let other_device = format!("{}.{}", uuid::Uuid::new_v4(), signature);
+        assert_eq!(verify_device(KEY, &user_id, &other_device), None);
+        assert_eq!(verify_device(KEY, &user_id, &device_id), None);
+        assert_eq!(verify_device(KEY, &user_id, "not-a-device.00"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::domain::{LoginAttemptId, TwoFACode, TwoFACodeHash};
+
+/// Keyed hash of a 2FA code, which is what gets stored instead of the code. The hash is
+/// bound to the login attempt the code was issued for, so it does not complete another
+/// one, and without the key it cannot be brute forced back into the code.
+pub fn hash_code(key: &str, login_attempt_id: &LoginAttemptId, code: &TwoFACode) -> TwoFACodeHash {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(login_attempt_id.as_ref().as_bytes());
+    mac.update(b":");
+    mac.update(code.as_ref().as_bytes());
+    let digest = mac.finalize().into_bytes();
+    let hash: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    TwoFACodeHash::parse(hash).expect("SHA-256 digests are 32 bytes")
+}
+
+/// Check a 2FA code against the stored hash in constant time
+pub fn verify_code(
+    key: &str,
+    hash: &TwoFACodeHash,
+    login_attempt_id: &LoginAttemptId,
+    code: &TwoFACode,
+) -> bool {
+    let expected = hash_code(key, login_attempt_id, code);
+    expected
+        .as_ref()
+        .as_bytes()
+        .ct_eq(hash.as_ref().as_bytes())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test-2fa-code-key";
+
+    #[test]
+    fn test_verify_code() {
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+        let hash = hash_code(KEY, &login_attempt_id, &code);
+
+        assert!(verify_code(KEY, &hash, &login_attempt_id, &code));
+        let wrong_code = TwoFACode::parse("654321".to_string()).unwrap();
+        assert!(!verify_code(KEY, &hash, &login_attempt_id, &wrong_code));
+    }
+
+    #[test]
+    fn test_hash_is_bound_to_login_attempt_and_key() {
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+        let hash = hash_code(KEY, &login_attempt_id, &code);
+
+        assert!(!verify_code(KEY, &hash, &LoginAttemptId::default(), &code));
+        assert!(!verify_code("other-key", &hash, &login_attempt_id, &code));
+    }
+}
@@ -0,0 +1,392 @@
+use color_eyre::eyre::{eyre, Result};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::cbor::Value;
+use crate::domain::{CredentialId, PasskeyCredential, WebauthnChallenge};
+
+/// COSE algorithm identifier of ES256, the only algorithm credentials may use
+pub const ES256: i64 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+const FLAG_EXTENSION_DATA: u8 = 0x80;
+
+// RP id hash, flags and signature counter
+const AUTHENTICATOR_DATA_HEADER_LENGTH: usize = 37;
+const AAGUID_LENGTH: usize = 16;
+
+/// The relying party WebAuthn responses have to be addressed to
+pub struct RelyingParty<'a> {
+    /// Domain credentials are scoped to
+    pub id: &'a str,
+    /// Origin the frontend calling the WebAuthn API is served from
+    pub origin: &'a str,
+}
+
+#[derive(Clone, Copy)]
+pub enum CeremonyType {
+    Create,
+    Get,
+}
+
+impl AsRef<str> for CeremonyType {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Create => "webauthn.create",
+            Self::Get => "webauthn.get",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// Check that the client data of a response was collected by our own origin for the
+/// expected ceremony, and return the challenge it answers. Whether the challenge was
+/// issued, and for what, is up to the caller.
+pub fn client_data_challenge(
+    rp: &RelyingParty,
+    client_data_json: &[u8],
+    ceremony_type: CeremonyType,
+) -> Result<WebauthnChallenge> {
+    let client_data: CollectedClientData = serde_json::from_slice(client_data_json)?;
+    if client_data.ceremony_type != ceremony_type.as_ref() {
+        return Err(eyre!("unexpected WebAuthn ceremony type"));
+    }
+    // A mismatch means the user was on another site, e.g. a phishing page
+    if client_data.origin != rp.origin || client_data.cross_origin {
+        return Err(eyre!("WebAuthn response from unexpected origin"));
+    }
+    WebauthnChallenge::parse(&client_data.challenge)
+}
+
+/// Verify the attestation object of a registration response and return the new
+/// credential. No attestation is requested, so the attestation statement is not checked:
+/// the credential is trusted because a logged in user registers it.
+pub fn verify_registration(
+    rp: &RelyingParty,
+    attestation_object: &[u8],
+    require_user_verification: bool,
+) -> Result<PasskeyCredential> {
+    let attestation_object = Value::decode(attestation_object)?;
+    let authenticator_data = attestation_object
+        .get(&Value::Text("authData".to_owned()))
+        .and_then(Value::as_bytes)
+        .ok_or_else(|| eyre!("attestation object without authenticator data"))?;
+
+    let authenticator_data =
+        AuthenticatorData::parse(rp, authenticator_data, require_user_verification)?;
+    let (id, public_key) = authenticator_data
+        .attested_credential
+        .ok_or_else(|| eyre!("registration response without credential"))?;
+
+    Ok(PasskeyCredential {
+        id,
+        public_key,
+        sign_count: authenticator_data.sign_count,
+        transports: Vec::new(),
+    })
+}
+
+/// Verify an assertion made with a credential and return the signature counter the
+/// authenticator reports. The challenge is checked with `client_data_challenge`.
+pub fn verify_assertion(
+    rp: &RelyingParty,
+    credential: &PasskeyCredential,
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+    require_user_verification: bool,
+) -> Result<u32> {
+    let parsed = AuthenticatorData::parse(rp, authenticator_data, require_user_verification)?;
+
+    // The authenticator signs its data followed by the hash of the client data
+    let signed_data = [
+        authenticator_data,
+        Sha256::digest(client_data_json).as_slice(),
+    ]
+    .concat();
+    UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &credential.public_key)
+        .verify(&signed_data, signature)
+        .map_err(|_| eyre!("invalid WebAuthn assertion signature"))?;
+
+    Ok(parsed.sign_count)
+}
+
+struct AuthenticatorData {
+    sign_count: u32,
+    attested_credential: Option<(CredentialId, Vec<u8>)>,
+}
+
+impl AuthenticatorData {
+    fn parse(rp: &RelyingParty, data: &[u8], require_user_verification: bool) -> Result<Self> {
+        if data.len() < AUTHENTICATOR_DATA_HEADER_LENGTH {
+            return Err(eyre!("authenticator data is too short"));
+        }
+        let (header, mut rest) = data.split_at(AUTHENTICATOR_DATA_HEADER_LENGTH);
+
+        if header[..32] != *Sha256::digest(rp.id.as_bytes()) {
+            return Err(eyre!("authenticator data for another relying party"));
+        }
+        let flags = header[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(eyre!("user was not present"));
+        }
+        if require_user_verification && flags & FLAG_USER_VERIFIED == 0 {
+            return Err(eyre!("user was not verified"));
+        }
+        let sign_count = u32::from_be_bytes([header[33], header[34], header[35], header[36]]);
+
+        let attested_credential = match flags & FLAG_ATTESTED_CREDENTIAL_DATA {
+            0 => None,
+            _ => {
+                let (credential, length) = parse_attested_credential(rest)?;
+                rest = &rest[length..];
+                Some(credential)
+            }
+        };
+        // Extensions are not requested, but authenticators may add them regardless
+        if !rest.is_empty() && flags & FLAG_EXTENSION_DATA == 0 {
+            return Err(eyre!("trailing bytes after authenticator data"));
+        }
+
+        Ok(Self {
+            sign_count,
+            attested_credential,
+        })
+    }
+}
+
+// Parse the credential id and public key of attested credential data, and return how
+// many bytes they take
+fn parse_attested_credential(data: &[u8]) -> Result<((CredentialId, Vec<u8>), usize)> {
+    let id_start = AAGUID_LENGTH + 2;
+    if data.len() < id_start {
+        return Err(eyre!("attested credential data is too short"));
+    }
+    let id_length = u16::from_be_bytes([data[AAGUID_LENGTH], data[AAGUID_LENGTH + 1]]) as usize;
+    let id = data
+        .get(id_start..id_start + id_length)
+        .ok_or_else(|| eyre!("attested credential data is too short"))?;
+    let id = CredentialId::parse(id.to_vec())?;
+
+    let (public_key, key_length) = Value::decode_prefix(&data[id_start + id_length..])?;
+    let public_key = es256_public_key(&public_key)?;
+
+    Ok(((id, public_key), id_start + id_length + key_length))
+}
+
+// Uncompressed P-256 point of an ES256 COSE key
+fn es256_public_key(key: &Value) -> Result<Vec<u8>> {
+    let parameter = |label: i128| key.get(&Value::Integer(label));
+    let is_integer = |label: i128, expected: i128| {
+        parameter(label).and_then(Value::as_integer) == Some(expected)
+    };
+
+    // Key type EC2 on curve P-256
+    if !is_integer(1, 2) || !is_integer(3, ES256 as i128) || !is_integer(-1, 1) {
+        return Err(eyre!("credential public key is not an ES256 key"));
+    }
+    match (
+        parameter(-2).and_then(Value::as_bytes),
+        parameter(-3).and_then(Value::as_bytes),
+    ) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => Ok([&[0x04], x, y].concat()),
+        _ => Err(eyre!("invalid ES256 public key coordinates")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    const RP: RelyingParty = RelyingParty {
+        id: "localhost",
+        origin: "http://localhost",
+    };
+
+    fn key_pair() -> EcdsaKeyPair {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng).unwrap()
+    }
+
+    fn cose_key(key_pair: &EcdsaKeyPair) -> Value {
+        let point = key_pair.public_key().as_ref();
+        Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(ES256 as i128)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(point[1..33].to_vec())),
+            (Value::Integer(-3), Value::Bytes(point[33..].to_vec())),
+        ])
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32, attested: &[u8]) -> Vec<u8> {
+        [
+            Sha256::digest(rp_id.as_bytes()).as_slice(),
+            &[flags],
+            &sign_count.to_be_bytes(),
+            attested,
+        ]
+        .concat()
+    }
+
+    fn attestation_object(authenticator_data: Vec<u8>) -> Vec<u8> {
+        Value::Map(vec![
+            (
+                Value::Text("fmt".to_owned()),
+                Value::Text("none".to_owned()),
+            ),
+            (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+            (
+                Value::Text("authData".to_owned()),
+                Value::Bytes(authenticator_data),
+            ),
+        ])
+        .encode()
+    }
+
+    fn attested_credential(id: &[u8], key: &Value) -> Vec<u8> {
+        [
+            [0u8; AAGUID_LENGTH].as_slice(),
+            &(id.len() as u16).to_be_bytes(),
+            id,
+            &key.encode(),
+        ]
+        .concat()
+    }
+
+    fn client_data(ceremony_type: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": ceremony_type,
+            "challenge": challenge,
+            "origin": origin,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_client_data_challenge() {
+        let challenge = WebauthnChallenge::default();
+        let json = client_data("webauthn.get", challenge.as_ref(), RP.origin);
+        assert_eq!(
+            client_data_challenge(&RP, &json, CeremonyType::Get).unwrap(),
+            challenge
+        );
+        assert!(client_data_challenge(&RP, &json, CeremonyType::Create).is_err());
+
+        let json = client_data(
+            "webauthn.get",
+            challenge.as_ref(),
+            "https://phishing.example",
+        );
+        assert!(client_data_challenge(&RP, &json, CeremonyType::Get).is_err());
+
+        let json = serde_json::json!({
+            "type": "webauthn.get",
+            "challenge": challenge.as_ref(),
+            "origin": RP.origin,
+            "crossOrigin": true,
+        })
+        .to_string();
+        assert!(client_data_challenge(&RP, json.as_bytes(), CeremonyType::Get).is_err());
+        assert!(client_data_challenge(&RP, b"{}", CeremonyType::Get).is_err());
+    }
+
+    #[test]
+    fn test_register_and_assert() {
+        let key_pair = key_pair();
+        let attested = attested_credential(b"credential", &cose_key(&key_pair));
+        let data = authenticator_data(RP.id, 0x45, 0, &attested);
+
+        let credential = verify_registration(&RP, &attestation_object(data), true).unwrap();
+        assert_eq!(credential.id.as_ref(), b"credential");
+        assert_eq!(credential.public_key, key_pair.public_key().as_ref());
+        assert_eq!(credential.sign_count, 0);
+
+        let data = authenticator_data(RP.id, 0x05, 7, &[]);
+        let client_data = client_data("webauthn.get", WebauthnChallenge::default().as_ref(), "");
+        let signed_data = [data.as_slice(), &Sha256::digest(&client_data)].concat();
+        let signature = key_pair.sign(&SystemRandom::new(), &signed_data).unwrap();
+
+        let sign_count = verify_assertion(
+            &RP,
+            &credential,
+            &data,
+            &client_data,
+            signature.as_ref(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(sign_count, 7);
+
+        // The signature covers the client data
+        let other_client_data = client_data.iter().rev().copied().collect::<Vec<_>>();
+        assert!(verify_assertion(
+            &RP,
+            &credential,
+            &data,
+            &other_client_data,
+            signature.as_ref(),
+            true
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_authenticator_data() {
+        let key = cose_key(&key_pair());
+        let attested = attested_credential(b"credential", &key);
+
+        // Another relying party, user not present or not verified
+        for (rp_id, flags, require_user_verification) in [
+            ("example.com", 0x45, false),
+            (RP.id, 0x44, false),
+            (RP.id, 0x41, true),
+        ] {
+            let data = authenticator_data(rp_id, flags, 0, &attested);
+            let result =
+                verify_registration(&RP, &attestation_object(data), require_user_verification);
+            assert!(result.is_err());
+        }
+
+        // No credential, or trailing bytes without the extension flag
+        let data = authenticator_data(RP.id, 0x05, 0, &[]);
+        assert!(verify_registration(&RP, &attestation_object(data), false).is_err());
+        let data = authenticator_data(RP.id, 0x45, 0, &[attested.as_slice(), &[0]].concat());
+        assert!(verify_registration(&RP, &attestation_object(data), false).is_err());
+
+        // Truncated data
+        let data = authenticator_data(RP.id, 0x45, 0, &attested[..attested.len() - 1]);
+        assert!(verify_registration(&RP, &attestation_object(data), false).is_err());
+        assert!(verify_registration(&RP, &attestation_object(vec![0; 36]), false).is_err());
+    }
+
+    #[test]
+    fn test_reject_keys_other_than_es256() {
+        let Value::Map(mut entries) = cose_key(&key_pair()) else {
+            unreachable!()
+        };
+        // RS256
+        entries[1].1 = Value::Integer(-257);
+        let attested = attested_credential(b"credential", &Value::Map(entries));
+        let data = authenticator_data(RP.id, 0x45, 0, &attested);
+        assert!(verify_registration(&RP, &attestation_object(data), false).is_err());
+    }
+}
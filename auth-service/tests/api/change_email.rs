@@ -0,0 +1,296 @@
+use auth_service::{routes::LoginResponse, ErrorResponse};
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+const CONFIRM_PARAM: &str = "confirm_email_change_token";
+const CANCEL_PARAM: &str = "cancel_email_change_token";
+
+async fn signup(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    app.post_login(&login_body).await
+}
+
+// Sign up and log in a new user, returning their email
+async fn logged_in_user(app: &TestApp) -> String {
+    let email = get_random_email();
+    signup(app, &email).await;
+    let response = login(app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    email
+}
+
+async fn request_change(app: &TestApp, new_email: &str, password: &str) -> reqwest::Response {
+    let body = serde_json::json!({
+        "newEmail": new_email,
+        "password": password
+    });
+    app.post_change_email_request(&body).await
+}
+
+async fn confirm_change(app: &TestApp, token: &str) -> reqwest::Response {
+    let body = serde_json::json!({ "token": token });
+    app.post_change_email_confirm(&body).await
+}
+
+async fn cancel_change(app: &TestApp, token: &str) -> reqwest::Response {
+    let body = serde_json::json!({ "token": token });
+    app.post_change_email_cancel(&body).await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_change_email() {
+    let mut app = TestApp::new(true).await;
+
+    let email = logged_in_user(&app).await;
+    let jwt = app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found");
+    let new_email = get_random_email();
+
+    let response = request_change(&app, &new_email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let product_name = &app.settings.email.product_name;
+    assert_eq!(
+        app.get_sent_emails(&new_email).last().unwrap().subject,
+        format!("Confirm your new email address for {}", product_name)
+    );
+    let notice = app.get_sent_emails(&email).pop().unwrap();
+    assert_eq!(
+        notice.subject,
+        format!("Your {} email address is being changed", product_name)
+    );
+    assert!(notice.text_body.contains(&new_email));
+
+    // Nothing changes until the new address is confirmed
+    let token_body = serde_json::json!({ "token": jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = app.get_link_token(&new_email, CONFIRM_PARAM).unwrap();
+    let response = confirm_change(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Sessions belong to the user rather than the address, so they carry over
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = login(&app, &new_email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The link is single-use
+    assert_error(
+        confirm_change(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_cancel_email_change() {
+    let mut app = TestApp::new(true).await;
+
+    let email = logged_in_user(&app).await;
+    let new_email = get_random_email();
+    request_change(&app, &new_email, PASSWORD).await;
+
+    let cancel_token = app.get_link_token(&email, CANCEL_PARAM).unwrap();
+    let response = cancel_change(&app, &cancel_token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let confirm_token = app.get_link_token(&new_email, CONFIRM_PARAM).unwrap();
+    assert_error(
+        confirm_change(&app, &confirm_token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_only_confirm_latest_request() {
+    let mut app = TestApp::new(true).await;
+
+    logged_in_user(&app).await;
+    let first_email = get_random_email();
+    let second_email = get_random_email();
+    request_change(&app, &first_email, PASSWORD).await;
+    request_change(&app, &second_email, PASSWORD).await;
+
+    let first_token = app.get_link_token(&first_email, CONFIRM_PARAM).unwrap();
+    assert_error(
+        confirm_change(&app, &first_token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+
+    let second_token = app.get_link_token(&second_email, CONFIRM_PARAM).unwrap();
+    let response = confirm_change(&app, &second_token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = login(&app, &second_email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_409_if_new_email_is_taken() {
+    let mut app = TestApp::new(true).await;
+
+    let taken_email = get_random_email();
+    signup(&app, &taken_email).await;
+    let email = logged_in_user(&app).await;
+
+    assert_error(
+        request_change(&app, &taken_email, PASSWORD).await,
+        StatusCode::CONFLICT,
+        "User already exists",
+    )
+    .await;
+
+    // The address can also be taken between the request and its confirmation
+    let new_email = get_random_email();
+    request_change(&app, &new_email, PASSWORD).await;
+    let token = app.get_link_token(&new_email, CONFIRM_PARAM).unwrap();
+    signup(&app, &new_email).await;
+
+    assert_error(
+        confirm_change(&app, &token).await,
+        StatusCode::CONFLICT,
+        "User already exists",
+    )
+    .await;
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_password_is_incorrect() {
+    let mut app = TestApp::new(true).await;
+
+    logged_in_user(&app).await;
+    let new_email = get_random_email();
+
+    assert_error(
+        request_change(&app, &new_email, "WrongPassword123!").await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    assert!(app.get_sent_emails(&new_email).is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_recaptcha_after_repeated_incorrect_passwords() {
+    let mut app = TestApp::new(true).await;
+
+    let email = logged_in_user(&app).await;
+    let new_email = get_random_email();
+
+    for _ in 0..3 {
+        let response = request_change(&app, &new_email, "WrongPassword123!").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the correct password is refused without reCAPTCHA now
+    let response = request_change(&app, &new_email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let login_response = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Could not deserialize response body to LoginResponse");
+    assert_eq!(login_response, LoginResponse::RecaptchaRequired);
+    assert!(app.get_sent_emails(&new_email).is_empty());
+
+    // The failures count towards logins too
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    let body = serde_json::json!({
+        "newEmail": new_email,
+        "password": PASSWORD,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_change_email_request(&body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The correct password clears the failed attempts
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_input_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    let email = logged_in_user(&app).await;
+
+    for new_email in ["not-an-email", email.as_str()] {
+        assert_error(
+            request_change(&app, new_email, PASSWORD).await,
+            StatusCode::BAD_REQUEST,
+            "Invalid input",
+        )
+        .await;
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    assert_error(
+        request_change(&app, &get_random_email(), PASSWORD).await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+}
@@ -0,0 +1,209 @@
+use auth_service::{
+    routes::{LoginResponse, SessionsResponse},
+    ErrorResponse,
+};
+use reqwest::{StatusCode, Url};
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+const NEW_PASSWORD: &str = "NewPassword456!";
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password
+    });
+    app.post_login(&login_body).await
+}
+
+async fn change_password(
+    app: &TestApp,
+    current_password: &str,
+    new_password: &str,
+) -> reqwest::Response {
+    let body = serde_json::json!({
+        "currentPassword": current_password,
+        "newPassword": new_password
+    });
+    app.post_change_password(&body).await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_change_password() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let other_jwt = app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found");
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = change_password(&app, PASSWORD, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let emails = app.get_sent_emails(&email);
+    assert_eq!(
+        emails.last().unwrap().subject,
+        format!(
+            "Your {} password was changed",
+            app.settings.email.product_name
+        )
+    );
+
+    // Other sessions are revoked
+    let token_body = serde_json::json!({ "token": other_jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The caller stays logged in with a fresh session
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let sessions = response
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse")
+        .sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = login(&app, &email, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_current_password_is_incorrect() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email, PASSWORD).await;
+
+    let response = change_password(&app, "WrongPassword123!", NEW_PASSWORD).await;
+    assert_error(response, StatusCode::UNAUTHORIZED, "Incorrect credentials").await;
+
+    // Nothing changed and no notification was sent
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app
+        .get_sent_emails(&email)
+        .iter()
+        .all(|message| !message.subject.contains("password was changed")));
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_recaptcha_after_repeated_incorrect_passwords() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email, PASSWORD).await;
+
+    for _ in 0..3 {
+        let response = change_password(&app, "WrongPassword123!", NEW_PASSWORD).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the correct password is refused without reCAPTCHA now
+    let response = change_password(&app, PASSWORD, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let login_response = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Could not deserialize response body to LoginResponse");
+    assert_eq!(login_response, LoginResponse::RecaptchaRequired);
+
+    // The failures count towards logins too
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    let body = serde_json::json!({
+        "currentPassword": PASSWORD,
+        "newPassword": NEW_PASSWORD,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_change_password(&body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The correct password clears the failed attempts
+    let response = login(&app, &email, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    let response = change_password(&app, PASSWORD, NEW_PASSWORD).await;
+    assert_error(response, StatusCode::BAD_REQUEST, "Missing token").await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_jwt_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}=invalid; HttpOnly; SameSite=Lax; Secure; Path=/",
+            app.settings.auth.jwt_cookie_name
+        ),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = change_password(&app, PASSWORD, NEW_PASSWORD).await;
+    assert_error(response, StatusCode::UNAUTHORIZED, "Invalid token").await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email, PASSWORD).await;
+
+    let response = change_password(&app, PASSWORD, "short").await;
+    assert_error(response, StatusCode::BAD_REQUEST, "Invalid input").await;
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
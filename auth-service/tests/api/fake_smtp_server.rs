@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+// A message delivered to the fake SMTP server
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    pub from: String,
+    pub recipients: Vec<String>,
+    pub data: String,
+}
+
+// Minimal in-process SMTP server that accepts every message and records it,
+// along with the credentials clients authenticated with
+#[derive(Clone)]
+pub struct FakeSmtpServer {
+    pub port: u16,
+    messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+    credentials: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl FakeSmtpServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind fake SMTP server");
+        let server = Self {
+            port: listener.local_addr().unwrap().port(),
+            messages: Arc::default(),
+            credentials: Arc::default(),
+        };
+
+        let accepting = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let session = accepting.clone();
+                tokio::spawn(async move { session.handle(stream).await });
+            }
+        });
+
+        server
+    }
+
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub fn credentials(&self) -> Vec<(String, String)> {
+        self.credentials.lock().unwrap().clone()
+    }
+
+    async fn handle(&self, stream: TcpStream) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut from = String::new();
+        let mut recipients = Vec::new();
+
+        if writer.write_all(b"220 localhost ESMTP\r\n").await.is_err() {
+            return;
+        }
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_ascii_uppercase();
+            let reply = if command.starts_with("EHLO") {
+                "250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n".to_owned()
+            } else if command.starts_with("HELO") {
+                "250 localhost\r\n".to_owned()
+            } else if command.starts_with("AUTH PLAIN") {
+                let encoded = line.split_whitespace().nth(2).unwrap_or_default();
+                let decoded = STANDARD.decode(encoded).unwrap_or_default();
+                let mut parts = decoded.split(|&b| b == 0).skip(1);
+                let username = String::from_utf8_lossy(parts.next().unwrap_or_default());
+                let password = String::from_utf8_lossy(parts.next().unwrap_or_default());
+                self.credentials
+                    .lock()
+                    .unwrap()
+                    .push((username.into_owned(), password.into_owned()));
+                "235 Authentication succeeded\r\n".to_owned()
+            } else if command.starts_with("MAIL FROM:") {
+                from = address(&line);
+                recipients.clear();
+                "250 OK\r\n".to_owned()
+            } else if command.starts_with("RCPT TO:") {
+                recipients.push(address(&line));
+                "250 OK\r\n".to_owned()
+            } else if command == "DATA" {
+                if writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                let mut data = Vec::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    // Undo dot-stuffing of lines starting with a dot
+                    data.push(line.strip_prefix('.').map_or(line.clone(), str::to_owned));
+                }
+                self.messages.lock().unwrap().push(ReceivedMessage {
+                    from: from.clone(),
+                    recipients: std::mem::take(&mut recipients),
+                    data: data.join("\r\n"),
+                });
+                "250 OK\r\n".to_owned()
+            } else if command == "RSET" || command == "NOOP" {
+                "250 OK\r\n".to_owned()
+            } else if command == "QUIT" {
+                let _ = writer.write_all(b"221 Bye\r\n").await;
+                return;
+            } else {
+                "502 Command not implemented\r\n".to_owned()
+            };
+
+            if writer.write_all(reply.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// Extract the address from a `MAIL FROM:<...>` or `RCPT TO:<...>` command
+fn address(line: &str) -> String {
+    line.split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map(|(address, _)| address.to_owned())
+        .unwrap_or_default()
+}
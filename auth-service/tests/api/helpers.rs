@@ -1,17 +1,28 @@
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use auth_service::{
-    app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType},
+    app_state::{
+        AppState, BannedTokenStoreType, EmailClientType, PasskeyStoreType, RecoveryCodeStoreType,
+        RefreshTokenStoreType, SessionStoreType, TotpStoreType, TrustedDeviceStoreType,
+        TwoFACodeStoreType, UserStoreType,
+    },
     config::Settings,
+    domain::{Email, EmailClient, EmailMessage, UserId},
     get_postgres_pool, get_redis_connection,
     services::{
-        postgres_user_store::PostgresUserStore, HashmapLoginAttemptStore, MockEmailClient,
-        MockRecaptchaService, RedisBannedTokenStore, RedisTwoFACodeStore,
+        postgres_user_store::PostgresUserStore, HashmapLoginAttemptStore, MockRecaptchaService,
+        PostgresPasskeyStore, PostgresRecoveryCodeStore, PostgresTotpStore, RedisBannedTokenStore,
+        RedisEmailChangeStore, RedisEmailTokenStore, RedisRefreshTokenStore, RedisSessionStore,
+        RedisSigningKeyStore, RedisTrustedDeviceStore, RedisTwoFACodeStore,
+        RedisWebauthnChallengeStore,
     },
+    utils::{auth::Claims, key_ring::KeyRing, secret_cipher::SecretCipher},
     Application,
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use reqwest::cookie::Jar;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::{PgConnectOptions, PgConnection, PgPoolOptions};
 use sqlx::{Connection, Executor, PgPool};
 use tokio::sync::RwLock;
@@ -21,8 +32,16 @@ pub struct TestApp {
     pub address: String,
     pub http_client: reqwest::Client,
     pub cookie_jar: Arc<Jar>,
+    pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub session_store: SessionStoreType,
+    pub trusted_device_store: TrustedDeviceStoreType,
+    pub totp_store: TotpStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub sent_emails: Arc<Mutex<Vec<SentEmail>>>,
     pub db_name: String,
     pub clean_up_called: bool,
     pub settings: Settings,
@@ -31,21 +50,48 @@ pub struct TestApp {
 
 impl TestApp {
     pub async fn new(recaptcha_success: bool) -> Self {
+        Self::build(recaptcha_success, None, |_| {}).await
+    }
+
+    pub async fn with_email_client(recaptcha_success: bool, email_client: EmailClientType) -> Self {
+        Self::build(recaptcha_success, Some(email_client), |_| {}).await
+    }
+
+    /// Start the app with test settings adjusted by `configure`
+    pub async fn with_settings(
+        recaptcha_success: bool,
+        configure: impl FnOnce(&mut Settings),
+    ) -> Self {
+        Self::build(recaptcha_success, None, configure).await
+    }
+
+    // Emails are recorded unless another email client is given
+    async fn build(
+        recaptcha_success: bool,
+        email_client: Option<EmailClientType>,
+        configure: impl FnOnce(&mut Settings),
+    ) -> Self {
         // Set RUN_MODE to "test" so it loads config/test.toml with short TTLs
         std::env::set_var("RUN_MODE", "test");
 
         // Load test configuration (will now use config/test.toml)
-        let settings = Settings::new().expect("Failed to load test configuration");
+        let mut settings = Settings::new().expect("Failed to load test configuration");
+        configure(&mut settings);
         let (pg_pool, db_name) = configure_postgresql(&settings.database.url()).await;
         let redis_conn = configure_redis(&settings.redis.hostname, &settings.redis.password).await;
 
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool.clone())));
+        let totp_store = Arc::new(RwLock::new(PostgresTotpStore::new(
+            pg_pool.clone(),
+            SecretCipher::new(settings.auth.totp_encryption_key.expose_secret()),
+        )));
+        let passkey_store = Arc::new(RwLock::new(PostgresPasskeyStore::new(pg_pool.clone())));
+        let recovery_code_store = Arc::new(RwLock::new(PostgresRecoveryCodeStore::new(pg_pool)));
         let login_attempt_store = Arc::new(RwLock::new(HashmapLoginAttemptStore::new()));
         let test_id = uuid::Uuid::new_v4().to_string();
         let banned_token_store = Arc::new(RwLock::new(
             RedisBannedTokenStore::new_with_config_and_prefix(
                 Arc::new(RwLock::new(redis_conn)),
-                settings.redis.banned_token_ttl_seconds,
                 settings.redis.banned_token_key_prefix.clone(),
                 format!("integration_test_{}:", test_id),
             ),
@@ -61,15 +107,99 @@ impl TestApp {
                 format!("integration_test_{}:", test_id),
             ),
         ));
-        let email_client = Arc::new(MockEmailClient);
+        let refresh_token_store = Arc::new(RwLock::new(
+            RedisRefreshTokenStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.auth.refresh_token_ttl_seconds,
+                settings.redis.refresh_token_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let session_store = Arc::new(RwLock::new(RedisSessionStore::new_with_config_and_prefix(
+            Arc::new(RwLock::new(
+                configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+            )),
+            settings.auth.refresh_token_ttl_seconds,
+            settings.redis.session_key_prefix.clone(),
+            format!("integration_test_{}:", test_id),
+        )));
+        let trusted_device_store = Arc::new(RwLock::new(
+            RedisTrustedDeviceStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.auth.trusted_device_days * 86400,
+                settings.redis.trusted_device_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let email_token_store = Arc::new(RwLock::new(
+            RedisEmailTokenStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.redis.email_token_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let email_change_store = Arc::new(RwLock::new(
+            RedisEmailChangeStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.redis.email_change_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let webauthn_challenge_store = Arc::new(RwLock::new(
+            RedisWebauthnChallengeStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.redis.webauthn_challenge_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let sent_emails = Arc::new(Mutex::new(Vec::new()));
+        let email_client = email_client.unwrap_or_else(|| {
+            Arc::new(RecordingEmailClient {
+                sent_emails: sent_emails.clone(),
+            })
+        });
+        let signing_key_store = Arc::new(RwLock::new(
+            RedisSigningKeyStore::new_with_config_and_prefix(
+                Arc::new(RwLock::new(
+                    configure_redis(&settings.redis.hostname, &settings.redis.password).await,
+                )),
+                settings.redis.signing_key_key_prefix.clone(),
+                format!("integration_test_{}:", test_id),
+            ),
+        ));
+        let key_ring = Arc::new(RwLock::new(
+            KeyRing::from_config(&settings.auth)
+                .expect("Failed to load signing keys")
+                .with_promotion_store(signing_key_store),
+        ));
 
         let app_state = AppState::new(
-            user_store,
+            user_store.clone(),
             login_attempt_store,
             recaptcha_service,
             banned_token_store.clone(),
             two_fa_code_store.clone(),
+            refresh_token_store.clone(),
+            session_store.clone(),
+            trusted_device_store.clone(),
+            email_token_store,
+            email_change_store,
+            totp_store.clone(),
+            passkey_store.clone(),
+            webauthn_challenge_store,
+            recovery_code_store.clone(),
             email_client,
+            key_ring,
             settings.clone(),
         );
 
@@ -98,8 +228,16 @@ impl TestApp {
             address,
             http_client,
             cookie_jar,
+            user_store,
             banned_token_store,
             two_fa_code_store,
+            refresh_token_store,
+            session_store,
+            trusted_device_store,
+            totp_store,
+            passkey_store,
+            recovery_code_store,
+            sent_emails,
             db_name,
             clean_up_called: false,
             settings,
@@ -109,7 +247,7 @@ impl TestApp {
 
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -120,7 +258,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
             .json(body)
             .send()
             .await
@@ -132,7 +270,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .json(body)
             .send()
             .await
@@ -141,7 +279,243 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout-all", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_enroll(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/totp/enroll", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_enable_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/enable", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/disable", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_2fa_method<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa/method", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_regenerate_recovery_codes<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/recovery-codes/regenerate", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_options(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/webauthn/register/options", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_verify<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/webauthn/register/verify", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_authenticate_options(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/webauthn/authenticate/options", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_authenticate_verify<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/webauthn/authenticate/verify", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-email/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-email/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email_cancel<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-email/cancel", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_magic_link_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/magic-link/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_magic_link_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/magic-link/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_enable_magic_link(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/magic-link/enable", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_magic_link(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/magic-link/disable", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -159,6 +533,108 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_resend_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/resend-2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-email", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_resend_verification_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-email/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_promote_signing_key<Body>(
+        &self,
+        body: &Body,
+        api_key: Option<&str>,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut request = self
+            .http_client
+            .post(format!("{}/admin/signing-keys/promote", &self.address))
+            .json(body);
+        if let Some(api_key) = api_key {
+            request = request.bearer_auth(api_key);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, session_id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, session_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_trusted_devices(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/trusted-devices", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_trusted_devices(&self) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/trusted-devices", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_trusted_device(&self, device_id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/trusted-devices/{}", &self.address, device_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -176,26 +652,103 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .delete(&format!("{}/delete-account", &self.address))
+            .delete(format!("{}/delete-account", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
-    /// Construct the Redis key for a banned token
-    pub fn get_banned_token_redis_key(&self, token: &str) -> String {
+    /// Construct the Redis key for a banned token id
+    pub fn get_banned_token_redis_key(&self, token_id: &str) -> String {
         format!(
             "integration_test_{}:{}{}",
-            self.test_id, self.settings.redis.banned_token_key_prefix, token
+            self.test_id, self.settings.redis.banned_token_key_prefix, token_id
         )
     }
 
-    /// Construct the Redis key for a 2FA code
-    pub fn get_two_fa_code_redis_key(&self, email: &str) -> String {
+    /// Construct the Redis key for the 2FA code of a login attempt
+    pub fn get_two_fa_code_redis_key(&self, email: &str, login_attempt_id: &str) -> String {
         format!(
-            "integration_test_{}:{}{}",
-            self.test_id, self.settings.redis.two_fa_code_key_prefix, email
+            "integration_test_{}:{}{}:{}",
+            self.test_id, self.settings.redis.two_fa_code_key_prefix, email, login_attempt_id
+        )
+    }
+
+    /// Id of the user signed up with `email`
+    pub async fn get_user_id(&self, email: &str) -> UserId {
+        let email = Email::parse(Secret::new(email.to_owned())).unwrap();
+        self.user_store
+            .read()
+            .await
+            .get_user(&email)
+            .await
+            .expect("User not found")
+            .id
+    }
+
+    /// Read the value of a cookie currently held by the client cookie jar
+    pub fn get_cookie_value(&self, name: &str) -> Option<String> {
+        use reqwest::cookie::CookieStore;
+        let cookies = self.cookie_jar.cookies(&self.address.parse().unwrap())?;
+        cookies
+            .to_str()
+            .ok()?
+            .split(';')
+            .filter_map(|s| s.trim().split_once('='))
+            .find(|(cookie_name, _)| *cookie_name == name)
+            .map(|(_, value)| value.to_string())
+    }
+
+    /// Emails recorded so far that were sent to `recipient`, oldest first
+    pub fn get_sent_emails(&self, recipient: &str) -> Vec<EmailMessage> {
+        self.sent_emails
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|email| email.recipient == recipient)
+            .map(|email| email.message.clone())
+            .collect()
+    }
+
+    /// Emails sent to `recipient`, once there are more than `sent_before`. Some emails are
+    /// sent in the background after the response. Gives up after a second, so tests can
+    /// also check that no email was sent.
+    pub async fn wait_for_new_email(
+        &self,
+        recipient: &str,
+        sent_before: usize,
+    ) -> Vec<EmailMessage> {
+        for _ in 0..100 {
+            let emails = self.get_sent_emails(recipient);
+            if emails.len() > sent_before {
+                return emails;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        self.get_sent_emails(recipient)
+    }
+
+    /// 2FA code in the latest email sent to `recipient`
+    pub fn get_two_fa_code(&self, recipient: &str) -> Option<String> {
+        let message = self.get_sent_emails(recipient).pop()?;
+        message
+            .text_body
+            .lines()
+            .map(str::trim)
+            .find(|line| line.len() == 6 && line.bytes().all(|byte| byte.is_ascii_digit()))
+            .map(str::to_owned)
+    }
+
+    /// Read the value of the `param` query parameter of the link in the latest email
+    /// sent to `recipient`
+    pub fn get_link_token(&self, recipient: &str, param: &str) -> Option<String> {
+        let message = self.get_sent_emails(recipient).pop()?;
+        let (_, rest) = message.text_body.split_once(&format!("{}=", param))?;
+        Some(
+            rest.chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect(),
         )
     }
 
@@ -207,6 +760,14 @@ impl TestApp {
         conn.exists(key).await.unwrap_or(false)
     }
 
+    /// Get the value of a key in Redis
+    pub async fn get_redis_value(&self, key: &str) -> Option<String> {
+        use redis::AsyncCommands;
+        let mut conn =
+            configure_redis(&self.settings.redis.hostname, &self.settings.redis.password).await;
+        conn.get(key).await.ok()
+    }
+
     /// Get the TTL (time to live) of a key in Redis
     /// Returns -1 if key doesn't exist, -2 if key exists but has no expiration
     pub async fn get_redis_ttl(&self, key: &str) -> i64 {
@@ -231,10 +792,51 @@ impl Drop for TestApp {
     }
 }
 
+/// An email sent by the app under test
+#[derive(Debug, Clone)]
+pub struct SentEmail {
+    pub recipient: String,
+    pub message: EmailMessage,
+}
+
+// Records emails instead of sending them, so tests can follow the links they contain
+struct RecordingEmailClient {
+    sent_emails: Arc<Mutex<Vec<SentEmail>>>,
+}
+
+#[async_trait::async_trait]
+impl EmailClient for RecordingEmailClient {
+    async fn send_multipart_email(
+        &self,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> color_eyre::eyre::Result<()> {
+        self.sent_emails.lock().unwrap().push(SentEmail {
+            recipient: recipient.as_ref().expose_secret().to_owned(),
+            message: message.clone(),
+        });
+        Ok(())
+    }
+}
+
 pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
 
+/// Read the claims of a JWT without verifying its signature
+pub fn get_token_claims(token: &str) -> Claims {
+    let payload = token.split('.').nth(1).expect("JWT has no payload");
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .expect("Failed to decode JWT payload");
+    serde_json::from_slice(&payload).expect("Failed to deserialize JWT claims")
+}
+
+/// Read the `jti` claim of a JWT, under which it is banned
+pub fn get_token_jti(token: &str) -> String {
+    get_token_claims(token).jti.expect("JWT has no jti")
+}
+
 async fn configure_postgresql(database_url: &str) -> (PgPool, String) {
     let postgresql_conn_url = database_url;
 
@@ -0,0 +1,150 @@
+use auth_service::{
+    config::{HttpEmailConfig, Settings},
+    domain::{Email, EmailClient, EmailMessage},
+    services::{HttpEmailClient, HttpEmailClientError},
+};
+use reqwest::StatusCode;
+use secrecy::Secret;
+use wiremock::{
+    matchers::{body_json, header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const SENDER: &str = "no-reply@example.com";
+const API_TOKEN: &str = "test-api-token";
+
+fn http_client(server: &MockServer, timeout_seconds: u64) -> HttpEmailClient {
+    let config = HttpEmailConfig {
+        base_url: server.uri(),
+        api_token: Secret::new(API_TOKEN.to_owned()),
+        timeout_seconds,
+        max_retries: 2,
+        retry_backoff_ms: 1,
+    };
+    HttpEmailClient::new(&config, SENDER).expect("Failed to build HTTP email client")
+}
+
+fn recipient() -> Email {
+    Email::parse(Secret::new("recipient@example.com".to_owned())).unwrap()
+}
+
+async fn send_email(client: &HttpEmailClient) -> color_eyre::eyre::Result<()> {
+    let message = EmailMessage {
+        subject: "Test subject".to_owned(),
+        html_body: "<p>HTML content</p>".to_owned(),
+        text_body: "Text content".to_owned(),
+    };
+    client.send_multipart_email(&recipient(), &message).await
+}
+
+#[tokio::test]
+async fn should_post_email_to_provider() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/email"))
+        .and(header("X-Postmark-Server-Token", API_TOKEN))
+        .and(body_json(serde_json::json!({
+            "From": SENDER,
+            "To": "recipient@example.com",
+            "Subject": "Test subject",
+            "TextBody": "Text content",
+            "HtmlBody": "<p>HTML content</p>"
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let result = send_email(&http_client(&server, 5)).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn should_retry_on_server_errors_and_rate_limits() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(429))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let result = send_email(&http_client(&server, 5)).await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn should_fail_when_retries_are_exhausted() {
+    let server = MockServer::start().await;
+    // The first attempt and 2 retries
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let error = send_email(&http_client(&server, 5)).await.unwrap_err();
+    let error = error.downcast_ref::<HttpEmailClientError>();
+    assert!(matches!(
+        error,
+        Some(HttpEmailClientError::Unavailable {
+            status: StatusCode::INTERNAL_SERVER_ERROR
+        })
+    ));
+}
+
+#[tokio::test]
+async fn should_not_retry_rejected_emails() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 300,
+            "Message": "Invalid 'To' address"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = send_email(&http_client(&server, 5)).await.unwrap_err();
+    match error.downcast_ref::<HttpEmailClientError>() {
+        Some(HttpEmailClientError::Rejected { status, message }) => {
+            assert_eq!(*status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(message, "Invalid 'To' address");
+        }
+        other => panic!("Unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn should_time_out_slow_provider() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(3)))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let error = send_email(&http_client(&server, 1)).await.unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<HttpEmailClientError>(),
+        Some(HttpEmailClientError::Timeout)
+    ));
+}
+
+#[tokio::test]
+async fn should_build_client_from_settings() {
+    let settings = Settings::new().expect("Failed to load configuration");
+    let client = HttpEmailClient::new(&settings.email.http, &settings.email.sender);
+    assert!(client.is_ok());
+}
@@ -0,0 +1,77 @@
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_configured_public_keys() {
+    let mut app = TestApp::new(true).await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let jwks = response
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    // Scheduled keys are published ahead of their activation
+    assert_eq!(jwks.keys.len(), app.settings.auth.signing_keys.len());
+    for signing_key in &app.settings.auth.signing_keys {
+        assert!(jwks.find(&signing_key.kid).is_some());
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn issued_jwt_should_verify_against_published_key() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    app.post_signup(&signup_body).await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_string();
+
+    let jwks = app
+        .get_jwks()
+        .await
+        .json::<JwkSet>()
+        .await
+        .expect("Could not deserialize response body to JwkSet");
+
+    // A downstream service only needs the JWKS and the token header to verify the token
+    let header = decode_header(&token).expect("Failed to decode JWT header");
+    let kid = header.kid.expect("JWT header has no kid");
+    let jwk = jwks.find(&kid).expect("No JWK found for kid");
+    let decoding_key = DecodingKey::from_jwk(jwk).expect("Failed to build decoding key");
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&app.settings.auth.issuer]);
+    validation.set_audience(&[&app.settings.auth.audience]);
+
+    let claims = decode::<serde_json::Value>(&token, &decoding_key, &validation)
+        .expect("Failed to verify JWT")
+        .claims;
+    let user_id = app.get_user_id(&email).await;
+    assert_eq!(claims["sub"], user_id.to_string());
+}
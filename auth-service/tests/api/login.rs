@@ -1,5 +1,9 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{domain::Email, routes::TwoFactorAuthResponse, ErrorResponse};
+use auth_service::{
+    domain::{Email, LoginAttemptId},
+    routes::TwoFactorAuthResponse,
+    ErrorResponse,
+};
 use reqwest::StatusCode;
 use secrecy::Secret;
 use test_macros::with_db_cleanup;
@@ -33,6 +37,29 @@ async fn login_returns_200_for_valid_credentials() {
     assert_eq!(response.status(), StatusCode::OK);
 }
 
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_200_if_email_case_differs_from_signup() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email.to_uppercase(),
+        "password": "Password123!",
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
 #[with_db_cleanup]
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
@@ -62,7 +89,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
 
     let auth_cookie = response
         .cookies()
-        .find(|cookie| cookie.name() == &app.settings.auth.jwt_cookie_name)
+        .find(|cookie| cookie.name() == app.settings.auth.jwt_cookie_name)
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
@@ -105,23 +132,20 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
 
     // Verify that the login_attempt_id is stored in the two_fa_code_store
     let login_attempt_id = json_body.login_attempt_id;
-    {
-        let two_fa_code_store = &app.two_fa_code_store;
-        let two_fa_code_store_lock = two_fa_code_store.read().await;
-
-        // Get the stored code for this email
-        let stored_code = two_fa_code_store_lock
-            .get_code(&Email::parse(Secret::new(email)).unwrap())
-            .await
-            .expect("2FA code should be stored for this email");
-
-        // Verify the login_attempt_id matches
-        assert_eq!(stored_code.0.as_ref(), login_attempt_id);
-
-        // Verify that a 6-digit code was generated (not checking exact value since it's random)
-        assert_eq!(stored_code.1.as_ref().len(), 6);
-        assert!(stored_code.1.as_ref().chars().all(|c| c.is_ascii_digit()));
-    }
+    app.two_fa_code_store
+        .read()
+        .await
+        .get_code_hash(
+            &Email::parse(Secret::new(email.clone())).unwrap(),
+            &LoginAttemptId::parse(login_attempt_id).unwrap(),
+        )
+        .await
+        .expect("2FA code should be stored for this login attempt");
+
+    // Verify that a 6-digit code was emailed (not checking exact value since it's random)
+    let emailed_code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+    assert_eq!(emailed_code.len(), 6);
+    assert!(emailed_code.chars().all(|c| c.is_ascii_digit()));
 }
 
 #[with_db_cleanup]
@@ -184,7 +208,7 @@ async fn should_return_422_if_malformed_credentials() {
 
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/login", &app.address))
+        .post(format!("{}/login", &app.address))
         .header("Content-Type", "application/json")
         .body(malformed_body)
         .send()
@@ -2,7 +2,7 @@ use auth_service::ErrorResponse;
 use reqwest::{cookie::CookieStore, StatusCode, Url};
 use test_macros::with_db_cleanup;
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, get_token_jti, TestApp};
 
 #[with_db_cleanup]
 #[tokio::test]
@@ -58,7 +58,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
         .banned_token_store
         .read()
         .await
-        .contains_token(&jwt_token)
+        .contains_token(&get_token_jti(&jwt_token))
         .await
         .expect("Failed to check banned token store");
 
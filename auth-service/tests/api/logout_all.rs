@@ -0,0 +1,120 @@
+use auth_service::ErrorResponse;
+use reqwest::{StatusCode, Url};
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp, email: &str, password: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+// Log in and return the JWT and refresh token of the new session
+async fn login(app: &TestApp, email: &str, password: &str) -> (String, String) {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let jwt = app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found");
+    let refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+    (jwt, refresh_token)
+}
+
+fn set_refresh_cookie(app: &TestApp, value: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Path=/",
+            app.settings.auth.refresh_cookie_name, value
+        ),
+        &Url::parse(&app.address).expect("Failed to parse URL"),
+    );
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_invalidate_every_session() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let password = "Password123!";
+    signup(&app, &email, password).await;
+
+    // Two sessions, e.g. on two different devices
+    let (other_jwt, other_refresh_token) = login(&app, &email, password).await;
+    let (current_jwt, _) = login(&app, &email, password).await;
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_none());
+
+    for token in [other_jwt, current_jwt] {
+        let token_body = serde_json::json!({ "token": token });
+        let response = app.post_verify_token(&token_body).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // The other session cannot obtain a new access token either
+    set_refresh_cookie(&app, &other_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // Logging in again starts a valid session
+    let (jwt, _) = login(&app, &email, password).await;
+    let token_body = serde_json::json!({ "token": jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    let response = app.post_logout_all().await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, "Missing token");
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_invalidate_sessions_of_deleted_account() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let password = "Password123!";
+    signup(&app, &email, password).await;
+    let (jwt, _) = login(&app, &email, password).await;
+
+    let delete_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.delete_account(&delete_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Tokens of the old account stay invalid even once the email is registered again
+    signup(&app, &email, password).await;
+    let token_body = serde_json::json!({ "token": jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,321 @@
+use auth_service::{
+    routes::{LoginResponse, MagicLinkResponse, Verify2FARequest},
+    ErrorResponse,
+};
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+const TOKEN_PARAM: &str = "magic_link_token";
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": requires_2fa,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+async fn request_link(app: &TestApp, email: &str) -> reqwest::Response {
+    post_link_request(app, email, serde_json::json!({ "email": email })).await
+}
+
+// The link is emailed in the background, so wait for it once the request succeeded
+async fn post_link_request(
+    app: &TestApp,
+    email: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    let sent_before = app.get_sent_emails(email).len();
+    let response = app.post_magic_link_request(&body).await;
+    if response.status() == StatusCode::OK {
+        app.wait_for_new_email(email, sent_before).await;
+    }
+    response
+}
+
+async fn confirm_link(app: &TestApp, token: &str) -> reqwest::Response {
+    app.post_magic_link_confirm(&serde_json::json!({ "token": token }))
+        .await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_log_in_with_magic_link() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, false).await;
+
+    let response = request_link(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let emails = app.get_sent_emails(&email);
+    assert_eq!(
+        emails.last().unwrap().subject,
+        format!("Your {} login link", app.settings.email.product_name)
+    );
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_none());
+
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_link(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let login_response = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Could not deserialize response body to LoginResponse");
+    assert_eq!(login_response, LoginResponse::RegularAuth);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_some());
+    assert!(app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .is_some());
+
+    // The link can only be used once
+    assert_error(
+        confirm_link(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_reveal_whether_account_exists() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, false).await;
+    let unknown_email = get_random_email();
+
+    let response = request_link(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let known = response.json::<MagicLinkResponse>().await.unwrap();
+
+    let response = request_link(&app, &unknown_email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let unknown = response.json::<MagicLinkResponse>().await.unwrap();
+
+    assert_eq!(known, unknown);
+    assert!(app.get_sent_emails(&unknown_email).is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_still_require_2fa() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, true).await;
+
+    request_link(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_link(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = match response.json::<LoginResponse>().await.unwrap() {
+        LoginResponse::TwoFactorAuth(response) => response.login_attempt_id,
+        response => panic!("Expected 2FA to be required, got {:?}", response),
+    };
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_none());
+
+    let verify_request = Verify2FARequest {
+        email: email.clone(),
+        login_attempt_id,
+        two_fa_code: app.get_two_fa_code(&email).expect("No 2FA code emailed"),
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_some());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_send_links_after_opt_out() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, false).await;
+
+    // A link sent before opting out
+    request_link(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.post_disable_magic_link().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    app.post_logout().await;
+
+    let emails_sent = app.get_sent_emails(&email).len();
+    let response = request_link(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent);
+
+    assert_error(
+        confirm_link(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_send_links_after_opting_back_in() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, false).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    app.post_disable_magic_link().await;
+    let response = app.post_enable_magic_link().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    app.post_logout().await;
+
+    request_link(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_link(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_recaptcha_after_repeated_requests() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app, false).await;
+
+    for _ in 0..3 {
+        let response = request_link(&app, &email).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let emails_sent = app.get_sent_emails(&email).len();
+    let response = request_link(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let login_response = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Could not deserialize response body to LoginResponse");
+    assert_eq!(login_response, LoginResponse::RecaptchaRequired);
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent);
+
+    let response = post_link_request(
+        &app,
+        &email,
+        serde_json::json!({
+            "email": email,
+            "recaptchaToken": "test_token"
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Using a link clears the failed attempts
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_link(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request_link(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_invalid_recaptcha() {
+    let mut app = TestApp::new(false).await;
+    // Requests are gated whether or not the account exists
+    let email = get_random_email();
+
+    for _ in 0..3 {
+        request_link(&app, &email).await;
+    }
+
+    let response = app
+        .post_magic_link_request(&serde_json::json!({
+            "email": email,
+            "recaptchaToken": "test_token"
+        }))
+        .await;
+    assert_error(response, StatusCode::BAD_REQUEST, "Invalid credentials").await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_verify_email() {
+    let mut app = TestApp::with_settings(true, |settings| {
+        settings.auth.require_email_verification = true
+    })
+    .await;
+    let email = signup(&app, false).await;
+
+    request_link(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_link(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_for_malformed_email() {
+    let mut app = TestApp::new(true).await;
+
+    assert_error(
+        request_link(&app, "not-an-email").await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_for_invalid_token() {
+    let mut app = TestApp::new(true).await;
+
+    for token in ["not-a-token", "00000000-0000-0000-0000-000000000000"] {
+        assert_error(
+            confirm_link(&app, token).await,
+            StatusCode::UNAUTHORIZED,
+            "Invalid token",
+        )
+        .await;
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    for response in [
+        app.post_enable_magic_link().await,
+        app.post_disable_magic_link().await,
+    ] {
+        assert_error(response, StatusCode::BAD_REQUEST, "Missing token").await;
+    }
+}
@@ -1,11 +1,30 @@
+mod change_email;
+mod change_password;
 mod delete_account;
+mod fake_smtp_server;
 mod helpers;
+mod http_email_client;
+mod jwks;
 mod login;
 mod logout;
+mod logout_all;
+mod magic_link;
+mod password_reset;
 mod progressive_recaptcha_login;
+mod promote_signing_key;
 mod recaptcha;
+mod recovery_codes;
+mod refresh;
+mod resend_2fa;
 mod root;
+mod sessions;
 mod signup;
+mod smtp_email_client;
+mod totp;
+mod trusted_devices;
 mod ttl_expiration;
+mod two_fa;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
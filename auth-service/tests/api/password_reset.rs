@@ -0,0 +1,206 @@
+use auth_service::ErrorResponse;
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+const NEW_PASSWORD: &str = "NewPassword456!";
+const TOKEN_PARAM: &str = "reset_password_token";
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password
+    });
+    app.post_login(&login_body).await
+}
+
+async fn request_reset(app: &TestApp, email: &str) -> reqwest::Response {
+    let body = serde_json::json!({
+        "email": email,
+        "recaptchaToken": "test_token"
+    });
+    let sent_before = app.get_sent_emails(email).len();
+    let response = app.post_password_reset_request(&body).await;
+    if response.status() == StatusCode::OK {
+        app.wait_for_new_email(email, sent_before).await;
+    }
+    response
+}
+
+async fn confirm_reset(app: &TestApp, token: &str, new_password: &str) -> reqwest::Response {
+    let body = serde_json::json!({
+        "token": token,
+        "newPassword": new_password
+    });
+    app.post_password_reset_confirm(&body).await
+}
+
+async fn assert_invalid_token(response: reqwest::Response) {
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, "Invalid token");
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reset_password() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let jwt = app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found");
+
+    let response = request_reset(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let emails = app.get_sent_emails(&email);
+    assert_eq!(
+        emails.last().unwrap().subject,
+        format!("Reset your {} password", app.settings.email.product_name)
+    );
+
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_reset(&app, &token, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Tokens issued before the reset are rejected
+    let token_body = serde_json::json!({ "token": jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = login(&app, &email, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_reveal_unknown_accounts() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let response = request_reset(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app.get_sent_emails(&email).is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_reused_token() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    request_reset(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+
+    let response = confirm_reset(&app, &token, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_invalid_token(confirm_reset(&app, &token, "OtherPassword789!").await).await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_keep_token_if_new_password_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    request_reset(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+
+    let response = confirm_reset(&app, &token, "short").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = confirm_reset(&app, &token, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_unknown_or_foreign_token() {
+    let mut app = TestApp::new(true).await;
+
+    // Email verification tokens cannot reset passwords
+    let email = signup(&app).await;
+    let verification_token = app.get_link_token(&email, "verify_email_token").unwrap();
+
+    for token in [
+        verification_token,
+        uuid::Uuid::new_v4().to_string(),
+        "not-a-token".to_owned(),
+    ] {
+        assert_invalid_token(confirm_reset(&app, &token, NEW_PASSWORD).await).await;
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_clear_login_failures() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    for _ in 0..3 {
+        let response = login(&app, &email, "WrongPassword123!").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    request_reset(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_reset(&app, &token, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // No reCAPTCHA is required anymore
+    let response = login(&app, &email, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_verify_email() {
+    let mut app = TestApp::with_settings(true, |settings| {
+        settings.auth.require_email_verification = true
+    })
+    .await;
+
+    let email = signup(&app).await;
+    request_reset(&app, &email).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = confirm_reset(&app, &token, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = login(&app, &email, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_recaptcha_fails() {
+    let mut app = TestApp::new(false).await;
+
+    let response = request_reset(&app, &get_random_email()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,105 @@
+use auth_service::ErrorResponse;
+use jsonwebtoken::decode_header;
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const ADMIN_API_KEY: &str = "test-admin-api-key";
+
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    app.post_signup(&signup_body).await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_string();
+    token
+}
+
+fn token_kid(token: &str) -> String {
+    decode_header(token)
+        .expect("Failed to decode JWT header")
+        .kid
+        .expect("JWT header has no kid")
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_sign_with_promoted_key_and_keep_accepting_previous_key() {
+    let mut app = TestApp::new(true).await;
+
+    let old_token = signup_and_login(&app, &get_random_email()).await;
+    assert_eq!(token_kid(&old_token), "test-ed25519");
+
+    let response = app
+        .post_promote_signing_key(
+            &serde_json::json!({ "kid": "test-rsa" }),
+            Some(ADMIN_API_KEY),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let new_token = signup_and_login(&app, &get_random_email()).await;
+    assert_eq!(token_kid(&new_token), "test-rsa");
+
+    // Tokens signed before the rotation stay valid during the overlap window
+    for token in [old_token, new_token] {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_key_is_unknown() {
+    let mut app = TestApp::new(true).await;
+
+    let response = app
+        .post_promote_signing_key(
+            &serde_json::json!({ "kid": "unknown" }),
+            Some(ADMIN_API_KEY),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_requests_without_valid_admin_key() {
+    let mut app = TestApp::new(true).await;
+    let body = serde_json::json!({ "kid": "test-rsa" });
+
+    let response = app.post_promote_signing_key(&body, None).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app.post_promote_signing_key(&body, Some("wrong-key")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, "Invalid token");
+
+    // The active signing key is unchanged
+    let token = signup_and_login(&app, &get_random_email()).await;
+    assert_eq!(token_kid(&token), "test-ed25519");
+}
@@ -0,0 +1,253 @@
+use auth_service::{
+    routes::{
+        LoginResponse, RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse,
+        Verify2FARequest,
+    },
+    ErrorResponse,
+};
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+// Sign up a user with 2FA, returning their email and recovery codes
+async fn signup(app: &TestApp) -> (String, Vec<String>) {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("No recovery codes issued");
+    (email, recovery_codes)
+}
+
+// Log in with the password, returning the login attempt id of the 2FA challenge
+async fn login(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+async fn verify_2fa(app: &TestApp, email: &str, login_attempt_id: &str, code: &str) -> StatusCode {
+    let verify_request = Verify2FARequest {
+        email: email.to_owned(),
+        login_attempt_id: login_attempt_id.to_owned(),
+        two_fa_code: code.to_owned(),
+        remember_device: false,
+    };
+    app.post_verify_2fa(&verify_request).await.status()
+}
+
+async fn regenerate(app: &TestApp, password: &str) -> reqwest::Response {
+    let body = serde_json::json!({ "password": password });
+    app.post_regenerate_recovery_codes(&body).await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_issue_distinct_recovery_codes_on_signup() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, codes) = signup(&app).await;
+    assert_eq!(codes.len(), 10);
+    for code in &codes {
+        assert_eq!(code.len(), 11);
+        assert_eq!(code.chars().nth(5), Some('-'));
+    }
+    let mut distinct = codes.clone();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), codes.len());
+
+    let user_id = app.get_user_id(&email).await;
+    let stored = app
+        .recovery_code_store
+        .read()
+        .await
+        .count_codes(&user_id)
+        .await
+        .unwrap();
+    assert_eq!(stored, 10);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_verify_2fa_with_recovery_code_once() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, codes) = signup(&app).await;
+    let login_attempt_id = login(&app, &email).await;
+
+    // Codes are accepted however the user types them in
+    let typed_code = codes[0].to_lowercase().replace('-', "");
+    let status = verify_2fa(&app, &email, &login_attempt_id, &typed_code).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_some());
+
+    let notification = app
+        .get_sent_emails(&email)
+        .pop()
+        .expect("No notification sent");
+    assert!(notification.subject.contains("recovery code was used"));
+    assert!(notification.text_body.contains("9 recovery codes left"));
+
+    // A used code is rejected, while the others still work
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &codes[0]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = verify_2fa(&app, &email, &login_attempt_id, &codes[1]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_recovery_code_is_unknown() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, _) = signup(&app).await;
+    let (_, other_codes) = signup(&app).await;
+    let login_attempt_id = login(&app, &email).await;
+
+    // Codes of other users and made up codes are rejected alike
+    for code in [other_codes[0].as_str(), "ABCDE-23456"] {
+        let status = verify_2fa(&app, &email, &login_attempt_id, code).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    // The code also needs a matching login attempt
+    let (other_email, _) = signup(&app).await;
+    let other_attempt_id = login(&app, &other_email).await;
+    let status = verify_2fa(&app, &email, &other_attempt_id, &other_codes[1]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    for code in ["ABCDE-2345", "ABCDE-1234O"] {
+        let status = verify_2fa(&app, &email, &login_attempt_id, code).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_regenerate_recovery_codes() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, old_codes) = signup(&app).await;
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &old_codes[0]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let response = regenerate(&app, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+    assert_eq!(new_codes.len(), 10);
+    assert!(new_codes.iter().all(|code| !old_codes.contains(code)));
+
+    // The earlier set stops working
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &old_codes[1]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = verify_2fa(&app, &email, &login_attempt_id, &new_codes[0]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_password_to_regenerate_recovery_codes() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, codes) = signup(&app).await;
+
+    assert_error(
+        regenerate(&app, PASSWORD).await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &codes[0]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_error(
+        regenerate(&app, "WrongPassword123!").await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    assert_error(
+        regenerate(&app, "short").await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+
+    // The codes are left as they were
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &codes[1]).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_recaptcha_after_repeated_incorrect_passwords() {
+    let mut app = TestApp::new(true).await;
+
+    let (email, codes) = signup(&app).await;
+    let login_attempt_id = login(&app, &email).await;
+    let status = verify_2fa(&app, &email, &login_attempt_id, &codes[0]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    for _ in 0..3 {
+        let response = regenerate(&app, "WrongPassword123!").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // Even the correct password is refused without reCAPTCHA now
+    let response = regenerate(&app, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+    let login_response = response
+        .json::<LoginResponse>()
+        .await
+        .expect("Could not deserialize response body to LoginResponse");
+    assert_eq!(login_response, LoginResponse::RecaptchaRequired);
+
+    let body = serde_json::json!({
+        "password": PASSWORD,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_regenerate_recovery_codes(&body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
@@ -0,0 +1,186 @@
+use auth_service::{
+    domain::{RefreshToken, RefreshTokenStoreError},
+    ErrorResponse,
+};
+use reqwest::{StatusCode, Url};
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp) -> String {
+    let email = get_random_email();
+    let password = "Password123!".to_string();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": password,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    app.post_signup(&signup_body).await;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    email
+}
+
+fn set_refresh_cookie(app: &TestApp, value: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Path=/",
+            app.settings.auth.refresh_cookie_name, value
+        ),
+        &Url::parse(&app.address).expect("Failed to parse URL"),
+    );
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn login_should_set_refresh_cookie() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+
+    let refresh_token = app.get_cookie_value(&app.settings.auth.refresh_cookie_name);
+    assert!(refresh_token.is_some(), "No refresh cookie found");
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_200_and_rotate_tokens() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+
+    let old_refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let cookies: Vec<_> = response.cookies().collect();
+    let auth_cookie = cookies
+        .iter()
+        .find(|c| c.name() == app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    let new_refresh_token = cookies
+        .iter()
+        .find(|c| c.name() == app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found")
+        .value()
+        .to_string();
+    assert_ne!(new_refresh_token, old_refresh_token);
+
+    // The freshly issued access token should be accepted
+    let token_body = serde_json::json!({ "token": auth_cookie.value() });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_revoke_family_if_rotated_token_is_reused() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup_and_login(&app).await;
+
+    let old_refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    // Legitimate rotation
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    // Replay the already rotated token
+    set_refresh_cookie(&app, &old_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The whole family is revoked, so even the newest token is rejected
+    set_refresh_cookie(&app, &new_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, "Invalid token");
+
+    // The killed session is no longer listed
+    let user_id = app.get_user_id(&email).await;
+    let sessions = app
+        .session_store
+        .read()
+        .await
+        .get_sessions(&user_id)
+        .await
+        .unwrap();
+    assert!(sessions.is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_after_logout() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+
+    let refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+
+    let response = app.post_logout().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // The refresh token family should be gone from the store
+    let parsed_token = RefreshToken::parse(refresh_token.clone()).unwrap();
+    let result = app
+        .refresh_token_store
+        .write()
+        .await
+        .rotate_token(&parsed_token)
+        .await;
+    assert_eq!(result.unwrap_err(), RefreshTokenStoreError::FamilyNotFound);
+
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, "Missing token");
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_refresh_token_malformed() {
+    let mut app = TestApp::new(true).await;
+
+    set_refresh_cookie(&app, "invalid");
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
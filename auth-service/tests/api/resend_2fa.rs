@@ -0,0 +1,183 @@
+use auth_service::{
+    domain::{Email, LoginAttemptId},
+    routes::{Resend2FARequest, Resend2FAResponse, TwoFactorAuthResponse, Verify2FARequest},
+    ErrorResponse,
+};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde_json::json;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup(app: &TestApp, email: &Email) {
+    let signup_body = json!({
+        "email": email.as_ref().expose_secret(),
+        "password": "Password123!",
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn start_login_attempt(app: &TestApp, email: &Email) -> LoginAttemptId {
+    let login_body = json!({
+        "email": email.as_ref().expose_secret(),
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    LoginAttemptId::parse(login_attempt_id).unwrap()
+}
+
+async fn resend(
+    app: &TestApp,
+    email: &Email,
+    login_attempt_id: &LoginAttemptId,
+) -> reqwest::Response {
+    let request = Resend2FARequest {
+        email: email.as_ref().expose_secret().to_string(),
+        login_attempt_id: login_attempt_id.as_ref().to_string(),
+    };
+    app.post_resend_2fa(&request).await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_send_new_code_for_login_attempt() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    let login_attempt_id = start_login_attempt(&app, &email).await;
+    let emails_sent = app.get_sent_emails(email.as_ref().expose_secret()).len();
+
+    let response = resend(&app, &email, &login_attempt_id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response
+        .json::<Resend2FAResponse>()
+        .await
+        .expect("Could not deserialize response body to Resend2FAResponse");
+    assert_eq!(body.message, "2FA code sent");
+    assert_eq!(
+        app.get_sent_emails(email.as_ref().expose_secret()).len(),
+        emails_sent + 1
+    );
+
+    // The new code completes the login
+    let code = app
+        .get_two_fa_code(email.as_ref().expose_secret())
+        .expect("No 2FA code emailed");
+    let verify_request = Verify2FARequest {
+        email: email.as_ref().expose_secret().to_string(),
+        login_attempt_id: login_attempt_id.as_ref().to_string(),
+        two_fa_code: code,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // And ends the login attempt, which has no code to resend anymore
+    assert_error(
+        resend(&app, &email, &login_attempt_id).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_limit_resends_per_login_attempt() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    let login_attempt_id = start_login_attempt(&app, &email).await;
+    for _ in 0..app.settings.auth.max_2fa_resends {
+        let response = resend(&app, &email, &login_attempt_id).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let emails_sent = app.get_sent_emails(email.as_ref().expose_secret()).len();
+
+    assert_error(
+        resend(&app, &email, &login_attempt_id).await,
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many requests",
+    )
+    .await;
+    assert_eq!(
+        app.get_sent_emails(email.as_ref().expose_secret()).len(),
+        emails_sent
+    );
+
+    // Other login attempts have their own resends
+    let other_login_attempt_id = start_login_attempt(&app, &email).await;
+    let response = resend(&app, &email, &other_login_attempt_id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_for_unknown_login_attempt() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+
+    // Unknown users
+    assert_error(
+        resend(&app, &email, &LoginAttemptId::default()).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    // Login attempts the user never started
+    signup(&app, &email).await;
+    start_login_attempt(&app, &email).await;
+    assert_error(
+        resend(&app, &email, &LoginAttemptId::default()).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new(true).await;
+
+    let test_cases = [
+        json!({
+            "email": "invalid_email",
+            "loginAttemptId": LoginAttemptId::default().as_ref(),
+        }),
+        json!({
+            "email": get_random_email(),
+            "loginAttemptId": "invalid_login_attempt_id",
+        }),
+    ];
+    for test_case in test_cases {
+        assert_error(
+            app.post_resend_2fa(&test_case).await,
+            StatusCode::BAD_REQUEST,
+            "Invalid input",
+        )
+        .await;
+    }
+}
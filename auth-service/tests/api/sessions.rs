@@ -0,0 +1,248 @@
+use auth_service::{
+    domain::RefreshToken,
+    routes::{SessionsResponse, TwoFactorAuthResponse, Verify2FARequest},
+    ErrorResponse,
+};
+use reqwest::{header::USER_AGENT, StatusCode};
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": requires_2fa,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+// Log in from a client with the given user agent and return the JWT of the new session
+async fn login(app: &TestApp, email: &str, user_agent: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header(USER_AGENT, user_agent)
+        .json(&login_body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    app.get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .expect("No auth cookie found")
+}
+
+async fn get_sessions(app: &TestApp) -> SessionsResponse {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse")
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_list_sessions_of_caller() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    login(&app, &email, "laptop").await;
+
+    // Sessions of other users are not listed
+    let other_email = signup(&app, false).await;
+    login(&app, &other_email, "other").await;
+
+    login(&app, &email, "phone").await;
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 2);
+
+    let current: Vec<_> = sessions.iter().filter(|s| s.current).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].session.user_agent.as_deref(), Some("phone"));
+
+    for session in &sessions {
+        assert_eq!(session.session.ip_address.as_deref(), Some("127.0.0.1"));
+        assert!(!session.session.two_fa_used);
+    }
+    assert!(sessions
+        .iter()
+        .any(|s| s.session.user_agent.as_deref() == Some("laptop")));
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_record_session_started_with_2fa() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, true).await;
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let two_fa_code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+
+    let verify_request = Verify2FARequest {
+        email,
+        login_attempt_id,
+        two_fa_code,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].session.two_fa_used);
+    assert!(sessions[0].current);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_keep_session_across_refresh() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    login(&app, &email, "laptop").await;
+    let session_id = get_sessions(&app).await.sessions[0].session.id.clone();
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].session.id, session_id);
+    assert!(sessions[0].current);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_revoke_other_session() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    let laptop_jwt = login(&app, &email, "laptop").await;
+    let laptop_refresh_token = app
+        .get_cookie_value(&app.settings.auth.refresh_cookie_name)
+        .expect("No refresh cookie found");
+    login(&app, &email, "phone").await;
+
+    let laptop_session = get_sessions(&app)
+        .await
+        .sessions
+        .into_iter()
+        .find(|s| !s.current)
+        .expect("No other session found");
+
+    let response = app.delete_session(&laptop_session.session.id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Every token of the revoked session is rejected
+    let token_body = serde_json::json!({ "token": laptop_jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let result = app
+        .refresh_token_store
+        .write()
+        .await
+        .rotate_token(&RefreshToken::parse(laptop_refresh_token).unwrap())
+        .await;
+    assert!(result.is_err());
+
+    // The current session is untouched
+    let sessions = get_sessions(&app).await.sessions;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_log_out_when_revoking_current_session() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    let jwt = login(&app, &email, "laptop").await;
+    let session_id = get_sessions(&app).await.sessions[0].session.id.clone();
+
+    let response = app.delete_session(&session_id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_none());
+
+    let token_body = serde_json::json!({ "token": jwt });
+    let response = app.post_verify_token(&token_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let user_id = app.get_user_id(&email).await;
+    let sessions = app.session_store.read().await.get_sessions(&user_id).await;
+    assert!(sessions.unwrap().is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_404_if_session_not_found() {
+    let mut app = TestApp::new(true).await;
+
+    // A session of another user cannot be revoked
+    let other_email = signup(&app, false).await;
+    login(&app, &other_email, "other").await;
+    let other_session_id = get_sessions(&app).await.sessions[0].session.id.clone();
+
+    let email = signup(&app, false).await;
+    login(&app, &email, "laptop").await;
+
+    for session_id in [other_session_id.as_str(), "unknown"] {
+        let response = app.delete_session(session_id).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let error_response = response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse");
+        assert_eq!(error_response.error, "Session not found");
+    }
+
+    let other_user_id = app.get_user_id(&other_email).await;
+    let sessions = app
+        .session_store
+        .read()
+        .await
+        .get_sessions(&other_user_id)
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    let response = app.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = app.delete_session("unknown").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -1,4 +1,11 @@
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use std::sync::Arc;
+
+use auth_service::{
+    domain::{Email, EmailClient, EmailMessage},
+    routes::SignupResponse,
+    ErrorResponse,
+};
+use color_eyre::eyre::{eyre, Result};
 use test_macros::with_db_cleanup;
 
 use crate::helpers::{get_random_email, TestApp};
@@ -17,12 +24,34 @@ async fn should_return_201_if_valid_input() {
         }))
         .await;
 
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Assert that we are getting the correct response body!
+    let signup_response = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
+    assert_eq!(signup_response.message, "User created successfully!");
+    // Users signing up with 2FA get their recovery codes
+    assert_eq!(
+        signup_response.recovery_codes.map(|codes| codes.len()),
+        Some(10)
+    );
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "Password123!",
+            "requires2FA": false,
+            "recaptchaToken": "test_token"
+        }))
+        .await;
+
     assert_eq!(response.status().as_u16(), 201);
     let expected_response = SignupResponse {
         message: "User created successfully!".to_owned(),
+        recovery_codes: None,
     };
-
-    // Assert that we are getting the correct response body!
     assert_eq!(
         response
             .json::<SignupResponse>()
@@ -140,6 +169,27 @@ async fn should_return_409_if_email_already_exists() {
     );
 }
 
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_409_if_email_differs_only_in_case() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    for (email, status) in [
+        (email.clone(), 201),
+        (format!(" {} ", email.to_uppercase()), 409),
+    ] {
+        let signup_body = serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false,
+            "recaptchaToken": "test_token"
+        });
+        let response = app.post_signup(&signup_body).await;
+        assert_eq!(response.status().as_u16(), status);
+    }
+}
+
 #[with_db_cleanup]
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -170,3 +220,39 @@ async fn should_return_422_if_malformed_input() {
         );
     }
 }
+
+struct FailingEmailClient;
+
+#[async_trait::async_trait]
+impl EmailClient for FailingEmailClient {
+    async fn send_multipart_email(&self, _: &Email, _: &EmailMessage) -> Result<()> {
+        Err(eyre!("email provider is down"))
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_create_user_if_verification_email_fails() {
+    let mut app = TestApp::with_email_client(true, Arc::new(FailingEmailClient)).await;
+    let signup_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "Password123!",
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let signup_response = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+    assert_eq!(
+        signup_response.message,
+        "User created successfully! The verification email could not be sent, please request a new one"
+    );
+
+    // The user can log in right away when verification is not required
+    let response = app.post_login(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use auth_service::{
+    config::{Settings, SmtpConfig, SmtpSecurity},
+    domain::{Email, EmailClient, EmailMessage},
+    routes::{TwoFactorAuthResponse, Verify2FARequest},
+    services::SmtpEmailClient,
+};
+use reqwest::StatusCode;
+use secrecy::Secret;
+use test_macros::with_db_cleanup;
+
+use crate::{
+    fake_smtp_server::FakeSmtpServer,
+    helpers::{get_random_email, TestApp},
+};
+
+const SENDER: &str = "Auth Service <no-reply@example.com>";
+
+fn smtp_client(port: u16, username: &str, password: &str) -> SmtpEmailClient {
+    let config = SmtpConfig {
+        host: "127.0.0.1".to_owned(),
+        port,
+        security: SmtpSecurity::None,
+        username: username.to_owned(),
+        password: Secret::new(password.to_owned()),
+        timeout_seconds: 5,
+    };
+    SmtpEmailClient::new(&config, SENDER).expect("Failed to build SMTP email client")
+}
+
+fn recipient() -> Email {
+    Email::parse(Secret::new("recipient@example.com".to_owned())).unwrap()
+}
+
+fn message() -> EmailMessage {
+    EmailMessage {
+        subject: "Test subject".to_owned(),
+        html_body: "<p>HTML content</p>".to_owned(),
+        text_body: "Text content".to_owned(),
+    }
+}
+
+#[tokio::test]
+async fn should_deliver_email_over_smtp() {
+    let server = FakeSmtpServer::start().await;
+    let client = smtp_client(server.port, "smtp-user", "smtp-password");
+
+    client
+        .send_multipart_email(&recipient(), &message())
+        .await
+        .expect("Failed to send email");
+
+    let messages = server.messages();
+    assert_eq!(messages.len(), 1);
+    let message = &messages[0];
+    assert_eq!(message.from, "no-reply@example.com");
+    assert_eq!(message.recipients, vec!["recipient@example.com".to_owned()]);
+    let data = &message.data;
+    assert!(data.contains("From: \"Auth Service\" <no-reply@example.com>"));
+    assert!(data.contains("To: recipient@example.com"));
+    assert!(data.contains("Subject: Test subject"));
+    assert!(data.contains("Content-Type: multipart/alternative"));
+    assert!(data.contains("Content-Type: text/plain"));
+    assert!(data.contains("Content-Type: text/html"));
+    assert!(data.contains("Text content"));
+    assert!(data.contains("<p>HTML content</p>"));
+
+    assert_eq!(
+        server.credentials(),
+        vec![("smtp-user".to_owned(), "smtp-password".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn should_not_authenticate_without_username() {
+    let server = FakeSmtpServer::start().await;
+    let client = smtp_client(server.port, "", "");
+
+    client
+        .send_multipart_email(&recipient(), &message())
+        .await
+        .expect("Failed to send email");
+
+    assert_eq!(server.messages().len(), 1);
+    assert!(server.credentials().is_empty());
+}
+
+#[tokio::test]
+async fn should_fail_if_smtp_server_unreachable() {
+    // Nothing listens on port 1
+    let client = smtp_client(1, "", "");
+
+    let result = client.send_multipart_email(&recipient(), &message()).await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn should_build_client_from_settings() {
+    let settings = Settings::new().expect("Failed to load configuration");
+    let client = SmtpEmailClient::new(&settings.email.smtp, &settings.email.sender);
+    assert!(client.is_ok());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_deliver_2fa_code_over_smtp() {
+    let server = FakeSmtpServer::start().await;
+    let email_client = Arc::new(smtp_client(server.port, "", ""));
+    let mut app = TestApp::with_email_client(true, email_client).await;
+
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    // The verification email sent on signup comes first
+    let messages = server.messages();
+    assert_eq!(messages.len(), 2);
+    let message = &messages[1];
+    assert_eq!(message.recipients, vec![email.clone()]);
+    assert!(message.data.contains(&format!(
+        "Subject: Your {} login code",
+        app.settings.email.product_name
+    )));
+
+    // The code in the delivered email completes the login
+    let two_fa_code = message
+        .data
+        .lines()
+        .find(|line| line.len() == 6 && line.chars().all(|c| c.is_ascii_digit()))
+        .expect("No 2FA code in email")
+        .to_owned();
+
+    let verify_request = Verify2FARequest {
+        email,
+        login_attempt_id,
+        two_fa_code,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
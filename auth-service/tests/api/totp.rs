@@ -0,0 +1,249 @@
+use auth_service::{
+    domain::TwoFAMethod,
+    routes::{TotpEnrollmentResponse, TwoFactorAuthResponse, Verify2FARequest},
+    utils::totp,
+    ErrorResponse,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use secrecy::ExposeSecret;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+async fn signup(app: &TestApp, requires_2fa: bool) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": requires_2fa,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+// Log in, returning the 2FA challenge when the user requires one
+async fn login(app: &TestApp, email: &str) -> Option<TwoFactorAuthResponse> {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let response = app.post_login(&login_body).await;
+    match response.status() {
+        StatusCode::OK => None,
+        StatusCode::PARTIAL_CONTENT => Some(
+            response
+                .json::<TwoFactorAuthResponse>()
+                .await
+                .expect("Could not deserialize response body to TwoFactorAuthResponse"),
+        ),
+        status => panic!("Unexpected login status {}", status),
+    }
+}
+
+async fn verify_2fa(app: &TestApp, email: &str, login_attempt_id: &str, code: &str) -> StatusCode {
+    let verify_request = Verify2FARequest {
+        email: email.to_owned(),
+        login_attempt_id: login_attempt_id.to_owned(),
+        two_fa_code: code.to_owned(),
+        remember_device: false,
+    };
+    app.post_verify_2fa(&verify_request).await.status()
+}
+
+async fn enroll(app: &TestApp) -> TotpEnrollmentResponse {
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse")
+}
+
+async fn confirm(app: &TestApp, code: &str) -> reqwest::Response {
+    let body = serde_json::json!({ "code": code });
+    app.post_totp_confirm(&body).await
+}
+
+// Code the authenticator app of the user shows `steps_ahead` time steps from now
+async fn authenticator_code(app: &TestApp, email: &str, steps_ahead: u64) -> String {
+    let user_id = app.get_user_id(email).await;
+    let enrollment = app
+        .totp_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+        .expect("Failed to get TOTP secret");
+    let step = totp::time_step(Utc::now().timestamp() as u64) + steps_ahead;
+    totp::code_at(&enrollment.secret, step)
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_enroll_and_confirm_totp() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    login(&app, &email).await;
+
+    let enrollment = enroll(&app).await;
+    let user_id = app.get_user_id(&email).await;
+    let stored = app
+        .totp_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+        .unwrap();
+    assert!(!stored.confirmed);
+    assert_eq!(
+        enrollment.secret,
+        *stored.secret.to_base32().expose_secret()
+    );
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(enrollment
+        .otpauth_uri
+        .contains(&format!("secret={}", enrollment.secret)));
+
+    let wrong_code = match authenticator_code(&app, &email, 0).await.as_str() {
+        "000000" => "111111",
+        _ => "000000",
+    };
+    assert_error(
+        confirm(&app, wrong_code).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    let code = authenticator_code(&app, &email, 0).await;
+    let response = confirm(&app, &code).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // A confirmed authenticator cannot be replaced by enrolling again
+    assert_error(
+        app.post_totp_enroll().await,
+        StatusCode::CONFLICT,
+        "TOTP already enabled",
+    )
+    .await;
+    assert_error(
+        confirm(&app, &code).await,
+        StatusCode::CONFLICT,
+        "TOTP already enabled",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_replace_pending_secret_when_enrolling_again() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    login(&app, &email).await;
+
+    let first = enroll(&app).await;
+    let second = enroll(&app).await;
+    assert_ne!(first.secret, second.secret);
+
+    let code = authenticator_code(&app, &email, 0).await;
+    let response = confirm(&app, &code).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_verify_2fa_with_totp_once_enabled() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, true).await;
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Email);
+    let emailed_code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+    let status = verify_2fa(&app, &email, &challenge.login_attempt_id, &emailed_code).await;
+    assert_eq!(status, StatusCode::OK);
+
+    enroll(&app).await;
+    let code = authenticator_code(&app, &email, 0).await;
+    assert_eq!(confirm(&app, &code).await.status(), StatusCode::OK);
+
+    // No code is emailed anymore
+    let emails_sent = app.get_sent_emails(&email).len();
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Totp);
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent);
+
+    // The code used to confirm the enrollment cannot be used again, but the next one
+    // is accepted within the drift window
+    let status = verify_2fa(&app, &email, &challenge.login_attempt_id, &code).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let next_code = authenticator_code(&app, &email, 1).await;
+    let status = verify_2fa(&app, &email, &challenge.login_attempt_id, &next_code).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Nor can a code that logged in once log in again
+    let challenge = login(&app, &email).await.expect("2FA required");
+    let status = verify_2fa(&app, &email, &challenge.login_attempt_id, &next_code).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_input_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app, false).await;
+    login(&app, &email).await;
+
+    // Nothing to confirm before enrolling
+    assert_error(
+        confirm(&app, "123456").await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+
+    enroll(&app).await;
+    for code in ["", "12345", "1234567", "abcdef"] {
+        assert_error(
+            confirm(&app, code).await,
+            StatusCode::BAD_REQUEST,
+            "Invalid input",
+        )
+        .await;
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    assert_error(
+        app.post_totp_enroll().await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+    assert_error(
+        confirm(&app, "123456").await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+}
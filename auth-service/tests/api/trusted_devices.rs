@@ -0,0 +1,286 @@
+use auth_service::{
+    domain::TrustedDevice,
+    routes::{TrustedDevicesResponse, TwoFactorAuthResponse, Verify2FARequest},
+    ErrorResponse,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password
+    });
+    app.post_login(&login_body).await
+}
+
+// Log in with the emailed 2FA code, trusting the browser if `remember_device` is set
+async fn login_with_2fa(app: &TestApp, email: &str, remember_device: bool) {
+    let response = login(app, email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let verify_request = Verify2FARequest {
+        email: email.to_owned(),
+        login_attempt_id,
+        two_fa_code: app.get_two_fa_code(email).expect("No 2FA code emailed"),
+        remember_device,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn get_trusted_devices(app: &TestApp) -> TrustedDevicesResponse {
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<TrustedDevicesResponse>()
+        .await
+        .expect("Could not deserialize response body to TrustedDevicesResponse")
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_skip_2fa_on_trusted_device() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+    assert!(app
+        .get_cookie_value(&app.settings.auth.trusted_device_cookie_name)
+        .is_some());
+    app.post_logout().await;
+
+    // The password alone logs in, and no code is emailed
+    let emails_sent = app.get_sent_emails(&email).len();
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app
+        .get_cookie_value(&app.settings.auth.jwt_cookie_name)
+        .is_some());
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent);
+
+    // The password is still checked
+    assert_error(
+        login(&app, &email, "WrongPassword123!").await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_only_trust_device_when_asked() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, false).await;
+    assert!(app
+        .get_cookie_value(&app.settings.auth.trusted_device_cookie_name)
+        .is_none());
+    assert!(get_trusted_devices(&app).await.devices.is_empty());
+    app.post_logout().await;
+
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_only_trust_device_for_its_user() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+    let other_email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+    app.post_logout().await;
+
+    let response = login(&app, &other_email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+    // Forged cookies are not trusted either
+    let cookie = format!(
+        "{}={}.{}; Path=/",
+        app.settings.auth.trusted_device_cookie_name,
+        uuid::Uuid::new_v4(),
+        "0".repeat(64)
+    );
+    app.cookie_jar
+        .add_cookie_str(&cookie, &app.address.parse().unwrap());
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_list_and_revoke_trusted_device() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+    let devices = get_trusted_devices(&app).await.devices;
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].current);
+    assert_eq!(devices[0].device.ip_address.as_deref(), Some("127.0.0.1"));
+    assert!(devices[0].device.expires_at > devices[0].device.created_at);
+
+    let response = app.delete_trusted_device(&devices[0].device.id).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(get_trusted_devices(&app).await.devices.is_empty());
+    assert_error(
+        app.delete_trusted_device(&devices[0].device.id).await,
+        StatusCode::NOT_FOUND,
+        "Trusted device not found",
+    )
+    .await;
+
+    app.post_logout().await;
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_revoke_all_trusted_devices() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+
+    // Another browser of the user
+    let now = Utc::now().timestamp();
+    let other_device = TrustedDevice {
+        id: uuid::Uuid::new_v4().to_string(),
+        created_at: now,
+        expires_at: now + 3600,
+        user_agent: Some("other".to_owned()),
+        ip_address: None,
+    };
+    let user_id = app.get_user_id(&email).await;
+    app.trusted_device_store
+        .write()
+        .await
+        .add_device(&user_id, other_device)
+        .await
+        .unwrap();
+    assert_eq!(get_trusted_devices(&app).await.devices.len(), 2);
+
+    let response = app.delete_trusted_devices().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(get_trusted_devices(&app).await.devices.is_empty());
+
+    app.post_logout().await;
+    let response = login(&app, &email, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_revoke_trusted_devices_on_password_change() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+    let new_password = "NewPassword123!";
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": PASSWORD,
+            "newPassword": new_password
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(get_trusted_devices(&app).await.devices.is_empty());
+
+    app.post_logout().await;
+    let response = login(&app, &email, new_password).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_revoke_trusted_devices_on_password_reset() {
+    let mut app = TestApp::new(true).await;
+    let email = signup(&app).await;
+
+    login_with_2fa(&app, &email, true).await;
+    app.post_logout().await;
+
+    let sent_before = app.get_sent_emails(&email).len();
+    let response = app
+        .post_password_reset_request(&serde_json::json!({
+            "email": email,
+            "recaptchaToken": "test_token"
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    app.wait_for_new_email(&email, sent_before).await;
+    let token = app.get_link_token(&email, "reset_password_token").unwrap();
+    let new_password = "NewPassword123!";
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": new_password
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let user_id = app.get_user_id(&email).await;
+    let devices = app
+        .trusted_device_store
+        .read()
+        .await
+        .get_devices(&user_id)
+        .await
+        .unwrap();
+    assert!(devices.is_empty());
+
+    let response = login(&app, &email, new_password).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    for response in [
+        app.get_trusted_devices().await,
+        app.delete_trusted_devices().await,
+        app.delete_trusted_device(&uuid::Uuid::new_v4().to_string())
+            .await,
+    ] {
+        assert_error(response, StatusCode::BAD_REQUEST, "Missing token").await;
+    }
+}
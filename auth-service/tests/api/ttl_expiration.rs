@@ -1,13 +1,14 @@
+use chrono::Utc;
 use reqwest::{cookie::CookieStore, StatusCode};
 use secrecy::Secret;
 use test_macros::with_db_cleanup;
 use tokio::time::{sleep, Duration};
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, get_token_claims, TestApp};
 
 #[with_db_cleanup]
 #[tokio::test]
-async fn banned_token_expires_after_ttl() {
+async fn banned_token_expires_with_token() {
     let mut app = TestApp::new(true).await;
 
     // Create and login a user to get a valid JWT token
@@ -49,6 +50,8 @@ async fn banned_token_expires_after_ttl() {
         .unwrap()
         .trim()
         .to_string();
+    let claims = get_token_claims(&jwt_token);
+    let jti = claims.jti.clone().expect("JWT has no jti");
 
     // Logout to add token to banned store
     let logout_response = app.post_logout().await;
@@ -59,7 +62,7 @@ async fn banned_token_expires_after_ttl() {
         .banned_token_store
         .read()
         .await
-        .contains_token(&jwt_token)
+        .contains_token(&jti)
         .await
         .expect("Failed to check banned token store");
 
@@ -68,23 +71,47 @@ async fn banned_token_expires_after_ttl() {
         "JWT token should be in the banned token store immediately after logout"
     );
 
-    // Construct the Redis key for the banned token
-    let redis_key = app.get_banned_token_redis_key(&jwt_token);
-
-    // Verify the key exists in Redis and has a TTL
+    // The ban is stored under the jti rather than the full token
+    let redis_key = app.get_banned_token_redis_key(&jti);
     assert!(
         app.redis_key_exists(&redis_key).await,
         "Banned token key should exist in Redis"
     );
 
+    // The ban lasts as long as the token could still be accepted
+    let remaining_lifetime =
+        claims.exp as i64 + app.settings.auth.leeway_seconds as i64 - Utc::now().timestamp();
     let ttl = app.get_redis_ttl(&redis_key).await;
     assert!(
-        ttl > 0,
-        "Banned token should have a positive TTL, got: {}",
+        (remaining_lifetime - 2..=remaining_lifetime).contains(&ttl),
+        "Banned token TTL should match the remaining token lifetime of {}s, got: {}",
+        remaining_lifetime,
         ttl
     );
+}
 
-    // Wait for TTL to expire (config/test.toml sets TTL to 1 second)
+#[with_db_cleanup]
+#[tokio::test]
+async fn banned_token_expires_at_token_expiry() {
+    let mut app = TestApp::new(true).await;
+
+    // Ban a token that expires in one second
+    let jti = uuid::Uuid::new_v4().to_string();
+    let expires_at = (Utc::now().timestamp() + 1) as u64;
+    app.banned_token_store
+        .write()
+        .await
+        .store_token(jti.clone(), expires_at)
+        .await
+        .expect("Failed to ban token");
+
+    let redis_key = app.get_banned_token_redis_key(&jti);
+    assert!(
+        app.redis_key_exists(&redis_key).await,
+        "Banned token key should exist in Redis"
+    );
+
+    // Wait for the token expiry to pass
     sleep(Duration::from_secs(2)).await;
 
     // Verify the token is no longer banned (expired from Redis)
@@ -92,13 +119,13 @@ async fn banned_token_expires_after_ttl() {
         .banned_token_store
         .read()
         .await
-        .contains_token(&jwt_token)
+        .contains_token(&jti)
         .await
         .expect("Failed to check banned token store");
 
     assert!(
         !is_still_banned,
-        "JWT token should no longer be banned after TTL expiration"
+        "JWT token should no longer be banned after it expired"
     );
 
     // Also verify the key no longer exists in Redis
@@ -135,13 +162,22 @@ async fn two_fa_code_expires_after_ttl() {
 
     let login_response = app.post_login(&login_body).await;
     assert_eq!(login_response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = login_response
+        .json::<auth_service::routes::TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let login_attempt_id = auth_service::domain::LoginAttemptId::parse(login_attempt_id).unwrap();
 
     // Verify the 2FA code exists in the store
     let two_fa_result = app
         .two_fa_code_store
         .read()
         .await
-        .get_code(&auth_service::domain::Email::parse(Secret::new(email.clone())).unwrap())
+        .get_code_hash(
+            &auth_service::domain::Email::parse(Secret::new(email.clone())).unwrap(),
+            &login_attempt_id,
+        )
         .await;
 
     assert!(
@@ -150,7 +186,7 @@ async fn two_fa_code_expires_after_ttl() {
     );
 
     // Construct the Redis key for the 2FA code
-    let redis_key = app.get_two_fa_code_redis_key(&email);
+    let redis_key = app.get_two_fa_code_redis_key(&email, login_attempt_id.as_ref());
 
     // Verify the key exists in Redis and has a TTL
     assert!(
@@ -169,7 +205,10 @@ async fn two_fa_code_expires_after_ttl() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&auth_service::domain::Email::parse(Secret::new(email.clone())).unwrap())
+        .get_code_hash(
+            &auth_service::domain::Email::parse(Secret::new(email.clone())).unwrap(),
+            &login_attempt_id,
+        )
         .await;
 
     assert!(
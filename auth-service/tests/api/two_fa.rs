@@ -0,0 +1,388 @@
+use auth_service::{
+    domain::TwoFAMethod,
+    routes::{EnableTwoFAResponse, LoginResponse, TwoFactorAuthResponse, Verify2FARequest},
+    utils::totp,
+    ErrorResponse,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+// Log in, returning the 2FA challenge when the user requires one
+async fn login(app: &TestApp, email: &str) -> Option<TwoFactorAuthResponse> {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let response = app.post_login(&login_body).await;
+    match response.status() {
+        StatusCode::OK => None,
+        StatusCode::PARTIAL_CONTENT => Some(
+            response
+                .json::<TwoFactorAuthResponse>()
+                .await
+                .expect("Could not deserialize response body to TwoFactorAuthResponse"),
+        ),
+        status => panic!("Unexpected login status {}", status),
+    }
+}
+
+async fn enable(app: &TestApp, body: serde_json::Value) -> EnableTwoFAResponse {
+    let response = app.post_enable_2fa(&body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<EnableTwoFAResponse>()
+        .await
+        .expect("Could not deserialize response body to EnableTwoFAResponse")
+}
+
+// Enroll and confirm an authenticator app for the logged in user
+async fn set_up_totp(app: &TestApp, email: &str) {
+    assert_eq!(app.post_totp_enroll().await.status(), StatusCode::OK);
+    let code = authenticator_code(app, email, 0).await;
+    let body = serde_json::json!({ "code": code });
+    assert_eq!(app.post_totp_confirm(&body).await.status(), StatusCode::OK);
+}
+
+// Code the authenticator app of the user shows `steps_ahead` time steps from now
+async fn authenticator_code(app: &TestApp, email: &str, steps_ahead: u64) -> String {
+    let user_id = app.get_user_id(email).await;
+    let enrollment = app
+        .totp_store
+        .read()
+        .await
+        .get_secret(&user_id)
+        .await
+        .expect("Failed to get TOTP secret");
+    let step = totp::time_step(Utc::now().timestamp() as u64) + steps_ahead;
+    totp::code_at(&enrollment.secret, step)
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_enable_2fa_after_signup() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    assert!(login(&app, &email).await.is_none());
+
+    let response = enable(&app, serde_json::json!({})).await;
+    assert_eq!(response.message, "2FA enabled");
+    assert_eq!(response.recovery_codes.len(), 10);
+
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Email);
+
+    // The emailed code completes the login
+    let code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+    let verify_request = Verify2FARequest {
+        email: email.clone(),
+        login_attempt_id: challenge.login_attempt_id,
+        two_fa_code: code,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_error(
+        app.post_enable_2fa(&serde_json::json!({})).await,
+        StatusCode::CONFLICT,
+        "2FA already enabled",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_only_enable_methods_that_are_set_up() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+
+    for method in ["totp", "passkey"] {
+        assert_error(
+            app.post_enable_2fa(&serde_json::json!({ "method": method }))
+                .await,
+            StatusCode::BAD_REQUEST,
+            "2FA method not set up",
+        )
+        .await;
+    }
+    assert!(login(&app, &email).await.is_none());
+
+    set_up_totp(&app, &email).await;
+    enable(&app, serde_json::json!({ "method": "totp" })).await;
+
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Totp);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_use_chosen_2fa_method() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    set_up_totp(&app, &email).await;
+    enable(&app, serde_json::json!({})).await;
+
+    // Without a choice the authenticator app is used
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Totp);
+    let verify_request = Verify2FARequest {
+        email: email.clone(),
+        login_attempt_id: challenge.login_attempt_id,
+        two_fa_code: authenticator_code(&app, &email, 1).await,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .post_2fa_method(&serde_json::json!({ "method": "email", "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Codes are now emailed, and codes of the app are no longer accepted
+    let emails_sent = app.get_sent_emails(&email).len();
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Email);
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent + 1);
+
+    let emailed_code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+    let app_code = authenticator_code(&app, &email, 1).await;
+    if app_code != emailed_code {
+        let verify_request = Verify2FARequest {
+            email: email.clone(),
+            login_attempt_id: challenge.login_attempt_id.clone(),
+            two_fa_code: app_code,
+            remember_device: false,
+        };
+        let response = app.post_verify_2fa(&verify_request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+    let verify_request = Verify2FARequest {
+        email: email.clone(),
+        login_attempt_id: challenge.login_attempt_id,
+        two_fa_code: emailed_code,
+        remember_device: false,
+    };
+    let response = app.post_verify_2fa(&verify_request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Clearing the choice goes back to the strongest method
+    let response = app
+        .post_2fa_method(&serde_json::json!({ "method": null, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Totp);
+
+    assert_error(
+        app.post_2fa_method(&serde_json::json!({ "method": "passkey", "password": PASSWORD }))
+            .await,
+        StatusCode::BAD_REQUEST,
+        "2FA method not set up",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_reauthentication_to_change_2fa_method() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    set_up_totp(&app, &email).await;
+    enable(&app, serde_json::json!({ "method": "totp" })).await;
+
+    // A stolen session alone cannot downgrade to codes sent by email
+    assert_error(
+        app.post_2fa_method(&serde_json::json!({ "method": "email" }))
+            .await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+    assert_error(
+        app.post_2fa_method(&serde_json::json!({
+            "method": "email",
+            "password": "WrongPassword123!"
+        }))
+        .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    let challenge = login(&app, &email).await.expect("2FA required");
+    assert_eq!(challenge.method, TwoFAMethod::Totp);
+
+    let code = authenticator_code(&app, &email, 1).await;
+    let response = app
+        .post_2fa_method(&serde_json::json!({ "method": "email", "code": code }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_require_recaptcha_after_repeated_failed_reauthentication() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    enable(&app, serde_json::json!({})).await;
+
+    for _ in 0..3 {
+        assert_error(
+            app.post_disable_2fa(&serde_json::json!({ "password": "WrongPassword123!" }))
+                .await,
+            StatusCode::UNAUTHORIZED,
+            "Incorrect credentials",
+        )
+        .await;
+    }
+
+    // Even the right password is refused without reCAPTCHA now
+    for response in [
+        app.post_disable_2fa(&serde_json::json!({ "password": PASSWORD }))
+            .await,
+        app.post_2fa_method(&serde_json::json!({ "method": null, "password": PASSWORD }))
+            .await,
+    ] {
+        assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+        let login_response = response
+            .json::<LoginResponse>()
+            .await
+            .expect("Could not deserialize response body to LoginResponse");
+        assert_eq!(login_response, LoginResponse::RecaptchaRequired);
+    }
+
+    let response = app
+        .post_disable_2fa(&serde_json::json!({
+            "password": PASSWORD,
+            "recaptchaToken": "test_token"
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_disable_2fa_with_password() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    enable(&app, serde_json::json!({})).await;
+
+    assert_error(
+        app.post_disable_2fa(&serde_json::json!({})).await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+    assert_error(
+        app.post_disable_2fa(&serde_json::json!({ "password": "WrongPassword123!" }))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    // Without an authenticator app there is no code to prove the second factor with
+    assert_error(
+        app.post_disable_2fa(&serde_json::json!({ "code": "123456" }))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    let response = app
+        .post_disable_2fa(&serde_json::json!({ "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(login(&app, &email).await.is_none());
+
+    // Recovery codes go away with 2FA
+    let user_id = app.get_user_id(&email).await;
+    let remaining = app
+        .recovery_code_store
+        .read()
+        .await
+        .count_codes(&user_id)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    assert_error(
+        app.post_disable_2fa(&serde_json::json!({ "password": PASSWORD }))
+            .await,
+        StatusCode::CONFLICT,
+        "2FA not enabled",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_disable_2fa_with_authenticator_code() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    login(&app, &email).await;
+    set_up_totp(&app, &email).await;
+    enable(&app, serde_json::json!({ "method": "totp" })).await;
+
+    // The code confirming the app was already used
+    let code = authenticator_code(&app, &email, 1).await;
+    let response = app
+        .post_disable_2fa(&serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(login(&app, &email).await.is_none());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    let body = serde_json::json!({ "password": PASSWORD });
+    for response in [
+        app.post_enable_2fa(&body).await,
+        app.post_disable_2fa(&body).await,
+        app.post_2fa_method(&body).await,
+    ] {
+        assert_error(response, StatusCode::BAD_REQUEST, "Missing token").await;
+    }
+}
@@ -1,6 +1,7 @@
 use auth_service::{
-    domain::{Email, LoginAttemptId, TwoFACode},
-    routes::Verify2FARequest,
+    domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeHash},
+    routes::{TwoFactorAuthResponse, Verify2FARequest},
+    utils::two_fa_code::hash_code,
     ErrorResponse,
 };
 use reqwest::StatusCode;
@@ -10,12 +11,34 @@ use test_macros::with_db_cleanup;
 
 use crate::helpers::{get_random_email, TestApp};
 
+// Sessions can only be started for registered users
+async fn signup(app: &TestApp, email: &Email) {
+    let signup_body = json!({
+        "email": email.as_ref().expose_secret(),
+        "password": "Password123!",
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+// Hash a code the way the service stores it
+fn code_hash(app: &TestApp, login_attempt_id: &LoginAttemptId, code: &TwoFACode) -> TwoFACodeHash {
+    hash_code(
+        app.settings.auth.two_fa_code_key.expose_secret(),
+        login_attempt_id,
+        code,
+    )
+}
+
 #[with_db_cleanup]
 #[tokio::test]
 async fn should_return_200_if_correct_code() {
     // Make sure to assert the auth cookie gets set
     let mut app = TestApp::new(true).await;
     let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
 
     // Store a code in the 2FA store
     let login_attempt_id = LoginAttemptId::default();
@@ -24,7 +47,11 @@ async fn should_return_200_if_correct_code() {
     {
         let mut store = app.two_fa_code_store.write().await;
         store
-            .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+            .add_code(
+                email.clone(),
+                login_attempt_id.clone(),
+                code_hash(&app, &login_attempt_id, &two_fa_code),
+            )
             .await
             .expect("Failed to add 2FA code");
     }
@@ -34,6 +61,7 @@ async fn should_return_200_if_correct_code() {
         email: email.as_ref().expose_secret().to_string(),
         login_attempt_id: login_attempt_id.as_ref().to_string(),
         two_fa_code: two_fa_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&correct_request).await;
@@ -43,7 +71,7 @@ async fn should_return_200_if_correct_code() {
     let cookies = response.cookies();
     let auth_cookie = cookies
         .into_iter()
-        .find(|c| c.name() == &app.settings.auth.jwt_cookie_name)
+        .find(|c| c.name() == app.settings.auth.jwt_cookie_name)
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
@@ -51,7 +79,7 @@ async fn should_return_200_if_correct_code() {
     // Verify the 2FA code was removed from the store
     {
         let store = app.two_fa_code_store.read().await;
-        let result = store.get_code(&email).await;
+        let result = store.get_code_hash(&email, &login_attempt_id).await;
         assert!(
             result.is_err(),
             "2FA code should have been removed after successful authentication"
@@ -69,6 +97,7 @@ async fn should_return_400_if_invalid_input() {
         email: "invalid-email".to_string(),
         login_attempt_id: "valid-id-123".to_string(),
         two_fa_code: "123456".to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&invalid_request).await;
@@ -98,7 +127,7 @@ async fn should_return_401_if_incorrect_credentials() {
             .add_code(
                 email.clone(),
                 correct_login_attempt_id.clone(),
-                correct_code.clone(),
+                code_hash(&app, &correct_login_attempt_id, &correct_code),
             )
             .await
             .expect("Failed to add 2FA code");
@@ -109,6 +138,7 @@ async fn should_return_401_if_incorrect_credentials() {
         email: email.as_ref().expose_secret().to_string(),
         login_attempt_id: correct_login_attempt_id.as_ref().to_string(),
         two_fa_code: "123456".to_string(), // Valid format but wrong code
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&wrong_code_request).await;
@@ -126,6 +156,7 @@ async fn should_return_401_if_incorrect_credentials() {
         email: email.as_ref().expose_secret().to_string(),
         login_attempt_id: wrong_login_id.as_ref().to_string(),
         two_fa_code: correct_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&wrong_id_request).await;
@@ -142,6 +173,7 @@ async fn should_return_401_if_incorrect_credentials() {
         email: get_random_email(),
         login_attempt_id: correct_login_attempt_id.as_ref().to_string(),
         two_fa_code: correct_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&non_existent_request).await;
@@ -159,6 +191,7 @@ async fn should_return_401_if_incorrect_credentials() {
 async fn should_return_401_if_same_code_twice() {
     let mut app = TestApp::new(true).await;
     let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
 
     // Store a code in the 2FA store
     let login_attempt_id = LoginAttemptId::default();
@@ -167,7 +200,11 @@ async fn should_return_401_if_same_code_twice() {
     {
         let mut store = app.two_fa_code_store.write().await;
         store
-            .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+            .add_code(
+                email.clone(),
+                login_attempt_id.clone(),
+                code_hash(&app, &login_attempt_id, &two_fa_code),
+            )
             .await
             .expect("Failed to add 2FA code");
     }
@@ -177,6 +214,7 @@ async fn should_return_401_if_same_code_twice() {
         email: email.as_ref().expose_secret().to_string(),
         login_attempt_id: login_attempt_id.as_ref().to_string(),
         two_fa_code: two_fa_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&correct_request).await;
@@ -185,7 +223,7 @@ async fn should_return_401_if_same_code_twice() {
     // Verify the code was removed
     {
         let store = app.two_fa_code_store.read().await;
-        let result = store.get_code(&email).await;
+        let result = store.get_code_hash(&email, &login_attempt_id).await;
         assert!(result.is_err(), "2FA code should have been removed");
     }
 
@@ -194,6 +232,7 @@ async fn should_return_401_if_same_code_twice() {
         email: email.as_ref().expose_secret().to_string(),
         login_attempt_id: login_attempt_id.as_ref().to_string(),
         two_fa_code: two_fa_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&same_request).await;
@@ -209,12 +248,13 @@ async fn should_return_401_if_same_code_twice() {
 #[with_db_cleanup]
 #[tokio::test]
 async fn should_return_401_if_old_code() {
-    // Call login twice. Then, attempt to call verify-fa with the 2FA code from the first login request. This should fail.
+    // Resend the code of a login attempt. Then, attempt to call verify-2fa with the code sent first. This should fail.
     let mut app = TestApp::new(true).await;
     let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
 
     // Add first 2FA code
-    let first_login_attempt_id = LoginAttemptId::default();
+    let login_attempt_id = LoginAttemptId::default();
     let first_code = TwoFACode::default();
 
     {
@@ -222,37 +262,37 @@ async fn should_return_401_if_old_code() {
         store
             .add_code(
                 email.clone(),
-                first_login_attempt_id.clone(),
-                first_code.clone(),
+                login_attempt_id.clone(),
+                code_hash(&app, &login_attempt_id, &first_code),
             )
             .await
             .expect("Failed to add first 2FA code");
     }
 
-    // Simulate second login - this should overwrite the first code
-    let second_login_attempt_id = LoginAttemptId::default();
-    let second_code = TwoFACode::default();
+    // Simulate a resend - this should replace the first code
+    let second_code = match first_code.as_ref() {
+        "123456" => TwoFACode::parse("654321".to_string()).unwrap(),
+        _ => TwoFACode::parse("123456".to_string()).unwrap(),
+    };
 
     {
         let mut store = app.two_fa_code_store.write().await;
-        // Remove old code first (simulating what login would do)
-        let _ = store.remove_code(&email).await;
-        // Add new code
         store
-            .add_code(
-                email.clone(),
-                second_login_attempt_id.clone(),
-                second_code.clone(),
+            .replace_code(
+                &email,
+                &login_attempt_id,
+                code_hash(&app, &login_attempt_id, &second_code),
             )
             .await
-            .expect("Failed to add second 2FA code");
+            .expect("Failed to replace 2FA code");
     }
 
     // Try to use the first (old) 2FA code - this should fail
     let old_code_request = Verify2FARequest {
         email: email.as_ref().expose_secret().to_string(),
-        login_attempt_id: first_login_attempt_id.as_ref().to_string(),
+        login_attempt_id: login_attempt_id.as_ref().to_string(),
         two_fa_code: first_code.as_ref().to_string(),
+        remember_device: false,
     };
 
     let response = app.post_verify_2fa(&old_code_request).await;
@@ -309,3 +349,162 @@ async fn should_return_422_if_malformed_input() {
     let response = app.post_verify_2fa(&wrong_types_request).await;
     assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
 }
+
+async fn login(app: &TestApp, email: &Email, recaptcha_token: Option<&str>) -> reqwest::Response {
+    let login_body = json!({
+        "email": email.as_ref().expose_secret(),
+        "password": "Password123!",
+        "recaptchaToken": recaptcha_token
+    });
+    app.post_login(&login_body).await
+}
+
+// Start a login attempt, returning it with the code it sent
+async fn start_login_attempt(app: &TestApp, email: &Email) -> (LoginAttemptId, TwoFACode) {
+    let response = login(app, email, Some("valid_test_token")).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let login_attempt_id = LoginAttemptId::parse(login_attempt_id).unwrap();
+
+    let code = app
+        .get_two_fa_code(email.as_ref().expose_secret())
+        .expect("No 2FA code emailed");
+    (login_attempt_id, TwoFACode::parse(code).unwrap())
+}
+
+async fn verify(
+    app: &TestApp,
+    email: &Email,
+    login_attempt_id: &LoginAttemptId,
+    code: &str,
+) -> StatusCode {
+    let request = Verify2FARequest {
+        email: email.as_ref().expose_secret().to_string(),
+        login_attempt_id: login_attempt_id.as_ref().to_string(),
+        two_fa_code: code.to_string(),
+        remember_device: false,
+    };
+    app.post_verify_2fa(&request).await.status()
+}
+
+// A code other than the one sent
+fn wrong_code(code: &TwoFACode) -> &'static str {
+    match code.as_ref() {
+        "123456" => "654321",
+        _ => "123456",
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_cancel_login_attempt_after_too_many_failed_codes() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    let (login_attempt_id, code) = start_login_attempt(&app, &email).await;
+    for _ in 0..app.settings.auth.max_2fa_attempts {
+        let status = verify(&app, &email, &login_attempt_id, wrong_code(&code)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // The login attempt is cancelled, so even the right code no longer works
+    let status = verify(&app, &email, &login_attempt_id, code.as_ref()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The failures count towards requiring reCAPTCHA on login
+    let status = login(&app, &email, None).await.status();
+    assert_eq!(status, StatusCode::PRECONDITION_REQUIRED);
+
+    // Failures of the email carry over, so the next login attempt is cancelled after a
+    // single wrong code
+    let (login_attempt_id, code) = start_login_attempt(&app, &email).await;
+    let status = verify(&app, &email, &login_attempt_id, wrong_code(&code)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = verify(&app, &email, &login_attempt_id, code.as_ref()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The right code on the first try still completes a login, which clears the failures
+    let (login_attempt_id, code) = start_login_attempt(&app, &email).await;
+    let status = verify(&app, &email, &login_attempt_id, code.as_ref()).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = login(&app, &email, None).await.status();
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_cancel_login_attempt_after_failed_password_logins() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    // Someone else guessing the password of the email
+    for _ in 0..app.settings.auth.max_2fa_attempts {
+        let login_body = json!({
+            "email": email.as_ref().expose_secret(),
+            "password": "WrongPassword123!",
+            "recaptchaToken": "valid_test_token"
+        });
+        let status = app.post_login(&login_body).await.status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    // A mistyped code of the owner leaves their login attempt pending
+    let (login_attempt_id, code) = start_login_attempt(&app, &email).await;
+    let status = verify(&app, &email, &login_attempt_id, wrong_code(&code)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let status = verify(&app, &email, &login_attempt_id, code.as_ref()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_complete_concurrent_login_attempts_independently() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    // A login from a second tab leaves the first one pending
+    let (first_login_attempt_id, first_code) = start_login_attempt(&app, &email).await;
+    let (second_login_attempt_id, second_code) = start_login_attempt(&app, &email).await;
+    assert_ne!(first_login_attempt_id, second_login_attempt_id);
+
+    // Codes only work for their own login attempt
+    if first_code != second_code {
+        let status = verify(&app, &email, &first_login_attempt_id, second_code.as_ref()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    let status = verify(&app, &email, &second_login_attempt_id, second_code.as_ref()).await;
+    assert_eq!(status, StatusCode::OK);
+    let status = verify(&app, &email, &first_login_attempt_id, first_code.as_ref()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_only_store_hash_of_code() {
+    let mut app = TestApp::new(true).await;
+    let email = Email::parse(Secret::new(get_random_email())).unwrap();
+    signup(&app, &email).await;
+
+    let (login_attempt_id, code) = start_login_attempt(&app, &email).await;
+
+    // Reading Redis does not give away the code
+    let redis_key =
+        app.get_two_fa_code_redis_key(email.as_ref().expose_secret(), login_attempt_id.as_ref());
+    let stored = app
+        .get_redis_value(&redis_key)
+        .await
+        .expect("2FA code should be stored for this login attempt");
+    assert_ne!(stored, code.as_ref());
+    assert_eq!(stored, code_hash(&app, &login_attempt_id, &code).as_ref());
+
+    let status = verify(&app, &email, &login_attempt_id, code.as_ref()).await;
+    assert_eq!(status, StatusCode::OK);
+}
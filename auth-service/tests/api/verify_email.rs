@@ -0,0 +1,222 @@
+use auth_service::{routes::VerifyEmailRequest, ErrorResponse};
+use reqwest::StatusCode;
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+const TOKEN_PARAM: &str = "verify_email_token";
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    email
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    app.post_login(&login_body).await
+}
+
+async fn verify_email(app: &TestApp, token: &str) -> reqwest::Response {
+    let request = VerifyEmailRequest {
+        token: token.to_owned(),
+    };
+    app.post_verify_email(&request).await
+}
+
+async fn resend(app: &TestApp, email: &str) -> reqwest::Response {
+    let body = serde_json::json!({
+        "email": email,
+        "recaptchaToken": "test_token"
+    });
+    app.post_resend_verification_email(&body).await
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, error: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, error);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_send_verification_email_on_signup() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+
+    let emails = app.get_sent_emails(&email);
+    assert_eq!(emails.len(), 1);
+    assert_eq!(
+        emails[0].subject,
+        format!(
+            "Verify your email address for {}",
+            app.settings.email.product_name
+        )
+    );
+    assert!(emails[0].html_body.contains(TOKEN_PARAM));
+    assert!(app.get_link_token(&email, TOKEN_PARAM).is_some());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_block_login_until_email_verified() {
+    let mut app = TestApp::with_settings(true, |settings| {
+        settings.auth.require_email_verification = true
+    })
+    .await;
+
+    let email = signup(&app).await;
+    assert_error(
+        login(&app, &email).await,
+        StatusCode::FORBIDDEN,
+        "Email not verified",
+    )
+    .await;
+
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    let response = verify_email(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_reveal_verification_state_for_wrong_password() {
+    let mut app = TestApp::with_settings(true, |settings| {
+        settings.auth.require_email_verification = true
+    })
+    .await;
+
+    let email = signup(&app).await;
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "WrongPassword123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_allow_unverified_login_when_not_required() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    let response = login(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_reused_token() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+
+    let response = verify_email(&app, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_error(
+        verify_email(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_expired_token() {
+    let mut app = TestApp::with_settings(true, |settings| {
+        settings.auth.email_verification_ttl_seconds = 1
+    })
+    .await;
+
+    let email = signup(&app).await;
+    let token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    assert_error(
+        verify_email(&app, &token).await,
+        StatusCode::UNAUTHORIZED,
+        "Invalid token",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_unknown_or_malformed_token() {
+    let mut app = TestApp::new(true).await;
+
+    for token in [uuid::Uuid::new_v4().to_string(), "not-a-token".to_owned()] {
+        assert_error(
+            verify_email(&app, &token).await,
+            StatusCode::UNAUTHORIZED,
+            "Invalid token",
+        )
+        .await;
+    }
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_resend_verification_email() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup(&app).await;
+    let first_token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+
+    let response = resend(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.get_sent_emails(&email).len(), 2);
+
+    let second_token = app.get_link_token(&email, TOKEN_PARAM).unwrap();
+    assert_ne!(first_token, second_token);
+
+    let response = verify_email(&app, &second_token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Verified accounts are not sent another email
+    let response = resend(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.get_sent_emails(&email).len(), 2);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_not_reveal_unknown_accounts_on_resend() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let response = resend(&app, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(app.get_sent_emails(&email).is_empty());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_recaptcha_fails_on_resend() {
+    let mut app = TestApp::new(false).await;
+
+    let response = resend(&app, &get_random_email()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
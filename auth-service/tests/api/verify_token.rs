@@ -2,7 +2,7 @@ use auth_service::ErrorResponse;
 use reqwest::{cookie::CookieStore, StatusCode};
 use test_macros::with_db_cleanup;
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, get_token_claims, TestApp};
 
 #[with_db_cleanup]
 #[tokio::test]
@@ -115,10 +115,11 @@ async fn should_return_401_if_banned_token() {
         .trim();
 
     // Ban the token by adding it to the banned token store
+    let claims = get_token_claims(jwt_token);
     app.banned_token_store
         .write()
         .await
-        .store_token(jwt_token.to_string())
+        .store_token(claims.jti.unwrap(), claims.exp as u64)
         .await
         .expect("Failed to ban token");
 
@@ -144,7 +145,7 @@ async fn should_return_422_if_malformed_input() {
 
     let client = reqwest::Client::new();
     let response = client
-        .post(&format!("{}/verify-token", &app.address))
+        .post(format!("{}/verify-token", &app.address))
         .header("Content-Type", "application/json")
         .body(malformed_body)
         .send()
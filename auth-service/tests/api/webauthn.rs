@@ -0,0 +1,557 @@
+use auth_service::{
+    domain::TwoFAMethod,
+    routes::{PasskeyCreationOptions, PasskeyRequestOptions, TwoFactorAuthResponse},
+    utils::cbor::Value,
+    ErrorResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::StatusCode;
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use sha2::{Digest, Sha256};
+use test_macros::with_db_cleanup;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "Password123!";
+
+// Authenticator holding a single ES256 credential, which answers WebAuthn options the
+// way a browser would serialize the response of a hardware authenticator
+struct SoftwareAuthenticator {
+    key_pair: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    user_handle: Option<Vec<u8>>,
+    sign_count: u32,
+    origin: String,
+    user_verification: bool,
+}
+
+impl SoftwareAuthenticator {
+    fn new(app: &TestApp) -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        Self {
+            key_pair,
+            credential_id: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            user_handle: None,
+            sign_count: 0,
+            origin: app.settings.auth.webauthn_origin.clone(),
+            user_verification: true,
+        }
+    }
+
+    fn flags(&self) -> u8 {
+        // User present, and verified if the authenticator can
+        match self.user_verification {
+            true => 0x05,
+            false => 0x01,
+        }
+    }
+
+    fn client_data(&self, ceremony_type: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({
+            "type": ceremony_type,
+            "challenge": challenge,
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn create(&mut self, options: &PasskeyCreationOptions) -> serde_json::Value {
+        let point = self.key_pair.public_key().as_ref();
+        let public_key = Value::Map(vec![
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(-7)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(point[1..33].to_vec())),
+            (Value::Integer(-3), Value::Bytes(point[33..].to_vec())),
+        ]);
+        let authenticator_data = [
+            Sha256::digest(options.rp.id.as_bytes()).as_slice(),
+            &[self.flags() | 0x40],
+            &self.sign_count.to_be_bytes(),
+            &[0u8; 16],
+            &(self.credential_id.len() as u16).to_be_bytes(),
+            &self.credential_id,
+            &public_key.encode(),
+        ]
+        .concat();
+        let attestation_object = Value::Map(vec![
+            (
+                Value::Text("fmt".to_owned()),
+                Value::Text("none".to_owned()),
+            ),
+            (Value::Text("attStmt".to_owned()), Value::Map(vec![])),
+            (
+                Value::Text("authData".to_owned()),
+                Value::Bytes(authenticator_data),
+            ),
+        ]);
+        self.user_handle = Some(URL_SAFE_NO_PAD.decode(&options.user.id).unwrap());
+
+        serde_json::json!({
+            "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(self.client_data("webauthn.create", &options.challenge)),
+                "attestationObject": URL_SAFE_NO_PAD.encode(attestation_object.encode()),
+                "transports": ["usb", "carrier-pigeon"],
+            },
+        })
+    }
+
+    fn get(&mut self, options: &PasskeyRequestOptions) -> serde_json::Value {
+        self.sign_count += 1;
+        let authenticator_data = [
+            Sha256::digest(options.rp_id.as_bytes()).as_slice(),
+            &[self.flags()],
+            &self.sign_count.to_be_bytes(),
+        ]
+        .concat();
+        let client_data = self.client_data("webauthn.get", &options.challenge);
+        let signed_data = [authenticator_data.as_slice(), &Sha256::digest(&client_data)].concat();
+        let signature = self
+            .key_pair
+            .sign(&SystemRandom::new(), &signed_data)
+            .unwrap();
+
+        serde_json::json!({
+            "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                "authenticatorData": URL_SAFE_NO_PAD.encode(authenticator_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+                "userHandle": self.user_handle.as_ref().map(|handle| URL_SAFE_NO_PAD.encode(handle)),
+            },
+        })
+    }
+}
+
+async fn signup_and_login(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": false,
+        "recaptchaToken": "test_token"
+    });
+    assert_eq!(
+        app.post_signup(&signup_body).await.status(),
+        StatusCode::CREATED
+    );
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    assert_eq!(app.post_login(&login_body).await.status(), StatusCode::OK);
+    email
+}
+
+async fn register_options(app: &TestApp) -> PasskeyCreationOptions {
+    let response = app.post_webauthn_register_options().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<PasskeyCreationOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyCreationOptions")
+}
+
+async fn register(app: &TestApp, authenticator: &mut SoftwareAuthenticator) {
+    let options = register_options(app).await;
+    let response = app
+        .post_webauthn_register_verify(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+async fn authenticate_options(app: &TestApp) -> PasskeyRequestOptions {
+    let response = app.post_webauthn_authenticate_options().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    response
+        .json::<PasskeyRequestOptions>()
+        .await
+        .expect("Could not deserialize response body to PasskeyRequestOptions")
+}
+
+async fn assert_error(response: reqwest::Response, status: StatusCode, message: &str) {
+    assert_eq!(response.status(), status);
+    let error_response = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(error_response.error, message);
+}
+
+fn jwt(app: &TestApp) -> Option<String> {
+    app.get_cookie_value(&app.settings.auth.jwt_cookie_name)
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_register_passkey_and_log_in_without_password() {
+    let mut app = TestApp::new(true).await;
+
+    let email = signup_and_login(&app).await;
+    let user_id = app.get_user_id(&email).await;
+
+    let options = register_options(&app).await;
+    assert_eq!(options.rp.id, app.settings.auth.webauthn_rp_id);
+    assert_eq!(options.user.name, email);
+    assert_eq!(
+        URL_SAFE_NO_PAD.decode(&options.user.id).unwrap(),
+        user_id.as_ref().as_bytes()
+    );
+    assert!(options.exclude_credentials.is_empty());
+
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+    let response = app
+        .post_webauthn_register_verify(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let credentials = app
+        .passkey_store
+        .read()
+        .await
+        .get_credentials(&user_id)
+        .await
+        .unwrap();
+    assert_eq!(credentials.len(), 1);
+    assert_eq!(credentials[0].id.as_ref(), authenticator.credential_id);
+    // Unknown transports are dropped
+    assert_eq!(credentials[0].transports, vec!["usb".to_owned()]);
+
+    // The registered passkey is excluded from further registrations
+    let options = register_options(&app).await;
+    assert_eq!(options.exclude_credentials.len(), 1);
+
+    app.post_logout().await;
+    assert!(jwt(&app).is_none());
+
+    let options = authenticate_options(&app).await;
+    assert!(options.allow_credentials.is_empty());
+    assert_eq!(options.user_verification, "required");
+    let response = app
+        .post_webauthn_authenticate_verify(&authenticator.get(&options))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(jwt(&app).is_some());
+
+    let credential = app
+        .passkey_store
+        .read()
+        .await
+        .get_credential(&credentials[0].id)
+        .await
+        .unwrap();
+    assert_eq!(credential.0, user_id);
+    assert_eq!(credential.1.sign_count, 1);
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_use_passkey_as_second_factor() {
+    let mut app = TestApp::new(true).await;
+
+    let email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD,
+        "requires2FA": true,
+        "recaptchaToken": "test_token"
+    });
+    app.post_signup(&signup_body).await;
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": PASSWORD
+    });
+    let login_attempt_id = app
+        .post_login(&login_body)
+        .await
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let code = app.get_two_fa_code(&email).expect("No 2FA code emailed");
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    assert_eq!(
+        app.post_verify_2fa(&verify_body).await.status(),
+        StatusCode::OK
+    );
+
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+    register(&app, &mut authenticator).await;
+    app.post_logout().await;
+
+    let emails_sent = app.get_sent_emails(&email).len();
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let challenge = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    assert_eq!(challenge.method, TwoFAMethod::Passkey);
+    assert_eq!(app.get_sent_emails(&email).len(), emails_sent);
+    let options = challenge.passkey_options.expect("Passkey options missing");
+    assert_eq!(options.allow_credentials.len(), 1);
+    assert_eq!(options.user_verification, "preferred");
+
+    // Codes are never accepted for passkey logins
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": challenge.login_attempt_id,
+        "2FACode": "123456",
+    });
+    assert_error(
+        app.post_verify_2fa(&verify_body).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    // A second factor does not require user verification
+    authenticator.user_verification = false;
+    let response = app
+        .post_webauthn_authenticate_verify(&authenticator.get(&options))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(jwt(&app).is_some());
+
+    // The login attempt is over
+    assert!(
+        !app.redis_key_exists(&app.get_two_fa_code_redis_key(&email, &challenge.login_attempt_id))
+            .await
+    );
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_reject_replayed_and_cloned_assertions() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+    register(&app, &mut authenticator).await;
+    app.post_logout().await;
+
+    let options = authenticate_options(&app).await;
+    let assertion = authenticator.get(&options);
+    let response = app.post_webauthn_authenticate_verify(&assertion).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    app.post_logout().await;
+
+    // Every challenge is answered once
+    assert_error(
+        app.post_webauthn_authenticate_verify(&assertion).await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    // A copy of the authenticator reports a signature counter that was already used
+    authenticator.sign_count -= 1;
+    let options = authenticate_options(&app).await;
+    assert_error(
+        app.post_webauthn_authenticate_verify(&authenticator.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    assert!(jwt(&app).is_none());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_assertion_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+    register(&app, &mut authenticator).await;
+    app.post_logout().await;
+
+    // Collected by a phishing site
+    authenticator.origin = "https://phishing.example".to_owned();
+    let options = authenticate_options(&app).await;
+    assert_error(
+        app.post_webauthn_authenticate_verify(&authenticator.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    authenticator.origin = app.settings.auth.webauthn_origin.clone();
+
+    // Passwordless logins require user verification
+    authenticator.user_verification = false;
+    let options = authenticate_options(&app).await;
+    assert_error(
+        app.post_webauthn_authenticate_verify(&authenticator.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    authenticator.user_verification = true;
+
+    // Signed by another key
+    let mut impostor = SoftwareAuthenticator::new(&app);
+    impostor.credential_id = authenticator.credential_id.clone();
+    let options = authenticate_options(&app).await;
+    assert_error(
+        app.post_webauthn_authenticate_verify(&impostor.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    // Unknown credential, and a challenge that was never issued
+    let mut unknown = SoftwareAuthenticator::new(&app);
+    let options = authenticate_options(&app).await;
+    assert_error(
+        app.post_webauthn_authenticate_verify(&unknown.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+    let mut options = authenticate_options(&app).await;
+    options.challenge = URL_SAFE_NO_PAD.encode([0u8; 32]);
+    assert_error(
+        app.post_webauthn_authenticate_verify(&authenticator.get(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    assert!(jwt(&app).is_none());
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_409_if_passkey_already_registered() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+    register(&app, &mut authenticator).await;
+
+    let options = register_options(&app).await;
+    assert_error(
+        app.post_webauthn_register_verify(&authenticator.create(&options))
+            .await,
+        StatusCode::CONFLICT,
+        "Passkey already registered",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_401_if_registration_answers_another_challenge() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+    let mut authenticator = SoftwareAuthenticator::new(&app);
+
+    // A challenge issued for logging in cannot register a passkey
+    let mut options = register_options(&app).await;
+    options.challenge = authenticate_options(&app).await.challenge;
+    assert_error(
+        app.post_webauthn_register_verify(&authenticator.create(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+
+    // Nor can a registration be completed for another relying party
+    let mut options = register_options(&app).await;
+    options.rp.id = "phishing.example".to_owned();
+    assert_error(
+        app.post_webauthn_register_verify(&authenticator.create(&options))
+            .await,
+        StatusCode::UNAUTHORIZED,
+        "Incorrect credentials",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_input_is_invalid() {
+    let mut app = TestApp::new(true).await;
+
+    signup_and_login(&app).await;
+    let response = serde_json::json!({
+        "id": "not base64url!",
+        "response": {
+            "clientDataJSON": "",
+            "attestationObject": "",
+        },
+    });
+    assert_error(
+        app.post_webauthn_register_verify(&response).await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+
+    let response = serde_json::json!({
+        "id": "",
+        "response": {
+            "clientDataJSON": "",
+            "authenticatorData": "",
+            "signature": "",
+        },
+    });
+    assert_error(
+        app.post_webauthn_authenticate_verify(&response).await,
+        StatusCode::BAD_REQUEST,
+        "Invalid input",
+    )
+    .await;
+}
+
+#[with_db_cleanup]
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new(true).await;
+
+    assert_error(
+        app.post_webauthn_register_options().await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+    let response = serde_json::json!({
+        "id": "",
+        "response": {
+            "clientDataJSON": "",
+            "attestationObject": "",
+        },
+    });
+    assert_error(
+        app.post_webauthn_register_verify(&response).await,
+        StatusCode::BAD_REQUEST,
+        "Missing token",
+    )
+    .await;
+}